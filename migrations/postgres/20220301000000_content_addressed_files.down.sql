@@ -0,0 +1,4 @@
+drop index sounds_file_hash_idx;
+
+alter table sounds
+    drop column file_hash;
@@ -0,0 +1,6 @@
+-- Sound files are stored under the hash of their contents.
+-- Null until the sound's file has been stored.
+alter table sounds
+    add column file_hash text default null;
+
+create index sounds_file_hash_idx on sounds (file_hash);
@@ -0,0 +1,7 @@
+drop index sounds_guild_id_lower_name_key;
+
+alter table sounds
+    add constraint sounds_guild_id_name_deleted_at_key
+        unique (guild_id, name, deleted_at);
+
+alter table sounds drop column legacy_name;
@@ -0,0 +1,30 @@
+-- Files that aren't stored by hash yet are named after their sound, so remember what they're
+-- called until they've been moved into the store.
+alter table sounds add column legacy_name text default null;
+update sounds set legacy_name = name where file_hash is null;
+
+-- Make existing names follow the rules enforced by `SoundName`.
+update sounds
+set name = left(trim(translate(name, '/\', '__')), 64)
+where name <> left(trim(translate(name, '/\', '__')), 64);
+
+update sounds
+set name = 'sound ' || id
+where name = '' or name ~ '^\.+$';
+
+-- Names are unique per guild regardless of case; keep the oldest of any duplicates as is.
+update sounds
+set name = left(name, 64 - length(id::text) - 3) || ' (' || id || ')'
+where deleted_at is null
+  and id not in (select min(id)
+                 from sounds
+                 where deleted_at is null
+                 group by guild_id, lower(name));
+
+-- Replaces `sounds_guild_id_name_deleted_at_key`, which never applied to live sounds since
+-- `deleted_at` is null for them.
+alter table sounds
+    drop constraint sounds_guild_id_name_deleted_at_key;
+
+create unique index sounds_guild_id_lower_name_key on sounds (guild_id, lower(name))
+    where deleted_at is null;
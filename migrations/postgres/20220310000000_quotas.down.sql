@@ -0,0 +1,8 @@
+alter table sounds
+    drop column file_size;
+
+alter table guilds
+    drop column max_sounds,
+    drop column max_total_size,
+    drop column max_size,
+    drop column max_length;
@@ -0,0 +1,11 @@
+-- Per-guild overrides of the default limits. Null uses the default; 0 means no limit.
+alter table guilds
+    add column max_length     int    default null,
+    add column max_size       bigint default null,
+    add column max_total_size bigint default null,
+    add column max_sounds     int    default null;
+
+-- Size of the sound's file in bytes, for storage quotas.
+-- Null until the sound's file has been stored.
+alter table sounds
+    add column file_size bigint default null;
@@ -0,0 +1,3 @@
+-- Why a playback was stopped when nobody stopped it, e.g. `shutdown`.
+alter table playbacks
+    add column stop_reason text default null;
@@ -0,0 +1 @@
+drop table sound_tags;
@@ -0,0 +1,12 @@
+-- Words guild members label sounds with, to find them by. Tags are stored in lower case.
+create table sound_tags
+(
+    sound_id int  not null,
+    tag      text not null,
+
+    primary key (sound_id, tag),
+    constraint sound_tags_sound_id_fkey
+        foreign key (sound_id) references sounds (id) on delete cascade
+);
+
+create index on sound_tags (tag);
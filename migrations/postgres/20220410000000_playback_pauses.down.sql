@@ -0,0 +1,3 @@
+alter table playbacks
+    drop column paused_at,
+    drop column paused_ms;
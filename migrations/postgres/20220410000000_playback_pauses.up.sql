@@ -0,0 +1,5 @@
+-- How long playbacks were paused, so their durations only count the time they played.
+-- `paused_at` is set while a playback is paused.
+alter table playbacks
+    add column paused_at timestamp with time zone default null,
+    add column paused_ms bigint not null default 0;
@@ -0,0 +1,3 @@
+drop table playbacks;
+drop table sounds;
+drop table guilds;
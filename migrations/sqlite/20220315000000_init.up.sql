@@ -0,0 +1,81 @@
+-- The same schema as the Postgres migrations produce, as of the quotas migration.
+-- Timestamps are stored as RFC 3339 text with millisecond precision.
+
+create table guilds
+(
+    id             integer primary key,
+    created_at     text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at     text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    deleted_at     text          default null,
+
+    max_length     integer       default null,
+    max_size       integer       default null,
+    max_total_size integer       default null,
+    max_sounds     integer       default null
+);
+
+create index guilds_deleted_at_idx on guilds (deleted_at asc);
+
+create trigger set_guilds_updated_at
+    after update
+    on guilds
+    for each row
+begin
+    update guilds set updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') where id = new.id;
+end;
+
+
+create table sounds
+(
+    id          integer primary key autoincrement,
+    created_at  text    not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at  text    not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    deleted_at  text             default null,
+
+    guild_id    integer not null references guilds (id) on delete cascade,
+    name        text    not null,
+    source      text    not null,
+    uploader_id integer not null,
+    length      integer not null,
+    file_hash   text             default null,
+    file_size   integer          default null
+);
+
+create index sounds_deleted_at_idx on sounds (deleted_at asc);
+create index sounds_file_hash_idx on sounds (file_hash);
+
+-- SQLite's `lower` only folds ASCII, unlike Postgres'.
+create unique index sounds_guild_id_lower_name_key on sounds (guild_id, lower(name))
+    where deleted_at is null;
+
+create trigger set_sounds_updated_at
+    after update
+    on sounds
+    for each row
+begin
+    update sounds set updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') where id = new.id;
+end;
+
+
+create table playbacks
+(
+    id         integer primary key autoincrement,
+    created_at text    not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at text    not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    deleted_at text             default null,
+
+    stopped_at text             default null,
+    sound_id   integer not null references sounds (id) on delete cascade,
+    player_id  integer not null,
+    stopper_id integer          default null
+);
+
+create index playbacks_deleted_at_idx on playbacks (deleted_at asc);
+
+create trigger set_playbacks_updated_at
+    after update
+    on playbacks
+    for each row
+begin
+    update playbacks set updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') where id = new.id;
+end;
@@ -0,0 +1,4 @@
+-- What yt-dlp said about the sound's source when it was added, as JSON: title, uploader and so on.
+-- Null for sounds that weren't downloaded with yt-dlp.
+alter table sounds
+    add column sound_metadata text default null;
@@ -0,0 +1,2 @@
+alter table playbacks
+    drop column stop_reason;
@@ -0,0 +1,10 @@
+-- Words guild members label sounds with, to find them by. Tags are stored in lower case.
+create table sound_tags
+(
+    sound_id integer not null references sounds (id) on delete cascade,
+    tag      text    not null,
+
+    primary key (sound_id, tag)
+);
+
+create index sound_tags_tag_idx on sound_tags (tag);
@@ -0,0 +1,8 @@
+-- Tokens people use to play sounds through the HTTP API, one per user. Only their SHA-256 hashes
+-- are kept, so a leaked database doesn't leak working tokens.
+create table api_tokens
+(
+    user_id    integer not null primary key,
+    token_hash text    not null unique,
+    created_at text    not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
@@ -0,0 +1,4 @@
+alter table playbacks
+    drop column paused_at;
+alter table playbacks
+    drop column paused_ms;
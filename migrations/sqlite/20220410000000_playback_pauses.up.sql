@@ -0,0 +1,6 @@
+-- How long playbacks were paused, so their durations only count the time they played.
+-- `paused_at` is set while a playback is paused.
+alter table playbacks
+    add column paused_at text default null;
+alter table playbacks
+    add column paused_ms integer not null default 0;
@@ -0,0 +1,6 @@
+drop index sounds_guild_id_name_key_key;
+create unique index sounds_guild_id_lower_name_key on sounds (guild_id, lower(name))
+    where deleted_at is null;
+
+alter table sounds
+    drop column name_key;
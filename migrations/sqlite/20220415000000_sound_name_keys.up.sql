@@ -0,0 +1,9 @@
+-- The form of each sound's name that uniqueness is checked against, from `SoundName::key`.
+-- SQLite's `lower` only folds ASCII, so keys are worked out by Bernie instead. Keys of existing
+-- sounds are filled in by Bernie once the database is migrated.
+alter table sounds
+    add column name_key text default null;
+
+drop index sounds_guild_id_lower_name_key;
+create unique index sounds_guild_id_name_key_key on sounds (guild_id, name_key)
+    where deleted_at is null;
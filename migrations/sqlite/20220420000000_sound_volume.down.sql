@@ -0,0 +1,2 @@
+alter table sounds
+    drop column volume;
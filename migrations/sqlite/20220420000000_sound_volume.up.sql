@@ -0,0 +1,3 @@
+-- How loud each sound plays, in percent of its file's own volume.
+alter table sounds
+    add column volume int not null default 100;
@@ -0,0 +1,665 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use tokio::task::block_in_place;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::db::{NewSound, Repository, DEFAULT_VOLUME};
+use crate::media::Prober;
+use crate::quota::Limits;
+use crate::services::sounds;
+use crate::sound_name::{name_key, SoundName};
+use crate::store::{hash_file, SoundStore};
+use crate::Error;
+
+/// Name of the manifest file at the root of every archive.
+pub const MANIFEST_NAME: &str = "manifest.json";
+/// Version of the manifest format written by this build.
+pub const MANIFEST_VERSION: u32 = 1;
+/// Directory under the storage directory where exports too large to upload are kept.
+pub const EXPORTS_DIR: &str = "exports";
+/// Directory under the storage directory where archives are unpacked while being imported.
+pub const IMPORTS_DIR: &str = "imports";
+/// Largest archive that can be imported, in bytes.
+pub const MAX_ARCHIVE_SIZE: u64 = 512 * 1024 * 1024;
+/// Most bytes an archive can unpack to, so a small archive can't fill the disk.
+pub const MAX_EXTRACTED_SIZE: u64 = 1024 * 1024 * 1024;
+/// Largest manifest that's read from an archive, in bytes.
+const MAX_MANIFEST_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Describes the contents of a sound library archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub guild_id: u64,
+    pub exported_at: DateTime<Utc>,
+    pub sounds: Vec<ManifestSound>,
+}
+
+/// A single sound in a [`Manifest`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestSound {
+    pub name: String,
+    /// Path of the sound's file inside the archive.
+    pub file: String,
+    pub source: String,
+    pub uploader_id: u64,
+    /// Length of the sound in milliseconds.
+    pub length: i32,
+    pub created_at: DateTime<Utc>,
+    /// Archives from before tags were exported have none.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// How loud the sound plays, in percent of its file's own volume. Archives from before volumes
+    /// were exported play sounds as they are.
+    #[serde(default = "default_volume")]
+    pub volume: i32,
+}
+
+fn default_volume() -> i32 {
+    DEFAULT_VOLUME
+}
+
+/// Build a file name for an export of `guild_id`, unique to the second.
+pub fn export_file_name(guild_id: GuildId) -> String {
+    format!("{}-{}.zip", guild_id.0, Utc::now().format("%Y%m%d%H%M%S"))
+}
+
+/// Directory that exports too large to upload to Discord are written to.
+pub fn exports_dir(storage_dir: &Path) -> PathBuf {
+    storage_dir.join(EXPORTS_DIR)
+}
+
+/// Write every live sound of `guild_id` to a zip archive at `dest`.
+///
+/// Sounds whose files are missing from the store are left out of the archive.
+pub async fn export_guild(
+    db: &dyn Repository,
+    store: &dyn SoundStore,
+    guild_id: GuildId,
+    dest: &Path,
+) -> Result<Manifest, Error> {
+    let sounds = db.guild_sounds(guild_id).await?;
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for (sound_id, tag) in db.guild_tags(guild_id).await? {
+        tags.entry(sound_id).or_default().push(tag);
+    }
+
+    let mut manifest = Manifest {
+        version: MANIFEST_VERSION,
+        guild_id: guild_id.0,
+        exported_at: Utc::now(),
+        sounds: vec![],
+    };
+    let mut files = BTreeSet::new();
+    for sound in sounds {
+        let file_hash = match sound.file_hash {
+            Some(file_hash) if store.exists(&file_hash).await? => file_hash,
+            _ => {
+                tracing::warn!(
+                    "Sound {:?} in guild {} has no file; leaving it out of the export.",
+                    sound.name,
+                    guild_id.0
+                );
+                continue;
+            }
+        };
+
+        // sounds with the same file share it in the archive too.
+        manifest.sounds.push(ManifestSound {
+            name: sound.name,
+            file: format!("sounds/{file_hash}"),
+            source: sound.source,
+            uploader_id: sound.uploader_id as u64,
+            length: sound.length,
+            created_at: sound.created_at,
+            tags: tags.remove(&sound.id).unwrap_or_default(),
+            volume: sound.volume,
+        });
+        files.insert(file_hash);
+    }
+
+    // the zip crate is synchronous, so let the runtime know when we're blocking.
+    let mut zip = block_in_place(|| -> Result<_, Error> {
+        let mut zip = ZipWriter::new(File::create(dest)?);
+        zip.start_file(MANIFEST_NAME, FileOptions::default())?;
+        serde_json::to_writer_pretty(&mut zip, &manifest)?;
+        Ok(zip)
+    })?;
+
+    // sound files are already compressed; don't bother compressing them again.
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for file_hash in files {
+        let data = store.get(&file_hash).await?;
+        block_in_place(|| -> Result<(), Error> {
+            zip.start_file(format!("sounds/{file_hash}"), options)?;
+            zip.write_all(&data)?;
+            Ok(())
+        })?;
+    }
+
+    block_in_place(|| zip.finish())?;
+    Ok(manifest)
+}
+
+/// What to do with an imported sound whose name is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::SlashChoiceParameter, clap::ArgEnum)]
+pub enum OnConflict {
+    #[name = "Skip the imported sound"]
+    Skip,
+    #[name = "Rename the imported sound"]
+    Rename,
+    #[name = "Replace the existing sound"]
+    Overwrite,
+}
+
+/// The outcome of importing an archive.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Names of all sounds that were added.
+    pub imported: Vec<String>,
+    /// Sounds that were added under a different name, as `(original, new)`.
+    pub renamed: Vec<(String, String)>,
+    /// Existing sounds that were replaced by imported ones.
+    pub overwritten: Vec<String>,
+    /// Imported sounds that were left out because their name was taken.
+    pub skipped: Vec<String>,
+    /// Imported sounds that were left out because their file isn't a usable sound or they couldn't
+    /// be renamed, with the reason.
+    pub invalid: Vec<(String, String)>,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Imported {} sounds.", self.imported.len())?;
+        for (original, new) in self.renamed.iter() {
+            write!(f, "\nRenamed `{original}` to `{new}`.")?;
+        }
+        if !self.overwritten.is_empty() {
+            write!(f, "\nReplaced: {}.", self.overwritten.join(", "))?;
+        }
+        if !self.skipped.is_empty() {
+            write!(f, "\nSkipped existing: {}.", self.skipped.join(", "))?;
+        }
+        for (name, reason) in self.invalid.iter() {
+            write!(f, "\nCouldn't import `{name}`: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Directory that archives are unpacked to while being imported.
+pub fn imports_dir(storage_dir: &Path) -> PathBuf {
+    storage_dir.join(IMPORTS_DIR)
+}
+
+/// Check that an archive of `size` bytes isn't too large to import.
+pub fn check_archive_size(size: u64) -> Result<(), Error> {
+    if size > MAX_ARCHIVE_SIZE {
+        bail!(
+            "Archive is too large to import; it can be at most {} MiB.",
+            MAX_ARCHIVE_SIZE / 1024 / 1024
+        );
+    }
+    Ok(())
+}
+
+/// Add the sounds in the zip archive `archive` to `guild_id`.
+///
+/// With `limits`, sounds that are too long or large are left out, and nothing is imported if the
+/// guild doesn't have room for the rest. Everything is added in one transaction; if anything goes
+/// wrong, nothing is imported.
+#[allow(clippy::too_many_arguments)]
+pub async fn import_guild(
+    db: &dyn Repository,
+    storage_dir: &Path,
+    store: &dyn SoundStore,
+    prober: &dyn Prober,
+    guild_id: GuildId,
+    archive: Vec<u8>,
+    on_conflict: OnConflict,
+    limits: Option<&Limits>,
+) -> Result<ImportSummary, Error> {
+    check_archive_size(archive.len() as u64)?;
+
+    // every import gets a directory of its own, which is removed even if importing fails.
+    let imports_dir = imports_dir(storage_dir);
+    tokio::fs::create_dir_all(&imports_dir).await?;
+    let staging_dir = tempfile::Builder::new()
+        .prefix(&format!("{}-", guild_id.0))
+        .tempdir_in(&imports_dir)?;
+
+    let result = import_staged(
+        db,
+        store,
+        prober,
+        guild_id,
+        archive,
+        on_conflict,
+        limits,
+        staging_dir.path(),
+    )
+    .await;
+
+    let path = staging_dir.path().to_path_buf();
+    if let Err(error) = staging_dir.close() {
+        tracing::warn!("Couldn't clean up import directory {path:?}: {error}");
+    }
+
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn import_staged(
+    db: &dyn Repository,
+    store: &dyn SoundStore,
+    prober: &dyn Prober,
+    guild_id: GuildId,
+    archive: Vec<u8>,
+    on_conflict: OnConflict,
+    limits: Option<&Limits>,
+    staging_dir: &Path,
+) -> Result<ImportSummary, Error> {
+    let staging = staging_dir.to_path_buf();
+    let manifest =
+        tokio::task::spawn_blocking(move || extract_archive(archive, &staging, MAX_EXTRACTED_SIZE))
+            .await??;
+
+    let mut summary = ImportSummary::default();
+
+    // probe everything before touching the database.
+    let mut staged = vec![];
+    for (index, sound) in manifest.sounds.into_iter().enumerate() {
+        let path = staging_dir.join(index.to_string());
+        match prober.probe_length(path.as_os_str()).await {
+            Ok(length) => {
+                let file_size = tokio::fs::metadata(&path).await?.len() as i64;
+                if let Some(limits) = limits {
+                    if let Err(error) = limits.check_length_and_size(length, file_size) {
+                        summary.invalid.push((sound.name, error.to_string()));
+                        continue;
+                    }
+                }
+                let file_hash = hash_file(&path).await?;
+                staged.push((sound, path, length, file_hash, file_size));
+            }
+            Err(error) => summary.invalid.push((sound.name, error.to_string())),
+        }
+    }
+
+    let guild_sounds = db.guild_sounds(guild_id).await?;
+    let guild_files: HashSet<String> = guild_sounds
+        .iter()
+        .filter_map(|sound| sound.file_hash.clone())
+        .collect();
+    // keyed like names are compared, since names are unique regardless of case.
+    let mut existing: HashSet<String> = guild_sounds
+        .into_iter()
+        .map(|sound| name_key(&sound.name))
+        .collect();
+
+    let mut replaced = vec![];
+    let mut sounds = vec![];
+    let mut sound_tags = vec![];
+    let mut files = vec![];
+
+    for (sound, path, length, file_hash, file_size) in staged {
+        // names were validated when the archive was extracted.
+        let mut name = SoundName::new(&sound.name)?;
+
+        if existing.contains(&name.key()) {
+            match on_conflict {
+                OnConflict::Skip => {
+                    summary.skipped.push(name.into_string());
+                    continue;
+                }
+                OnConflict::Rename => {
+                    let new_name = (2..)
+                        .map(|n| SoundName::new(&format!("{name} ({n})")))
+                        .find(|candidate| match candidate {
+                            Ok(candidate) => !existing.contains(&candidate.key()),
+                            Err(_) => true,
+                        })
+                        .unwrap();
+                    let new_name = match new_name {
+                        Ok(new_name) => new_name,
+                        Err(error) => {
+                            summary
+                                .invalid
+                                .push((name.into_string(), error.to_string()));
+                            continue;
+                        }
+                    };
+                    summary
+                        .renamed
+                        .push((name.into_string(), new_name.to_string()));
+                    name = new_name;
+                }
+                OnConflict::Overwrite => {
+                    replaced.push((guild_id, name.clone()));
+                    summary.overwritten.push(name.to_string());
+                }
+            }
+        }
+
+        files.push((path, file_hash.clone()));
+        existing.insert(name.key());
+        summary.imported.push(name.to_string());
+        sounds.push(NewSound {
+            guild_id,
+            name,
+            source: sound.source,
+            uploader_id: UserId(sound.uploader_id),
+            length,
+            created_at: None,
+            file_hash,
+            file_size,
+            metadata: None,
+            volume: sound.volume,
+        });
+        sound_tags.push(sound.tags);
+    }
+
+    if let (Some(limits), false) = (limits, sounds.is_empty()) {
+        // files the guild already has don't take up any more of its storage. replaced sounds
+        // might free some up, but they're still counted, to be safe.
+        let new_files: HashMap<&str, i64> = sounds
+            .iter()
+            .filter(|sound| !guild_files.contains(&sound.file_hash))
+            .map(|sound| (sound.file_hash.as_str(), sound.file_size))
+            .collect();
+        let usage = db.usage(guild_id).await?;
+        limits
+            .check_room_for(
+                &usage,
+                (sounds.len() - replaced.len()) as i64,
+                new_files.values().sum(),
+            )
+            .context("Couldn't import the archive")?;
+    }
+
+    // store files first; if adding the sounds fails, they're left for garbage collection.
+    for (path, file_hash) in files {
+        store.put(&file_hash, &path).await?;
+    }
+
+    let ids = db.import_sounds(&replaced, &sounds, &[]).await?;
+    for (sound_id, tags) in ids.into_iter().zip(sound_tags) {
+        if !tags.is_empty() {
+            db.set_sound_tags(sound_id, &tags).await?;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Validate the manifest of `archive` and unpack its sounds to `staging_dir`.
+///
+/// Each sound is written to a file named after its index in the manifest. Archives that unpack to
+/// more than `max_size` bytes are rejected, whatever their entries claim their sizes are.
+fn extract_archive(archive: Vec<u8>, staging_dir: &Path, max_size: u64) -> Result<Manifest, Error> {
+    let mut zip = ZipArchive::new(Cursor::new(archive))?;
+
+    let manifest: Manifest = {
+        let mut file = zip
+            .by_name(MANIFEST_NAME)
+            .map_err(|_| anyhow!("Archive has no `{MANIFEST_NAME}`."))?;
+        let mut contents = vec![];
+        (&mut file)
+            .take(MAX_MANIFEST_SIZE + 1)
+            .read_to_end(&mut contents)?;
+        if contents.len() as u64 > MAX_MANIFEST_SIZE {
+            bail!("Archive's `{MANIFEST_NAME}` is too large.");
+        }
+        serde_json::from_slice(&contents)?
+    };
+
+    if manifest.version > MANIFEST_VERSION {
+        bail!(
+            "Archive was made by a newer version of this bot (manifest version {}).",
+            manifest.version
+        );
+    }
+
+    let mut names = HashSet::new();
+    for sound in manifest.sounds.iter_mut() {
+        let name = SoundName::new(&sound.name).map_err(|error| {
+            anyhow!(
+                "Archive contains a sound with an invalid name, {:?}: {error}",
+                sound.name
+            )
+        })?;
+        if !names.insert(name.key()) {
+            bail!("Archive contains more than one sound named `{name}`.");
+        }
+        sound.tags = sounds::normalize_tags(&sound.tags)
+            .map_err(|error| anyhow!("Archive has invalid tags for `{name}`: {error}"))?;
+        sounds::check_volume(sound.volume)
+            .map_err(|error| anyhow!("Archive has an invalid volume for `{name}`: {error}"))?;
+    }
+
+    let mut budget = max_size;
+    for (index, sound) in manifest.sounds.iter().enumerate() {
+        let file = zip
+            .by_name(&sound.file)
+            .map_err(|_| anyhow!("Archive is missing the file for `{}`.", sound.name))?;
+        let mut dest = File::create(staging_dir.join(index.to_string()))?;
+        let written = std::io::copy(&mut file.take(budget + 1), &mut dest)?;
+        if written > budget {
+            bail!(
+                "Archive unpacks to more than {} MiB.",
+                max_size / 1024 / 1024
+            );
+        }
+        budget -= written;
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{names, seed, setup, Setup, GUILD, NO_LIMITS, OTHER_GUILD};
+
+    /// Build an archive with one sound, `bonk`, whose file is `size` zeros.
+    fn archive_with_sound(size: usize) -> Vec<u8> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            guild_id: 1,
+            exported_at: Utc::now(),
+            sounds: vec![ManifestSound {
+                name: "bonk".to_owned(),
+                file: "sounds/bonk".to_owned(),
+                source: "https://example.com/bonk".to_owned(),
+                uploader_id: 2,
+                length: 1000,
+                created_at: Utc::now(),
+                tags: vec![],
+                volume: DEFAULT_VOLUME,
+            }],
+        };
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file(MANIFEST_NAME, FileOptions::default())
+            .unwrap();
+        serde_json::to_writer(&mut zip, &manifest).unwrap();
+        zip.start_file("sounds/bonk", FileOptions::default())
+            .unwrap();
+        zip.write_all(&vec![0; size]).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn extracting_stops_at_the_size_budget() {
+        let staging_dir = tempfile::tempdir().unwrap();
+        let mib = 1024 * 1024;
+
+        // zeros compress well, so the archive is much smaller than what it unpacks to.
+        let archive = archive_with_sound(2 * mib);
+        assert!(archive.len() < 64 * 1024);
+
+        let error = extract_archive(archive.clone(), staging_dir.path(), mib as u64).unwrap_err();
+        assert_eq!(error.to_string(), "Archive unpacks to more than 1 MiB.");
+
+        let manifest = extract_archive(archive, staging_dir.path(), 2 * mib as u64).unwrap();
+        assert_eq!(manifest.sounds.len(), 1);
+        let extracted = std::fs::metadata(staging_dir.path().join("0")).unwrap();
+        assert_eq!(extracted.len(), 2 * mib as u64);
+    }
+
+    /// Export `GUILD`'s sounds, returning the archive.
+    async fn export(setup: &Setup) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join(export_file_name(GUILD));
+
+        export_guild(&*setup.data.db, &setup.store, GUILD, &dest)
+            .await
+            .unwrap();
+        std::fs::read(dest).unwrap()
+    }
+
+    /// Import `archive` to `guild_id`, with `limits` if there are any, checking that nothing's
+    /// left behind in the imports directory.
+    async fn import(
+        setup: &Setup,
+        guild_id: GuildId,
+        archive: Vec<u8>,
+        on_conflict: OnConflict,
+        limits: Option<&Limits>,
+    ) -> Result<ImportSummary, Error> {
+        let storage_dir = tempfile::tempdir().unwrap();
+
+        let result = import_guild(
+            &*setup.data.db,
+            storage_dir.path(),
+            &setup.store,
+            &setup.media,
+            guild_id,
+            archive,
+            on_conflict,
+            limits,
+        )
+        .await;
+
+        let staged = std::fs::read_dir(imports_dir(storage_dir.path())).unwrap();
+        assert_eq!(staged.count(), 0);
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exported_sounds_can_be_imported_elsewhere() {
+        let setup = setup();
+        let bonk = seed(&setup, GUILD, "bonk").await;
+        let boop = seed(&setup, GUILD, "boop").await;
+        let db = &*setup.data.db;
+        db.set_sound_tags(bonk, &["funny".to_owned(), "loud".to_owned()])
+            .await
+            .unwrap();
+        db.set_sound_volume(boop, 50).await.unwrap();
+        // seeded files hold their sound's name.
+        setup
+            .media
+            .add_sound("https://example.com/bonk", b"bonk", 1000);
+        setup
+            .media
+            .add_sound("https://example.com/boop", b"boop", 2000);
+        let archive = export(&setup).await;
+
+        let summary = import(&setup, OTHER_GUILD, archive.clone(), OnConflict::Skip, None)
+            .await
+            .unwrap();
+        assert_eq!(summary.imported.len(), 2);
+        assert_eq!(names(&setup.data, OTHER_GUILD).await, ["bonk", "boop"]);
+        let boop = setup
+            .data
+            .db
+            .find_sound(OTHER_GUILD, &SoundName::new("boop").unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(boop.length, 2000);
+        assert_eq!(boop.source, "https://example.com/boop");
+        assert_eq!(boop.volume, 50);
+        let tagged = sounds::list_tagged(&setup.data, OTHER_GUILD).await.unwrap();
+        assert_eq!(tagged[0].tags, ["funny", "loud"]);
+        assert_eq!(tagged[0].sound.volume, DEFAULT_VOLUME);
+        assert!(tagged[1].tags.is_empty());
+
+        // importing again only renames the sounds it's asked to.
+        let summary = import(&setup, OTHER_GUILD, archive, OnConflict::Rename, None)
+            .await
+            .unwrap();
+        assert_eq!(summary.renamed.len(), 2);
+        assert_eq!(
+            names(&setup.data, OTHER_GUILD).await,
+            ["bonk", "bonk (2)", "boop", "boop (2)"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn imports_are_held_to_limits() {
+        let setup = setup();
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+        setup
+            .media
+            .add_sound("https://example.com/bonk", b"bonk", 1000);
+        setup
+            .media
+            .add_sound("https://example.com/boop", b"boop", 5000);
+        let archive = export(&setup).await;
+
+        let limits = Limits {
+            max_length: Some(2000),
+            ..NO_LIMITS
+        };
+        let summary = import(
+            &setup,
+            OTHER_GUILD,
+            archive.clone(),
+            OnConflict::Skip,
+            Some(&limits),
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.imported, ["bonk"]);
+        assert_eq!(
+            summary.invalid,
+            [(
+                "boop".to_owned(),
+                "The sound is 5.0 seconds, but sounds can be at most 2.0 seconds.".to_owned()
+            )]
+        );
+
+        // nothing's imported if there isn't room for everything.
+        let limits = Limits {
+            max_sounds: Some(1),
+            ..NO_LIMITS
+        };
+        let error = import(&setup, GuildId(3), archive, OnConflict::Skip, Some(&limits))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Couldn't import the archive");
+        assert!(names(&setup.data, GuildId(3)).await.is_empty());
+    }
+
+    #[test]
+    fn large_archives_are_rejected_before_importing() {
+        assert!(check_archive_size(MAX_ARCHIVE_SIZE).is_ok());
+        let error = check_archive_size(MAX_ARCHIVE_SIZE + 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Archive is too large to import; it can be at most 512 MiB."
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Parser, Subcommand};
+use poise::serenity_prelude::{ApplicationCommand, GuildId, Http};
+
+use crate::archive::{self, OnConflict};
+use crate::config::Config;
+use crate::quota::{GuildLimits, Limits};
+use crate::soundbert::{self, Dump};
+use crate::{all_commands, connect_database, migrate_database, storage, Data, Error};
+
+/// A soundboard for Discord.
+///
+/// Runs the bot when no subcommand is given.
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+pub struct Cli {
+    /// Config file to read settings from, instead of BERNIE_CONFIG or `bernie.toml`.
+    #[clap(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the bot.
+    Run,
+    #[clap(flatten)]
+    Admin(AdminCommand),
+}
+
+/// Subcommands that administer the bot instead of running it.
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// Apply or revert database migrations.
+    Migrate {
+        #[clap(arg_enum, default_value = "up")]
+        direction: Direction,
+    },
+    /// Report sounds without files, unused and stray files, and sounds with wrong lengths.
+    CheckStorage {
+        /// Fix the problems found by re-probing lengths, re-downloading missing files from their
+        /// source, deleting unused files, and quarantining stray files.
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Download sounds from their source again, keeping their ids and play history.
+    ///
+    /// Replaced files are left for collect-garbage.
+    RefreshSounds {
+        /// Only refresh the sounds in this guild.
+        #[clap(long)]
+        guild_id: Option<u64>,
+    },
+    /// Delete sound files that no sound uses anymore.
+    ///
+    /// Files stored within the last hour are kept, since they may belong to sounds being added.
+    CollectGarbage,
+    /// Permanently delete removed sounds and their play history.
+    PurgeDeleted {
+        /// Only purge sounds removed at least this many days ago.
+        #[clap(long, default_value = "0")]
+        older_than: i32,
+    },
+    /// Register slash commands with Discord.
+    RegisterCommands {
+        /// Register the commands in this guild only, instead of globally.
+        #[clap(long)]
+        guild_id: Option<u64>,
+    },
+    /// Export a guild's sounds to an archive.
+    Export {
+        /// ID of the guild to export.
+        guild_id: u64,
+        /// Where to write the archive. Defaults to the exports directory in STORAGE_DIR.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import sounds from an archive into a guild, regardless of its limits.
+    Import {
+        /// ID of the guild to import into.
+        guild_id: u64,
+        /// Archive to import.
+        path: PathBuf,
+        /// What to do with sounds whose names are already taken.
+        #[clap(long, arg_enum, default_value = "skip")]
+        on_conflict: OnConflict,
+    },
+    /// Migrate guilds, sounds and plays from a SoundBert data dump.
+    MigrateSoundbert {
+        /// JSON export of SoundBert's `sounds` table.
+        #[clap(long)]
+        sounds: PathBuf,
+        /// JSON export of SoundBert's `plays` table.
+        #[clap(long)]
+        plays: Option<PathBuf>,
+        /// SoundBert's sound directory.
+        #[clap(long)]
+        sound_dir: PathBuf,
+    },
+    /// Override a guild's limits on sounds. Limits that aren't given are left as they are.
+    ///
+    /// A limit of 0 means there's no limit.
+    SetLimits {
+        /// ID of the guild whose limits to change.
+        guild_id: u64,
+        /// Longest a sound can be, in seconds.
+        #[clap(long)]
+        max_seconds: Option<i32>,
+        /// Largest a sound's file can be, in MiB.
+        #[clap(long)]
+        max_mb: Option<i64>,
+        /// Most storage the guild's sounds can use together, in MiB.
+        #[clap(long)]
+        storage_mb: Option<i64>,
+        /// Most sounds the guild can have.
+        #[clap(long)]
+        max_sounds: Option<i32>,
+        /// Go back to the default limits before applying any given ones.
+        #[clap(long)]
+        reset: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum Direction {
+    /// Apply all pending migrations.
+    Up,
+    /// Revert the latest applied migration.
+    Down,
+}
+
+/// Run an administration subcommand.
+pub async fn execute(command: AdminCommand, config: &Config) -> Result<(), Error> {
+    match command {
+        AdminCommand::Migrate { direction } => migrate(config, direction).await,
+        AdminCommand::CheckStorage { repair } => check_storage(config, repair).await,
+        AdminCommand::RefreshSounds { guild_id } => refresh_sounds(config, guild_id).await,
+        AdminCommand::CollectGarbage => collect_garbage(config).await,
+        AdminCommand::PurgeDeleted { older_than } => purge_deleted(config, older_than).await,
+        AdminCommand::RegisterCommands { guild_id } => register_commands(config, guild_id).await,
+        AdminCommand::Export { guild_id, output } => export(config, guild_id, output).await,
+        AdminCommand::Import {
+            guild_id,
+            path,
+            on_conflict,
+        } => import(config, guild_id, path, on_conflict).await,
+        AdminCommand::MigrateSoundbert {
+            sounds,
+            plays,
+            sound_dir,
+        } => migrate_soundbert(config, sounds, plays, sound_dir).await,
+        AdminCommand::SetLimits {
+            guild_id,
+            max_seconds,
+            max_mb,
+            storage_mb,
+            max_sounds,
+            reset,
+        } => {
+            let overrides = GuildLimits {
+                max_length: max_seconds.map(|seconds| seconds * 1000),
+                max_size: max_mb.map(|mb| mb * 1024 * 1024),
+                max_total_size: storage_mb.map(|mb| mb * 1024 * 1024),
+                max_sounds,
+            };
+            set_limits(config, guild_id, overrides, reset).await
+        }
+    }
+}
+
+async fn migrate(config: &Config, direction: Direction) -> Result<(), Error> {
+    let db = connect_database(config).await?;
+
+    match direction {
+        Direction::Up => migrate_database(&*db).await?,
+        Direction::Down => match db.revert_migration().await? {
+            Some((version, description)) => {
+                println!("Reverted migration {version} ({description}).")
+            }
+            None => println!("No migrations to revert."),
+        },
+    }
+
+    Ok(())
+}
+
+async fn check_storage(config: &Config, repair: bool) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let report = storage::check(&*data.db, &data.storage_dir, &*data.store, &*data.prober).await?;
+    println!("{report}");
+
+    if repair && !report.is_consistent() {
+        let summary = storage::repair(
+            &*data.db,
+            &data.storage_dir,
+            &*data.store,
+            &*data.downloader,
+            &*data.prober,
+            report,
+        )
+        .await?;
+        println!("{summary}");
+    }
+
+    Ok(())
+}
+
+async fn refresh_sounds(config: &Config, guild_id: Option<u64>) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let summary = storage::refresh_all(
+        &*data.db,
+        &*data.store,
+        &*data.downloader,
+        &*data.prober,
+        guild_id.map(GuildId),
+    )
+    .await?;
+    println!("{summary}");
+
+    Ok(())
+}
+
+async fn collect_garbage(config: &Config) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let collected = storage::collect_garbage(&*data.db, &*data.store).await?;
+    println!("Deleted {collected} unreferenced files.");
+
+    Ok(())
+}
+
+async fn purge_deleted(config: &Config, older_than: i32) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let purged = data.db.purge_removed(older_than).await?;
+    println!("Purged {purged} sounds.");
+
+    Ok(())
+}
+
+async fn register_commands(config: &Config, guild_id: Option<u64>) -> Result<(), Error> {
+    let token = config.discord_token()?;
+
+    let application_id = Http::new_with_token(&token)
+        .get_current_application_info()
+        .await?
+        .id;
+    let http = Http::new_with_token_application_id(&token, application_id.0);
+
+    let builder = poise::builtins::create_application_commands(&all_commands());
+
+    let count = match guild_id {
+        Some(guild_id) => GuildId(guild_id)
+            .set_application_commands(&http, |b| {
+                *b = builder;
+                b
+            })
+            .await?
+            .len(),
+        None => ApplicationCommand::set_global_application_commands(&http, |b| {
+            *b = builder;
+            b
+        })
+        .await?
+        .len(),
+    };
+
+    println!("Registered {count} commands.");
+
+    Ok(())
+}
+
+async fn export(config: &Config, guild_id: u64, output: Option<PathBuf>) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let guild_id = GuildId(guild_id);
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let exports_dir = archive::exports_dir(&data.storage_dir);
+            tokio::fs::create_dir_all(&exports_dir).await?;
+            exports_dir.join(archive::export_file_name(guild_id))
+        }
+    };
+
+    let manifest = archive::export_guild(&*data.db, &*data.store, guild_id, &output).await?;
+    println!(
+        "Exported {} sounds from guild {} to {output:?}.",
+        manifest.sounds.len(),
+        guild_id.0
+    );
+
+    Ok(())
+}
+
+async fn import(
+    config: &Config,
+    guild_id: u64,
+    path: PathBuf,
+    on_conflict: OnConflict,
+) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    archive::check_archive_size(tokio::fs::metadata(&path).await?.len())?;
+    let archive = tokio::fs::read(&path).await?;
+    let summary = archive::import_guild(
+        &*data.db,
+        &data.storage_dir,
+        &*data.store,
+        &*data.prober,
+        GuildId(guild_id),
+        archive,
+        on_conflict,
+        None,
+    )
+    .await?;
+    println!("{summary}");
+
+    Ok(())
+}
+
+async fn migrate_soundbert(
+    config: &Config,
+
+    sounds: PathBuf,
+    plays: Option<PathBuf>,
+    sound_dir: PathBuf,
+) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let dump = Dump::load(&sounds, plays.as_deref(), &sound_dir).await?;
+    let summary = soundbert::migrate(&*data.db, &*data.store, &*data.prober, dump).await?;
+
+    for (guild_id, name, reason) in summary.skipped.iter() {
+        println!("Skipped `{name}` in guild {guild_id}: {reason}");
+    }
+    println!(
+        "Migrated {} sounds and {} playbacks across {} guilds.",
+        summary.sounds, summary.playbacks, summary.guilds
+    );
+    if summary.skipped_playbacks > 0 {
+        println!(
+            "Skipped {} playbacks of sounds that weren't migrated.",
+            summary.skipped_playbacks
+        );
+    }
+
+    Ok(())
+}
+
+async fn set_limits(
+    config: &Config,
+    guild_id: u64,
+    overrides: GuildLimits,
+    reset: bool,
+) -> Result<(), Error> {
+    let data = Data::load(config).await?;
+
+    let guild_id = GuildId(guild_id);
+    let current = if reset {
+        GuildLimits::default()
+    } else {
+        data.db.guild_limits(guild_id).await?
+    };
+
+    let limits = GuildLimits {
+        max_length: overrides.max_length.or(current.max_length),
+        max_size: overrides.max_size.or(current.max_size),
+        max_total_size: overrides.max_total_size.or(current.max_total_size),
+        max_sounds: overrides.max_sounds.or(current.max_sounds),
+    };
+    data.db.set_guild_limits(guild_id, &limits).await?;
+
+    let limits = Limits::for_guild(&*data.db, guild_id, &data.limits).await?;
+    println!("Limits for guild {}:\n{limits}", guild_id.0);
+
+    Ok(())
+}
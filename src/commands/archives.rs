@@ -0,0 +1,99 @@
+use poise::serenity_prelude::{Attachment, AttachmentType};
+
+use crate::archive::{self, OnConflict};
+use crate::quota::Limits;
+use crate::{Context, Error};
+
+/// Largest file the bot will try to upload to Discord.
+const ATTACHMENT_LIMIT: u64 = 8 * 1024 * 1024;
+
+command! {
+    /// Export all sounds on this server as an archive.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn export(ctx: Context<'_>) -> Result<(), Error> {
+        let db = &ctx.data().db;
+        let storage_dir = &ctx.data().storage_dir;
+
+        let guild_id = ctx.guild_id().unwrap();
+
+        // let discord know we're not dead.
+        let _ = ctx.defer_or_broadcast().await;
+
+        let exports_dir = archive::exports_dir(storage_dir);
+        tokio::fs::create_dir_all(&exports_dir).await?;
+
+        let file_name = archive::export_file_name(guild_id);
+        let path = exports_dir.join(&file_name);
+
+        let manifest = archive::export_guild(db, &*ctx.data().store, guild_id, &path).await?;
+        let count = manifest.sounds.len();
+
+        if tokio::fs::metadata(&path).await?.len() <= ATTACHMENT_LIMIT {
+            let data = tokio::fs::read(&path).await?;
+            tokio::fs::remove_file(&path).await?;
+
+            ctx.send(|m| {
+                m.content(format!("Exported {count} sounds."))
+                    .attachment(AttachmentType::Bytes {
+                        data: data.into(),
+                        filename: file_name,
+                    })
+            })
+            .await?;
+        } else {
+            ctx.say(format!(
+                "Exported {count} sounds, but the archive is too large to upload. \
+                It was saved on the bot's host at `{}`.",
+                path.display()
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+command! {
+    /// Import sounds from an archive made with /export.
+    #[poise::command(
+        slash_command,
+        required_permissions = "MANAGE_GUILD",
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn import(
+        ctx: Context<'_>,
+        #[description = "Archive to import."] file: Attachment,
+        #[description = "What to do with sounds whose names are already taken. Defaults to skipping them."]
+        on_conflict: Option<OnConflict>,
+    ) -> Result<(), Error> {
+        let db = &ctx.data().db;
+        let storage_dir = &ctx.data().storage_dir;
+
+        let guild_id = ctx.guild_id().unwrap();
+
+        // let discord know we're not dead.
+        let _ = ctx.defer_or_broadcast().await;
+
+        archive::check_archive_size(file.size)?;
+        let limits = Limits::for_guild(db, guild_id, &ctx.data().limits).await?;
+        let data = file.download().await?;
+        let summary = archive::import_guild(
+            db,
+            storage_dir,
+            &*ctx.data().store,
+            &*ctx.data().prober,
+            guild_id,
+            data,
+            on_conflict.unwrap_or(OnConflict::Skip),
+            Some(&limits),
+        )
+        .await?;
+
+        ctx.say(summary.to_string()).await?;
+        Ok(())
+    }
+}
@@ -1,15 +1,23 @@
+use std::sync::Arc;
+
 use poise::Command;
 
 use crate::{Data, Error};
 
+mod archives;
 mod meta;
 mod playbacks;
+mod quotas;
 mod sounds;
+mod tokens;
 
-use playbacks::{history, play, random, stop};
-use sounds::{add, list, remove, rename};
-
-pub use playbacks::TrackManager;
+use archives::{export, import};
+use playbacks::{history, pause, play, random, resume, seek, skip, stop};
+use quotas::quota;
+use sounds::{add, cancel, info, list, refresh, remove, rename};
+use tokens::token;
 
-pub const COMMANDS: [fn() -> Command<Data, Error>; 8] =
-    [play, random, stop, add, list, rename, remove, history];
+pub const COMMANDS: [fn() -> Command<Arc<Data>, Error>; 19] = [
+    play, random, stop, skip, pause, resume, seek, add, cancel, list, info, refresh, rename,
+    remove, history, export, import, quota, token,
+];
@@ -1,200 +1,251 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
-use poise::serenity_prelude::{GuildId, Mention, UserId};
-use songbird::tracks::TrackHandle;
-use tokio::sync::Mutex;
+use poise::serenity_prelude::{Mention, User, UserId};
 
+use crate::services;
+use crate::services::playbacks::StopFilter;
+use crate::sound_name::SoundName;
 use crate::{Context, Error};
 
-type PlaybackId = i32;
-
-type AtomicHashMap<K, V> = Arc<Mutex<HashMap<K, V>>>;
+command! {
+    /// Play a sound in your current voice channel.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn play(
+        ctx: Context<'_>,
+        #[description = "Sound to play."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: SoundName,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+        let player_id = ctx.author().id;
+
+        let guild = ctx.guild().ok_or(anyhow!("Not in a guild."))?;
+        let channel_id = guild
+            .voice_states
+            .get(&player_id)
+            .and_then(|voice_state| voice_state.channel_id);
+        let roles = guild
+            .members
+            .get(&player_id)
+            .map(|member| member.roles.clone())
+            .unwrap_or_default();
+
+        let playback_id =
+            services::playbacks::play(ctx.data(), guild_id, channel_id, player_id, &roles, &name)
+                .await?;
+        tracing::Span::current().record("playback_id", &playback_id);
+
+        ctx.say("✅").await?;
+        Ok(())
+    }
+}
 
-#[derive(Debug)]
-pub struct TrackManager {
-    handles: AtomicHashMap<GuildId, Vec<(PlaybackId, TrackHandle)>>,
+command! {
+    /// Play a random sound in your current voice channel.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn random(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.say("✅").await?;
+        Ok(())
+    }
 }
 
-impl TrackManager {
-    pub fn new() -> Self {
-        Self {
-            handles: Arc::new(Mutex::new(HashMap::new())),
+command! {
+    /// Stop the currently playing sounds, or only some of them.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn stop(
+        ctx: Context<'_>,
+        #[description = "Only stop this sound."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: Option<SoundName>,
+        #[description = "Only stop sounds you played."] mine: Option<bool>,
+        #[description = "Only stop sounds this user played."] user: Option<User>,
+        #[description = "Only stop the most recent sound."] latest: Option<bool>,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+        let stopper_id = ctx.author().id;
+
+        let player_id = match (mine.unwrap_or(false), user) {
+            (true, Some(_)) => return Err(anyhow!("Pick either `mine` or `user`, not both.")),
+            (true, None) => Some(stopper_id),
+            (false, user) => user.map(|user| user.id),
+        };
+        let filter = StopFilter {
+            name: name.as_ref(),
+            player_id,
+            latest: latest.unwrap_or(false),
+        };
+        let targeted = filter.name.is_some() || filter.player_id.is_some();
+
+        let stopped = services::playbacks::stop(ctx.data(), guild_id, stopper_id, filter).await?;
+
+        if stopped.is_empty() && targeted {
+            ctx.say("Nothing like that is playing.").await?;
+        } else {
+            ctx.say("✅").await?;
         }
+        Ok(())
     }
+}
+
+command! {
+    /// Stop the sound that's been playing the longest.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn skip(ctx: Context<'_>) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
 
-    pub async fn register_playback(
-        &self,
-        guild_id: GuildId,
-        playback_id: PlaybackId,
-        track_handle: TrackHandle,
-    ) {
-        let mut lock = self.handles.lock().await;
+        let skipped = services::playbacks::skip(ctx.data(), guild_id, ctx.author().id).await?;
 
-        let handlers = lock.entry(guild_id).or_default();
-        handlers.push((playback_id, track_handle));
+        say_done(ctx, &skipped).await
     }
+}
 
-    pub async fn stop_playback(&self, guild_id: &GuildId) -> Result<Vec<PlaybackId>, Error> {
-        let mut lock = self.handles.lock().await;
-        let handlers = lock.remove(guild_id).unwrap_or_default();
+command! {
+    /// Pause the sounds playing in this server.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn pause(ctx: Context<'_>) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
 
-        let mut stopped = vec![];
-        for (playback_id, handler) in handlers.iter() {
-            handler.stop()?;
-            stopped.push(playback_id.to_owned());
-        }
+        let paused = services::playbacks::pause(ctx.data(), guild_id).await?;
 
-        Ok(stopped)
+        say_done(ctx, &paused).await
     }
 }
 
-/// Play a sound in your current voice channel.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn play(
-    ctx: Context<'_>,
-    #[description = "Sound to play."]
-    #[autocomplete = "super::meta::autocomplete_sound_name"]
-    name: String,
-) -> Result<(), Error> {
-    let db = &ctx.data().db;
-    let storage_dir = &ctx.data().storage_dir;
-    let mut transaction = db.begin().await?;
-
-    let guild_id = ctx.guild_id().unwrap();
-    let player_id = ctx.author().id;
-
-    let playback_id = sqlx::query!(
-        "insert into playbacks(sound_id, player_id) \
-        select id, $1 from sounds where guild_id = $2 and name = $3 and deleted_at is null
-        returning playbacks.id",
-        player_id.0 as i64,
-        guild_id.0 as i64,
-        &name
-    )
-    .map(|record| record.id)
-    .fetch_one(&mut transaction)
-    .await?;
-
-    let manager = songbird::get(ctx.discord())
-        .await
-        .expect("Expected songbird client in data at initialization.")
-        .clone();
-
-    let call_lock = manager.get_or_insert(guild_id.0);
-    let channel = ctx
-        .guild()
-        .ok_or(anyhow!("Not in a guild."))?
-        .voice_states
-        .get(&ctx.author().id)
-        .and_then(|voice_state| voice_state.channel_id)
-        .ok_or(anyhow!("Not in a voice channel."))?;
-    let mut call = call_lock.lock().await;
-
-    let file = storage_dir.join(guild_id.0.to_string()).join(name);
-    let source = songbird::ffmpeg(file).await?;
-
-    let (track, track_handle) = songbird::create_player(source);
-
-    ctx.data()
-        .track_manager
-        .register_playback(guild_id, playback_id, track_handle)
-        .await;
-
-    call.join(channel).await?;
-    call.play(track);
-
-    transaction.commit().await?;
-    ctx.say("✅").await?;
-    Ok(())
+command! {
+    /// Resume the paused sounds in this server.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn resume(ctx: Context<'_>) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        let resumed = services::playbacks::resume(ctx.data(), guild_id).await?;
+
+        say_done(ctx, &resumed).await
+    }
 }
 
-/// Play a random sound in your current voice channel.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn random(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("✅").await?;
-    Ok(())
+command! {
+    /// Skip to a time in the sounds playing in this server.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn seek(
+        ctx: Context<'_>,
+        #[description = "Time to skip to, like `1:30` or `90`."] time: String,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+        let position = parse_time(&time)?;
+
+        let moved = services::playbacks::seek(ctx.data(), guild_id, position).await?;
+
+        say_done(ctx, &moved).await
+    }
 }
 
-/// Stop the currently playing sound.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn stop(ctx: Context<'_>) -> Result<(), Error> {
-    let db = &ctx.data().db;
-    let guild_id = ctx.guild_id().unwrap();
-    let playback_manager = &ctx.data().track_manager;
+/// Reply that it's done, or that nothing was playing to do it to.
+async fn say_done(ctx: Context<'_>, playback_ids: &[i32]) -> Result<(), Error> {
+    if playback_ids.is_empty() {
+        ctx.say("Nothing is playing.").await?;
+    } else {
+        ctx.say("✅").await?;
+    }
+    Ok(())
+}
 
-    let stopped = playback_manager.stop_playback(&guild_id).await?;
+/// Parse a time like `90`, `1:30` or `1:02:03.5` into how far into a sound it is.
+fn parse_time(time: &str) -> Result<Duration, Error> {
+    let invalid = || anyhow!("Give a time like `1:30` or `90`.");
 
-    let stopper_id = ctx.author().id;
+    let parts: Vec<&str> = time.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
 
-    sqlx::query!(
-        "update playbacks set stopper_id = $1, stopped_at = current_timestamp \
-        from (select unnest($2::int[]) as id) as stopped \
-        where playbacks.id = stopped.id",
-        stopper_id.0 as i64,
-        &stopped
-    )
-    .execute(db)
-    .await?;
+    let (seconds, larger) = parts.split_last().ok_or_else(invalid)?;
+    let mut total: f64 = seconds.parse().map_err(|_| invalid())?;
+    for (part, unit) in larger.iter().rev().zip([60.0, 3600.0]) {
+        let value: u32 = part.parse().map_err(|_| invalid())?;
+        total += f64::from(value) * unit;
+    }
+    if !total.is_finite() || total < 0.0 {
+        return Err(invalid());
+    }
 
-    ctx.say("✅").await?;
-    Ok(())
+    Ok(Duration::from_secs_f64(total))
 }
 
-/// Show sound play history.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn history(
-    ctx: Context<'_>,
-    #[description = "Optional sound to get the playback history of."]
-    #[autocomplete = "super::meta::autocomplete_sound_name"]
-    name: Option<String>,
-) -> Result<(), Error> {
-    let db = &ctx.data().db;
-
-    let guild_id = ctx.guild_id().unwrap();
-
-    // language=PostgreSQL
-    let history: Vec<String> = sqlx::query!(
-        "select playbacks.*, sounds.name from playbacks \
-        inner join sounds on sounds.id = playbacks.sound_id \
-        where sounds.guild_id = $1 and ($2::text is null or sounds.name = $2) \
-        order by playbacks.created_at desc",
-        guild_id.0 as i64,
-        name
-    )
-    .map(|record| {
-        let name = record.name.to_string();
-        let player: UserId = (record.player_id as u64).into();
-        let stopper: Option<UserId> = record.stopper_id.map(|id| (id as u64).into());
-
-        let created = record.created_at;
-
-        if let Some(stopper) = stopper {
-            let stopped = record.stopped_at.unwrap();
-            let duration = stopped - created;
-            let seconds = duration.num_milliseconds() as f64 / 1000_f64;
-
-            format!(
-                "{} by {}; stopped by {} after {} seconds",
-                name,
-                Mention::from(player),
-                Mention::from(stopper),
-                seconds
-            )
+command! {
+    /// Show sound play history.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn history(
+        ctx: Context<'_>,
+        #[description = "Optional sound to get the playback history of."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: Option<SoundName>,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        let history: Vec<String> = services::playbacks::history(ctx.data(), guild_id, name.as_ref())
+            .await?
+            .into_iter()
+            .map(|record| {
+                let name = record.name.to_string();
+                let player: UserId = (record.player_id as u64).into();
+                let stopper: Option<UserId> = record.stopper_id.map(|id| (id as u64).into());
+
+                let seconds = record
+                    .duration()
+                    .map(|duration| duration.num_milliseconds() as f64 / 1000_f64);
+
+                match (stopper, record.stop_reason, seconds) {
+                    (Some(stopper), _, Some(seconds)) => format!(
+                        "{} by {}; stopped by {} after {} seconds",
+                        name,
+                        Mention::from(player),
+                        Mention::from(stopper),
+                        seconds
+                    ),
+                    (None, Some(reason), Some(seconds)) => format!(
+                        "{} by {}; stopped after {} seconds ({})",
+                        name,
+                        Mention::from(player),
+                        seconds,
+                        reason
+                    ),
+                    _ => format!("{} by {}", name, Mention::from(player)),
+                }
+            })
+            .collect();
+
+        let msg = if history.is_empty() {
+            "There's nothing here. Play a sound with /play or add a new sound with /add.".to_owned()
         } else {
-            format!("{} by {}", name, Mention::from(player))
-        }
-    })
-    .fetch_all(db)
-    .await?;
+            history.join("\n")
+        };
 
-    let msg = if history.is_empty() {
-        "There's nothing here. Play a sound with /play or add a new sound with /add.".to_owned()
-    } else {
-        history.join("\n")
-    };
-
-    ctx.say(msg).await?;
-    Ok(())
+        ctx.say(msg).await?;
+        Ok(())
+    }
 }
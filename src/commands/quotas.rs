@@ -0,0 +1,43 @@
+use crate::quota::{format_length, format_size, Limits};
+use crate::{Context, Error};
+
+command! {
+    /// Show how much of this server's sound quota is used.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn quota(ctx: Context<'_>) -> Result<(), Error> {
+        let db = &*ctx.data().db;
+
+        let guild_id = ctx.guild_id().unwrap();
+
+        let limits = Limits::for_guild(db, guild_id, &ctx.data().limits).await?;
+        let usage = db.usage(guild_id).await?;
+
+        let of = |limit: Option<String>| match limit {
+            Some(limit) => format!(" of {limit}"),
+            None => String::new(),
+        };
+        let msg = format!(
+            "Sounds: {}{}\n\
+            Storage: {}{}\n\
+            Longest sound: {}\n\
+            Largest sound: {}",
+            usage.sounds,
+            of(limits.max_sounds.map(|max| max.to_string())),
+            format_size(usage.total_size),
+            of(limits.max_total_size.map(format_size)),
+            limits
+                .max_length
+                .map_or_else(|| "unlimited".to_owned(), format_length),
+            limits
+                .max_size
+                .map_or_else(|| "unlimited".to_owned(), format_size),
+        );
+
+        ctx.say(msg).await?;
+        Ok(())
+    }
+}
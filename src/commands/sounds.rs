@@ -1,230 +1,206 @@
+use poise::serenity_prelude::{Mention, UserId};
+
+use crate::db::DEFAULT_VOLUME;
+use crate::quota::format_length;
+use crate::services;
+use crate::sound_name::SoundName;
 use crate::{Context, Error};
-use anyhow::anyhow;
-use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
-
-/// Add a new sound.
-#[poise::command(
-    slash_command,
-    prefix_command,
-    check = "super::meta::ensure_guild_check"
-)]
-pub(super) async fn add(
-    ctx: Context<'_>,
-    #[description = "Name of the new sound."] name: String,
-    #[description = "Where to download the sound from."] source: String,
-) -> Result<(), Error> {
-    let db = &ctx.data().db;
-    let mut transaction = db.begin().await?;
-
-    let guild_id = ctx.guild_id().unwrap();
-    let uploader_id = ctx.author().id;
-
-    // try this insert first to make sure the sound doesn't already exist.
-    let sound_id = sqlx::query!(
-        "insert into sounds(guild_id, name, source, uploader_id, length) \
-            values($1, $2, $3, $4, $5)
-            returning id",
-        guild_id.0 as i64,
-        name,
-        source,
-        uploader_id.0 as i64,
-        0 // we calculate this later
-    )
-    .map(|record| record.id)
-    .fetch_one(&mut transaction)
-    .await?;
-
-    // let discord know we're not dead.
-    let _ = ctx.defer_or_broadcast().await;
-
-    let ytdl_args = [
-        "--quiet",
-        // "--print-json",
-        "-f",
-        "webm[abr>0]/bestaudio/best",
-        "-R",
-        "infinite",
-        "--no-playlist",
-        "--ignore-config",
-        "--no-warnings",
-        &source,
-        "-o",
-        "-",
-    ];
-
-    // todo: make this so that it writes directly to file rather than to memory, then to file.
-    // download file and write it to `<storage dir>/<guild id>/<sound name>`
-    let ytdl_output = tokio::process::Command::new("yt-dlp")
-        .args(&ytdl_args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        // .stderr(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?
-        .wait_with_output()
-        .await?;
 
-    // let metadata: Value = serde_json::from_slice(&ytdl_output.stderr)?;
-    let download = &ytdl_output.stdout;
+command! {
+    /// Add a new sound.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn add(
+        ctx: Context<'_>,
+        #[description = "Where to download the sound from."] source: String,
+        #[description = "Name of the new sound, if not the source's title."]
+        name: Option<SoundName>,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+        let uploader_id = ctx.author().id;
+        let named = name.is_some();
+
+        // let discord know we're not dead.
+        let _ = ctx.defer_or_broadcast().await;
+
+        let (_, name) =
+            services::sounds::add(ctx.data(), guild_id, uploader_id, name, source).await?;
+
+        // we're done here.
+        if named {
+            ctx.say("✅").await?;
+        } else {
+            ctx.say(format!("✅ Added as `{name}`.")).await?;
+        }
+        Ok(())
+    }
+}
+
+command! {
+    /// Cancel the sound you're adding.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn cancel(ctx: Context<'_>) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
 
-    let guild_dir = &ctx.data().storage_dir.join(guild_id.0.to_string());
-    let sound_path = guild_dir.join(&name);
+        services::sounds::cancel(ctx.data(), guild_id, ctx.author().id)?;
 
-    if sound_path.is_file() {
-        panic!("Sound path already exists: {sound_path:?}");
+        ctx.say("✅").await?;
+        Ok(())
     }
-    let mut file = tokio::fs::File::create(&sound_path).await?;
-
-    file.write_all(download).await?;
-
-    // get the sound's length.
-    // using ffprobe here because `metadata["duration"]` is unreliable
-    static FFPROBE_ARGS: [&str; 6] = [
-        "-v",
-        "quiet",
-        "-show_entries",
-        "format=duration",
-        "-of",
-        "default=noprint_wrappers=1:nokey=1",
-    ];
-
-    let ffprobe_output = tokio::process::Command::new("ffprobe")
-        .arg(&sound_path)
-        .args(&FFPROBE_ARGS)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await?;
-
-    let length = std::str::from_utf8(&ffprobe_output.stdout)?
-        .trim()
-        .parse::<f64>()?
-        * 1000.0;
-
-    sqlx::query!(
-        "update sounds set length = $1 \
-        where id = $2",
-        length as i32,
-        sound_id
-    )
-    .execute(&mut transaction)
-    .await?;
-
-    // we're done here.
-    transaction.commit().await?;
-    ctx.say("✅").await?;
-    Ok(())
 }
 
-/// List all sounds on this server.
-#[poise::command(
-    slash_command,
-    prefix_command,
-    check = "super::meta::ensure_guild_check"
-)]
-pub(super) async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let db = &ctx.data().db;
-
-    let guild_id = ctx.guild_id().unwrap();
-
-    let sounds: Vec<String> = sqlx::query!(
-        "select name from sounds \
-        where guild_id = $1 and deleted_at is null \
-        order by name",
-        guild_id.0 as i64
-    )
-    .map(|record| record.name)
-    .fetch_all(db)
-    .await?;
-
-    let msg = if sounds.is_empty() {
-        "There's nothing here. Try adding a sound using /add.".to_owned()
-    } else {
-        sounds.join("\n")
-    };
-
-    ctx.say(msg).await?;
-    Ok(())
+command! {
+    /// List all sounds on this server.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        let sounds: Vec<String> = services::sounds::list(ctx.data(), guild_id)
+            .await?
+            .into_iter()
+            .map(|sound| sound.name)
+            .collect();
+
+        let msg = if sounds.is_empty() {
+            "There's nothing here. Try adding a sound using /add.".to_owned()
+        } else {
+            sounds.join("\n")
+        };
+
+        ctx.say(msg).await?;
+        Ok(())
+    }
 }
 
-/// Rename a sound.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn rename(
-    ctx: Context<'_>,
-    #[description = "Sound to rename."]
-    #[autocomplete = "super::meta::autocomplete_sound_name"]
-    old_name: String,
-    #[description = "New name for the sound."] new_name: String,
-) -> Result<(), Error> {
-    let db = &ctx.data().db;
-    let mut transaction = db.begin().await?;
-    let storage_dir = &ctx.data().storage_dir;
-
-    let guild_id = ctx.guild_id().unwrap();
-
-    let result = sqlx::query!(
-        "update sounds set name = $1 \
-        where guild_id = $2 and name = $3 and deleted_at is null",
-        new_name,
-        guild_id.0 as i64,
-        old_name
-    )
-    .execute(&mut transaction)
-    .await?;
-
-    if result.rows_affected() != 1 {
-        // todo: better errors
-        return Err(anyhow!(
-            "non-1 rows updated for remove. sound probably doesn't exist."
-        ));
+command! {
+    /// Show where a sound came from.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn info(
+        ctx: Context<'_>,
+        #[description = "Sound to show."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: SoundName,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        let (sound, metadata) = services::sounds::info(ctx.data(), guild_id, &name).await?;
+
+        let uploader: UserId = (sound.uploader_id as u64).into();
+        let mut lines = vec![
+            format!("**{}**", sound.name),
+            format!("Source: <{}>", sound.source),
+            format!(
+                "Added by {} on {}",
+                Mention::from(uploader),
+                sound.created_at.format("%Y-%m-%d")
+            ),
+            format!("Length: {}", format_length(sound.length)),
+        ];
+        if sound.volume != DEFAULT_VOLUME {
+            lines.push(format!("Volume: {}%", sound.volume));
+        }
+        if let Some(metadata) = metadata {
+            let fields = [
+                ("Title", metadata.title),
+                ("Uploader", metadata.uploader),
+                ("Site", metadata.extractor),
+                (
+                    "Original URL",
+                    metadata.original_url.map(|url| format!("<{url}>")),
+                ),
+                (
+                    "Thumbnail",
+                    metadata.thumbnail.map(|url| format!("<{url}>")),
+                ),
+                (
+                    "Original length",
+                    metadata
+                        .duration
+                        .map(|duration| format_length((duration * 1000.0) as i32)),
+                ),
+            ];
+            for (label, value) in fields {
+                if let Some(value) = value {
+                    lines.push(format!("{label}: {value}"));
+                }
+            }
+        }
+
+        ctx.say(lines.join("\n")).await?;
+        Ok(())
     }
-
-    let old_path = storage_dir.join(guild_id.0.to_string()).join(old_name);
-    let new_path = storage_dir.join(guild_id.0.to_string()).join(new_name);
-
-    tokio::fs::rename(old_path, new_path).await?;
-
-    transaction.commit().await?;
-    ctx.say("✅").await?;
-    Ok(())
 }
 
-/// Delete a sound.
-#[poise::command(slash_command, prefix_command)]
-pub(super) async fn remove(
-    ctx: Context<'_>,
-    #[description = "Sound to remove."]
-    #[autocomplete = "super::meta::autocomplete_sound_name"]
-    name: String,
-) -> Result<(), Error> {
-    let db = &ctx.data().db;
-    let mut transaction = db.begin().await?;
-    let storage_dir = &ctx.data().storage_dir;
-
-    let guild_id = ctx.guild_id().unwrap();
-
-    let result = sqlx::query!(
-        "update sounds set deleted_at = current_timestamp \
-        where guild_id = $1 and name = $2 and deleted_at is null",
-        guild_id.0 as i64,
-        name
-    )
-    .execute(&mut transaction)
-    .await?;
-
-    if result.rows_affected() != 1 {
-        // todo: better errors
-        return Err(anyhow!(
-            "non-1 rows updated for remove. sound probably doesn't exist."
-        ));
+command! {
+    /// Download a sound from its source again.
+    #[poise::command(
+        slash_command,
+        prefix_command,
+        check = "super::meta::ensure_guild_check"
+    )]
+    pub(super) async fn refresh(
+        ctx: Context<'_>,
+        #[description = "Sound to refresh."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: SoundName,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        // let discord know we're not dead.
+        let _ = ctx.defer_or_broadcast().await;
+
+        let sound = services::sounds::refresh(ctx.data(), guild_id, &name).await?;
+
+        ctx.say(format!(
+            "✅ `{}` is now {} long.",
+            sound.name,
+            format_length(sound.length)
+        ))
+        .await?;
+        Ok(())
     }
+}
 
-    let file = storage_dir.join(guild_id.0.to_string()).join(name);
-    tokio::fs::remove_file(file).await?;
+command! {
+    /// Rename a sound.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn rename(
+        ctx: Context<'_>,
+        #[description = "Sound to rename."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        old_name: SoundName,
+        #[description = "New name for the sound."] new_name: SoundName,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        services::sounds::rename(ctx.data(), guild_id, &old_name, &new_name).await?;
+
+        ctx.say("✅").await?;
+        Ok(())
+    }
+}
 
-    transaction.commit().await?;
-    ctx.say("✅").await?;
-    Ok(())
+command! {
+    /// Delete a sound.
+    #[poise::command(slash_command, prefix_command)]
+    pub(super) async fn remove(
+        ctx: Context<'_>,
+        #[description = "Sound to remove."]
+        #[autocomplete = "super::meta::autocomplete_sound_name"]
+        name: SoundName,
+    ) -> Result<(), Error> {
+        let guild_id = ctx.guild_id().unwrap();
+
+        services::sounds::remove(ctx.data(), guild_id, &name).await?;
+
+        ctx.say("✅").await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,32 @@
+use crate::services;
+use crate::{Context, Error};
+
+command! {
+    /// Get a token for playing sounds through the HTTP API.
+    #[poise::command(slash_command)]
+    pub(super) async fn token(
+        ctx: Context<'_>,
+        #[description = "Revoke your token instead of making a new one."] revoke: Option<bool>,
+    ) -> Result<(), Error> {
+        let user_id = ctx.author().id;
+
+        let msg = if revoke.unwrap_or(false) {
+            if services::tokens::revoke(ctx.data(), user_id).await? {
+                "Revoked your token.".to_owned()
+            } else {
+                "You don't have a token.".to_owned()
+            }
+        } else {
+            let token = services::tokens::create(ctx.data(), user_id).await?;
+            format!(
+                "Your new token is `{token}`. It replaces any token you had before, and won't be \
+                shown again. Play sounds in your voice channel with \
+                `POST /guilds/<server id>/play` and `Authorization: Bearer <token>`."
+            )
+        };
+
+        // only show the token to whoever asked for it.
+        ctx.send(|m| m.content(msg).ephemeral(true)).await?;
+        Ok(())
+    }
+}
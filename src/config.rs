@@ -0,0 +1,725 @@
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+
+use poise::serenity_prelude::RoleId;
+
+use crate::cooldown::CooldownSettings;
+use crate::quota::Limits;
+use crate::storage::StartupCheck;
+use crate::Error;
+
+/// The config file that's read if it exists, when no other one is given.
+const DEFAULT_PATH: &str = "bernie.toml";
+
+/// Bernie's settings, read from a TOML file and overridden by environment variables.
+///
+/// See `bernie.example.toml` for the file's layout and the variable overriding each setting.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Only needed to connect to Discord, so it's checked by [`Config::discord_token`].
+    discord_token: Option<String>,
+    pub database_url: String,
+    /// Local working directory for exports, imports and the like.
+    /// Also holds sound files when they're stored locally.
+    pub storage_dir: PathBuf,
+    pub storage_check: StartupCheck,
+    pub store: StoreConfig,
+    /// Limits for guilds that don't override them. Nothing's limited unless configured.
+    pub limits: Limits,
+    pub cooldowns: CooldownSettings,
+    /// How long downloads can take. `None` means there's no limit.
+    pub download_timeout: Option<Duration>,
+    /// How many times yt-dlp retries failed requests.
+    pub download_retries: u64,
+    pub yt_dlp: Program,
+    pub ffprobe: Program,
+    /// Where to serve health checks and metrics. Nothing is served if it's `None`.
+    pub http_address: Option<SocketAddr>,
+    /// How people sign in to the dashboard. It's only served if this is set.
+    pub dashboard: Option<DashboardConfig>,
+    pub log_format: LogFormat,
+}
+
+/// Where sound files are kept.
+#[derive(Debug, Clone)]
+pub enum StoreConfig {
+    /// In the storage directory.
+    Local,
+    S3(S3Config),
+}
+
+/// An S3-compatible bucket to keep sound files in.
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    /// Only needed for services other than AWS, like MinIO.
+    pub endpoint: Option<String>,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// The Discord application people sign in to the dashboard with, using OAuth2.
+#[derive(Debug, Clone)]
+pub struct DashboardConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where browsers reach the dashboard, e.g. `https://bernie.example.com`, without a trailing
+    /// slash. Discord sends people back to `<public url>/auth/callback` once they've signed in.
+    pub public_url: String,
+}
+
+/// A program Bernie runs, along with extra arguments to pass it.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub path: OsString,
+    pub args: Vec<String>,
+}
+
+/// How logs are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// A JSON object per line, including the current span.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Expected `text` or `json`, got {s:?}.")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StorageBackend {
+    Local,
+    S3,
+}
+
+impl FromStr for StorageBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Self::Local),
+            "s3" => Ok(Self::S3),
+            _ => Err(anyhow!("Expected `local` or `s3`, got {s:?}.")),
+        }
+    }
+}
+
+/// The config file as written. Everything is optional, so the environment can fill in what it
+/// leaves out.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    discord_token: Option<String>,
+    database_url: Option<String>,
+    log_format: Option<LogFormat>,
+    storage: RawStorage,
+    limits: RawLimits,
+    cooldowns: RawCooldowns,
+    downloads: RawDownloads,
+    yt_dlp: RawProgram,
+    ffprobe: RawProgram,
+    http: RawHttp,
+    dashboard: RawDashboard,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawStorage {
+    dir: Option<PathBuf>,
+    check: Option<StartupCheck>,
+    backend: Option<StorageBackend>,
+    s3: RawS3,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawS3 {
+    bucket: Option<String>,
+    region: Option<String>,
+    endpoint: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawLimits {
+    max_sound_seconds: Option<u32>,
+    max_sound_mb: Option<u32>,
+    max_guild_mb: Option<u32>,
+    max_guild_sounds: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawCooldowns {
+    plays_per_user: Option<u32>,
+    window_seconds: Option<u64>,
+    sound_seconds: Option<u64>,
+    max_tracks: Option<usize>,
+    exempt_roles: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawDownloads {
+    timeout_seconds: Option<u64>,
+    retries: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawProgram {
+    path: Option<PathBuf>,
+    args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawHttp {
+    address: Option<SocketAddr>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawDashboard {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    public_url: Option<String>,
+}
+
+impl Config {
+    /// Read the config file at `path`, `BERNIE_CONFIG` or `bernie.toml`, whichever is given
+    /// first, then override its settings with environment variables.
+    ///
+    /// `bernie.toml` doesn't have to exist, in which case everything comes from the environment.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| var("BERNIE_CONFIG").map(PathBuf::from));
+
+        let mut raw = match path {
+            Some(path) => RawConfig::read(&path)?,
+            None if Path::new(DEFAULT_PATH).is_file() => RawConfig::read(Path::new(DEFAULT_PATH))?,
+            None => RawConfig::default(),
+        };
+        raw.override_from_env()?;
+
+        raw.resolve()
+    }
+
+    /// Get the token to connect to Discord with.
+    pub fn discord_token(&self) -> Result<&str, Error> {
+        self.discord_token
+            .as_deref()
+            .ok_or_else(|| anyhow!("Missing setting: `discord_token` (or DISCORD_TOKEN)."))
+    }
+}
+
+impl RawConfig {
+    fn read(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+
+        toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", path.display()))
+    }
+
+    fn override_from_env(&mut self) -> Result<(), Error> {
+        set(&mut self.discord_token, "DISCORD_TOKEN", Ok)?;
+        set(&mut self.database_url, "DATABASE_URL", Ok)?;
+        set(&mut self.log_format, "LOG_FORMAT", choice)?;
+
+        let storage = &mut self.storage;
+        set(&mut storage.dir, "STORAGE_DIR", path)?;
+        set(&mut storage.check, "STORAGE_CHECK", choice)?;
+        set(&mut storage.backend, "STORAGE_BACKEND", choice)?;
+
+        let s3 = &mut self.storage.s3;
+        set(&mut s3.bucket, "S3_BUCKET", Ok)?;
+        set(&mut s3.region, "S3_REGION", Ok)?;
+        set(&mut s3.endpoint, "S3_ENDPOINT", Ok)?;
+        set(&mut s3.access_key, "S3_ACCESS_KEY", Ok)?;
+        set(&mut s3.secret_key, "S3_SECRET_KEY", Ok)?;
+
+        let limits = &mut self.limits;
+        set(&mut limits.max_sound_seconds, "MAX_SOUND_SECONDS", number)?;
+        set(&mut limits.max_sound_mb, "MAX_SOUND_MB", number)?;
+        set(&mut limits.max_guild_mb, "MAX_GUILD_MB", number)?;
+        set(&mut limits.max_guild_sounds, "MAX_GUILD_SOUNDS", number)?;
+
+        let cooldowns = &mut self.cooldowns;
+        set(&mut cooldowns.plays_per_user, "PLAYS_PER_USER", number)?;
+        set(
+            &mut cooldowns.window_seconds,
+            "COOLDOWN_WINDOW_SECONDS",
+            number,
+        )?;
+        set(
+            &mut cooldowns.sound_seconds,
+            "SOUND_COOLDOWN_SECONDS",
+            number,
+        )?;
+        set(&mut cooldowns.max_tracks, "MAX_TRACKS", number)?;
+        set(&mut cooldowns.exempt_roles, "COOLDOWN_EXEMPT_ROLES", ids)?;
+
+        let downloads = &mut self.downloads;
+        set(
+            &mut downloads.timeout_seconds,
+            "DOWNLOAD_TIMEOUT_SECONDS",
+            number,
+        )?;
+        set(&mut downloads.retries, "DOWNLOAD_RETRIES", number)?;
+
+        set(&mut self.yt_dlp.path, "YTDLP_PATH", path)?;
+        set(&mut self.yt_dlp.args, "YTDLP_ARGS", args)?;
+        set(&mut self.ffprobe.path, "FFPROBE_PATH", path)?;
+        set(&mut self.ffprobe.args, "FFPROBE_ARGS", args)?;
+
+        set(&mut self.http.address, "HTTP_ADDRESS", address)?;
+
+        let dashboard = &mut self.dashboard;
+        set(&mut dashboard.client_id, "DISCORD_CLIENT_ID", Ok)?;
+        set(&mut dashboard.client_secret, "DISCORD_CLIENT_SECRET", Ok)?;
+        set(&mut dashboard.public_url, "PUBLIC_URL", Ok)?;
+
+        Ok(())
+    }
+
+    /// Fill in defaults and make sure nothing required is missing.
+    fn resolve(self) -> Result<Config, Error> {
+        let mut missing = vec![];
+
+        let database_url = required(
+            self.database_url,
+            "database_url",
+            "DATABASE_URL",
+            &mut missing,
+        );
+        let storage_dir = required(self.storage.dir, "storage.dir", "STORAGE_DIR", &mut missing);
+        let store = match self.storage.backend.unwrap_or(StorageBackend::Local) {
+            StorageBackend::Local => Some(StoreConfig::Local),
+            StorageBackend::S3 => {
+                let s3 = self.storage.s3;
+                let bucket = required(s3.bucket, "storage.s3.bucket", "S3_BUCKET", &mut missing);
+                let access_key = required(
+                    s3.access_key,
+                    "storage.s3.access_key",
+                    "S3_ACCESS_KEY",
+                    &mut missing,
+                );
+                let secret_key = required(
+                    s3.secret_key,
+                    "storage.s3.secret_key",
+                    "S3_SECRET_KEY",
+                    &mut missing,
+                );
+
+                match (bucket, access_key, secret_key) {
+                    (Some(bucket), Some(access_key), Some(secret_key)) => {
+                        Some(StoreConfig::S3(S3Config {
+                            bucket,
+                            region: s3.region.unwrap_or_else(|| "us-east-1".to_owned()),
+                            endpoint: s3.endpoint,
+                            access_key,
+                            secret_key,
+                        }))
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        // The dashboard is optional, but once any of it is set, all of it has to be.
+        let dashboard = self.dashboard;
+        let dashboard = if dashboard.client_id.is_none()
+            && dashboard.client_secret.is_none()
+            && dashboard.public_url.is_none()
+        {
+            Some(None)
+        } else {
+            let client_id = required(
+                dashboard.client_id,
+                "dashboard.client_id",
+                "DISCORD_CLIENT_ID",
+                &mut missing,
+            );
+            let client_secret = required(
+                dashboard.client_secret,
+                "dashboard.client_secret",
+                "DISCORD_CLIENT_SECRET",
+                &mut missing,
+            );
+            let public_url = required(
+                dashboard.public_url,
+                "dashboard.public_url",
+                "PUBLIC_URL",
+                &mut missing,
+            );
+
+            match (client_id, client_secret, public_url) {
+                (Some(client_id), Some(client_secret), Some(public_url)) => {
+                    Some(Some(DashboardConfig {
+                        client_id,
+                        client_secret,
+                        public_url: public_url.trim_end_matches('/').to_owned(),
+                    }))
+                }
+                _ => None,
+            }
+        };
+
+        let (database_url, storage_dir, store, dashboard) =
+            match (database_url, storage_dir, store, dashboard) {
+                (Some(database_url), Some(storage_dir), Some(store), Some(dashboard)) => {
+                    (database_url, storage_dir, store, dashboard)
+                }
+                _ => bail!("Missing settings: {}.", missing.join(", ")),
+            };
+
+        if dashboard.is_some() && self.http.address.is_none() {
+            bail!("The dashboard needs `http.address` (or HTTP_ADDRESS) to be served on.");
+        }
+
+        // limits are opt in, so upgrading doesn't suddenly hold existing sounds to them.
+        let limits = Limits::from_settings(
+            self.limits.max_sound_seconds.unwrap_or_default(),
+            self.limits.max_sound_mb.unwrap_or_default(),
+            self.limits.max_guild_mb.unwrap_or_default(),
+            self.limits.max_guild_sounds.unwrap_or_default(),
+        )
+        .context("Invalid limits")?;
+
+        // like limits, cooldowns are opt in.
+        let cooldowns = self.cooldowns.resolve().context("Invalid cooldowns")?;
+
+        let download_timeout = match self.downloads.timeout_seconds.unwrap_or(120) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        };
+
+        Ok(Config {
+            discord_token: self.discord_token,
+            database_url,
+            storage_dir,
+            storage_check: self.storage.check.unwrap_or(StartupCheck::Off),
+            store,
+            limits,
+            cooldowns,
+            download_timeout,
+            download_retries: self.downloads.retries.unwrap_or(3),
+            yt_dlp: self.yt_dlp.resolve("yt-dlp"),
+            ffprobe: self.ffprobe.resolve("ffprobe"),
+            http_address: self.http.address,
+            dashboard,
+            log_format: self.log_format.unwrap_or_default(),
+        })
+    }
+}
+
+impl RawCooldowns {
+    /// Fill in the defaults: nothing is limited, and plays per user are counted over 30 seconds.
+    /// 0 plays per user or seconds of cooldown on sounds turns those off.
+    fn resolve(self) -> Result<CooldownSettings, Error> {
+        if self.window_seconds == Some(0) {
+            bail!("`cooldowns.window_seconds` (or COOLDOWN_WINDOW_SECONDS) can't be 0.");
+        }
+        if self.max_tracks == Some(0) {
+            bail!("`cooldowns.max_tracks` (or MAX_TRACKS) can't be 0; leave it out for no limit.");
+        }
+        let nonzero = |value: u64| (value > 0).then(|| Duration::from_secs(value));
+
+        Ok(CooldownSettings {
+            plays_per_user: self.plays_per_user.filter(|&plays| plays > 0),
+            window: Duration::from_secs(self.window_seconds.unwrap_or(30)),
+            sound_cooldown: nonzero(self.sound_seconds.unwrap_or(0)),
+            max_tracks: self.max_tracks,
+            exempt_roles: self
+                .exempt_roles
+                .unwrap_or_default()
+                .into_iter()
+                .map(RoleId)
+                .collect(),
+        })
+    }
+}
+
+impl RawProgram {
+    /// Fill in the defaults: `program` on the `PATH`, without extra arguments.
+    fn resolve(self, program: &str) -> Program {
+        Program {
+            path: self
+                .path
+                .map_or_else(|| program.into(), PathBuf::into_os_string),
+            args: self.args.unwrap_or_default(),
+        }
+    }
+}
+
+/// Get an environment variable, treating empty ones as unset.
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Override `setting` with the environment variable `name`, if it's set.
+fn set<T>(
+    setting: &mut Option<T>,
+    name: &str,
+    parse: impl FnOnce(String) -> Result<T, Error>,
+) -> Result<(), Error> {
+    if let Some(value) = var(name) {
+        *setting = Some(parse(value).with_context(|| format!("Couldn't read {name}"))?);
+    }
+
+    Ok(())
+}
+
+fn choice<T: FromStr<Err = Error>>(value: String) -> Result<T, Error> {
+    value.parse()
+}
+
+fn path(value: String) -> Result<PathBuf, Error> {
+    Ok(value.into())
+}
+
+fn number<T: FromStr>(value: String) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Expected a whole number, got {value:?}."))
+}
+
+fn address(value: String) -> Result<SocketAddr, Error> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Expected an address like `0.0.0.0:8080`, got {value:?}."))
+}
+
+/// Split ids on whitespace.
+fn ids(value: String) -> Result<Vec<u64>, Error> {
+    value
+        .split_whitespace()
+        .map(|id| number(id.to_owned()))
+        .collect()
+}
+
+/// Split extra arguments on whitespace.
+fn args(value: String) -> Result<Vec<String>, Error> {
+    Ok(value.split_whitespace().map(str::to_owned).collect())
+}
+
+/// Note `setting` as missing if it isn't set.
+fn required<T>(
+    setting: Option<T>,
+    key: &str,
+    variable: &str,
+    missing: &mut Vec<String>,
+) -> Option<T> {
+    if setting.is_none() {
+        missing.push(format!("`{key}` (or {variable})"));
+    }
+
+    setting
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read a config file's contents, leaving the environment out of it.
+    fn parse(contents: &str) -> Result<Config, Error> {
+        toml::from_str::<RawConfig>(contents)?.resolve()
+    }
+
+    const MINIMAL: &str = r#"
+        database_url = "postgres://localhost/bernie"
+
+        [storage]
+        dir = "/var/lib/bernie"
+    "#;
+
+    #[test]
+    fn defaults_fill_in_everything_optional() {
+        let config = parse(MINIMAL).unwrap();
+
+        assert_eq!(config.database_url, "postgres://localhost/bernie");
+        assert_eq!(config.storage_dir, Path::new("/var/lib/bernie"));
+        assert!(matches!(config.store, StoreConfig::Local));
+        assert_eq!(config.storage_check, StartupCheck::Off);
+        assert_eq!(
+            config.limits,
+            Limits {
+                max_length: None,
+                max_size: None,
+                max_total_size: None,
+                max_sounds: None,
+            }
+        );
+        assert_eq!(
+            config.cooldowns,
+            CooldownSettings {
+                plays_per_user: None,
+                window: Duration::from_secs(30),
+                sound_cooldown: None,
+                max_tracks: None,
+                exempt_roles: vec![],
+            }
+        );
+        assert_eq!(config.download_timeout, Some(Duration::from_secs(120)));
+        assert_eq!(config.download_retries, 3);
+        assert_eq!(config.yt_dlp.path, "yt-dlp");
+        assert!(config.http_address.is_none());
+        assert!(config.dashboard.is_none());
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert!(config.discord_token().is_err());
+    }
+
+    #[test]
+    fn settings_are_read_from_the_file() {
+        let config = parse(&format!(
+            r#"
+            {MINIMAL}
+
+            [limits]
+            max_sound_seconds = 10
+            max_guild_sounds = 50
+
+            [cooldowns]
+            plays_per_user = 0
+            sound_seconds = 5
+            exempt_roles = [1000]
+
+            [downloads]
+            timeout_seconds = 0
+            "#
+        ))
+        .unwrap();
+
+        assert_eq!(config.limits.max_length, Some(10_000));
+        assert_eq!(config.limits.max_sounds, Some(50));
+        assert_eq!(config.limits.max_size, None);
+        assert_eq!(config.cooldowns.plays_per_user, None);
+        assert_eq!(
+            config.cooldowns.sound_cooldown,
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(config.cooldowns.exempt_roles, [RoleId(1000)]);
+        assert_eq!(config.download_timeout, None);
+    }
+
+    #[test]
+    fn missing_and_unknown_settings_are_rejected() {
+        let error = parse("").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Missing settings: `database_url` (or DATABASE_URL), `storage.dir` (or STORAGE_DIR)."
+        );
+
+        let error = parse(&format!("{MINIMAL}\nbackend = \"s3\"")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Missing settings: `storage.s3.bucket` (or S3_BUCKET), `storage.s3.access_key` \
+            (or S3_ACCESS_KEY), `storage.s3.secret_key` (or S3_SECRET_KEY)."
+        );
+
+        assert!(toml::from_str::<RawConfig>("[limits]\nmax_sounds = 5").is_err());
+    }
+
+    #[test]
+    fn cooldowns_that_would_stop_every_play_are_rejected() {
+        let error = parse(&format!("{MINIMAL}\n[cooldowns]\nwindow_seconds = 0")).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid cooldowns: `cooldowns.window_seconds` (or COOLDOWN_WINDOW_SECONDS) can't be 0."
+        );
+
+        let error = parse(&format!("{MINIMAL}\n[cooldowns]\nmax_tracks = 0")).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid cooldowns: `cooldowns.max_tracks` (or MAX_TRACKS) can't be 0; leave it out \
+            for no limit."
+        );
+    }
+
+    #[test]
+    fn the_dashboard_needs_all_of_its_settings_and_an_address() {
+        let error = parse(&format!("{MINIMAL}\n[dashboard]\nclient_id = \"1\"")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Missing settings: `dashboard.client_secret` (or DISCORD_CLIENT_SECRET), \
+            `dashboard.public_url` (or PUBLIC_URL)."
+        );
+
+        let dashboard = r#"
+            [dashboard]
+            client_id = "1"
+            client_secret = "secret"
+            public_url = "https://bernie.example.com/"
+        "#;
+        let error = parse(&format!("{MINIMAL}{dashboard}")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The dashboard needs `http.address` (or HTTP_ADDRESS) to be served on."
+        );
+
+        let config = parse(&format!(
+            "{MINIMAL}{dashboard}\n[http]\naddress = \"127.0.0.1:8080\""
+        ))
+        .unwrap();
+        assert_eq!(
+            config.dashboard.unwrap().public_url,
+            "https://bernie.example.com"
+        );
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file() {
+        // no other test reads these variables, so setting them doesn't affect anything else.
+        std::env::set_var("MAX_GUILD_SOUNDS", "10");
+        std::env::set_var("PLAYS_PER_USER", "0");
+        std::env::set_var("COOLDOWN_EXEMPT_ROLES", "1000 1001");
+        std::env::set_var("YTDLP_ARGS", "--proxy socks5://localhost");
+
+        let mut raw: RawConfig =
+            toml::from_str(&format!("{MINIMAL}\n[limits]\nmax_guild_sounds = 5")).unwrap();
+        let overridden = raw.override_from_env();
+
+        for name in [
+            "MAX_GUILD_SOUNDS",
+            "PLAYS_PER_USER",
+            "COOLDOWN_EXEMPT_ROLES",
+            "YTDLP_ARGS",
+        ] {
+            std::env::remove_var(name);
+        }
+        overridden.unwrap();
+        let config = raw.resolve().unwrap();
+
+        assert_eq!(config.limits.max_sounds, Some(10));
+        assert_eq!(config.cooldowns.plays_per_user, None);
+        assert_eq!(config.cooldowns.exempt_roles, [RoleId(1000), RoleId(1001)]);
+        assert_eq!(config.yt_dlp.args, ["--proxy", "socks5://localhost"]);
+    }
+}
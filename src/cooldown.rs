@@ -0,0 +1,243 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use poise::serenity_prelude::{GuildId, RoleId, UserId};
+
+use crate::Error;
+
+/// How often sounds can be played. `None` means there's no limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CooldownSettings {
+    /// Most sounds someone can play in a guild within `window`.
+    pub plays_per_user: Option<u32>,
+    pub window: Duration,
+    /// How long a sound has to wait before it can be played again.
+    pub sound_cooldown: Option<Duration>,
+    /// Most sounds that can play at once in a guild. Unlike cooldowns, this applies to everyone.
+    pub max_tracks: Option<usize>,
+    /// Members with any of these roles aren't held to cooldowns.
+    pub exempt_roles: Vec<RoleId>,
+}
+
+impl CooldownSettings {
+    /// Settings that don't limit anything.
+    pub fn none() -> Self {
+        Self {
+            plays_per_user: None,
+            window: Duration::ZERO,
+            sound_cooldown: None,
+            max_tracks: None,
+            exempt_roles: vec![],
+        }
+    }
+
+    pub fn is_exempt(&self, roles: &[RoleId]) -> bool {
+        roles.iter().any(|role| self.exempt_roles.contains(role))
+    }
+}
+
+/// Keeps track of recent plays, to hold people to [`CooldownSettings`].
+///
+/// Plays are only kept in memory, so cooldowns start over when Bernie restarts.
+#[derive(Debug)]
+pub struct Cooldowns {
+    settings: CooldownSettings,
+    state: Mutex<CooldownState>,
+}
+
+/// A play counted by [`Cooldowns::start`], which can be taken back with [`Cooldowns::undo`] if
+/// the sound doesn't end up playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub struct Started {
+    guild_id: GuildId,
+    player_id: UserId,
+    sound_id: i32,
+    /// When the play was counted, or `None` if the player is exempt and it wasn't.
+    at: Option<Instant>,
+    /// When the sound was played before this.
+    sound_played_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct CooldownState {
+    /// When people played sounds within the window, oldest first.
+    plays: HashMap<(GuildId, UserId), VecDeque<Instant>>,
+    /// When sounds were last played, by id.
+    sounds: HashMap<i32, Instant>,
+}
+
+impl Cooldowns {
+    pub fn new(settings: CooldownSettings) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(CooldownState::default()),
+        }
+    }
+
+    pub fn settings(&self) -> &CooldownSettings {
+        &self.settings
+    }
+
+    /// Count a play of the sound with the id `sound_id` by `player_id`, or fail with how long
+    /// they have to wait if it's on cooldown. Members with exempt `roles` can always play.
+    pub fn start(
+        &self,
+        guild_id: GuildId,
+        player_id: UserId,
+        roles: &[RoleId],
+        sound_id: i32,
+        name: &str,
+    ) -> Result<Started, Error> {
+        let mut started = Started {
+            guild_id,
+            player_id,
+            sound_id,
+            at: None,
+            sound_played_at: None,
+        };
+        if self.settings.is_exempt(roles) {
+            return Ok(started);
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        self.forget_expired(&mut state, now);
+
+        if let (Some(max_plays), Some(plays)) = (
+            self.settings.plays_per_user,
+            state.plays.get(&(guild_id, player_id)),
+        ) {
+            if plays.len() >= max_plays as usize {
+                let wait = self.settings.window - now.duration_since(plays[0]);
+                bail!("You're on cooldown for {}.", format_wait(wait));
+            }
+        }
+
+        if let (Some(cooldown), Some(&played_at)) =
+            (self.settings.sound_cooldown, state.sounds.get(&sound_id))
+        {
+            let since = now.duration_since(played_at);
+            if since < cooldown {
+                bail!(
+                    "`{name}` is on cooldown for {}.",
+                    format_wait(cooldown - since)
+                );
+            }
+        }
+
+        if self.settings.plays_per_user.is_some() {
+            state
+                .plays
+                .entry((guild_id, player_id))
+                .or_default()
+                .push_back(now);
+        }
+        if self.settings.sound_cooldown.is_some() {
+            started.sound_played_at = state.sounds.insert(sound_id, now);
+        }
+
+        started.at = Some(now);
+        Ok(started)
+    }
+
+    /// Take back a play counted by [`start`](Self::start), because the sound couldn't be played.
+    pub fn undo(&self, started: Started) {
+        let at = match started.at {
+            Some(at) => at,
+            None => return,
+        };
+        let mut state = self.state.lock().unwrap();
+
+        let key = (started.guild_id, started.player_id);
+        if let Some(plays) = state.plays.get_mut(&key) {
+            plays.retain(|&played_at| played_at != at);
+            if plays.is_empty() {
+                state.plays.remove(&key);
+            }
+        }
+        // unless the sound has been played again since, it's back to its previous play.
+        if state.sounds.get(&started.sound_id) == Some(&at) {
+            match started.sound_played_at {
+                Some(played_at) => state.sounds.insert(started.sound_id, played_at),
+                None => state.sounds.remove(&started.sound_id),
+            };
+        }
+    }
+
+    /// Forget plays that don't count towards any cooldown anymore, so they aren't kept forever.
+    fn forget_expired(&self, state: &mut CooldownState, now: Instant) {
+        let window = self.settings.window;
+        state.plays.retain(|_, plays| {
+            while let Some(&oldest) = plays.front() {
+                if now.duration_since(oldest) < window {
+                    break;
+                }
+                plays.pop_front();
+            }
+            !plays.is_empty()
+        });
+
+        let cooldown = self.settings.sound_cooldown.unwrap_or_default();
+        state
+            .sounds
+            .retain(|_, &mut played_at| now.duration_since(played_at) < cooldown);
+    }
+}
+
+/// Format a wait in whole seconds, rounding up so nobody's told to wait 0s.
+fn format_wait(wait: Duration) -> String {
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    format!("{}s", seconds.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_plays_are_forgotten() {
+        let cooldowns = Cooldowns::new(CooldownSettings {
+            plays_per_user: Some(5),
+            window: Duration::from_millis(50),
+            sound_cooldown: Some(Duration::from_millis(50)),
+            ..CooldownSettings::none()
+        });
+        let guild_id = GuildId(1);
+        let start = |player_id: u64, sound_id: i32| {
+            cooldowns
+                .start(guild_id, UserId(player_id), &[], sound_id, "bonk")
+                .unwrap()
+        };
+
+        let _ = start(1, 1);
+        let _ = start(2, 2);
+        std::thread::sleep(Duration::from_millis(80));
+        let _ = start(3, 3);
+
+        let state = cooldowns.state.lock().unwrap();
+        assert_eq!(
+            state.plays.keys().collect::<Vec<_>>(),
+            [&(guild_id, UserId(3))]
+        );
+        assert_eq!(state.sounds.keys().collect::<Vec<_>>(), [&3]);
+    }
+
+    #[test]
+    fn undone_plays_dont_count() {
+        let cooldowns = Cooldowns::new(CooldownSettings {
+            plays_per_user: Some(1),
+            window: Duration::from_secs(60),
+            sound_cooldown: Some(Duration::from_secs(60)),
+            ..CooldownSettings::none()
+        });
+        let start = || cooldowns.start(GuildId(1), UserId(1), &[], 1, "bonk");
+
+        let started = start().unwrap();
+        assert!(start().is_err());
+        cooldowns.undo(started);
+        let _ = start().unwrap();
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+
+use super::{LegacyFile, NewPlayback, NewSound, Playback, PoolStats, Repository, Sound};
+use crate::media::SoundMetadata;
+use crate::quota::{GuildLimits, Usage};
+use crate::sound_name::{name_key, SoundName};
+use crate::Error;
+
+/// Keeps everything in memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    state: Mutex<State>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    guilds: HashMap<GuildId, GuildLimits>,
+    sounds: Vec<StoredSound>,
+    playbacks: Vec<StoredPlayback>,
+    /// Hashes of API tokens by who they belong to.
+    api_tokens: HashMap<UserId, String>,
+    last_id: i32,
+}
+
+#[derive(Debug, Clone)]
+struct StoredSound {
+    sound: Sound,
+    file_size: Option<i64>,
+    metadata: Option<SoundMetadata>,
+    tags: Vec<String>,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredPlayback {
+    id: i32,
+    sound_id: i32,
+    player_id: UserId,
+    created_at: DateTime<Utc>,
+    stopper_id: Option<UserId>,
+    stopped_at: Option<DateTime<Utc>>,
+    stop_reason: Option<String>,
+    paused_at: Option<DateTime<Utc>>,
+    paused_ms: i64,
+}
+
+impl StoredPlayback {
+    /// Add the pause that's going on, if any, to how long the playback was paused.
+    fn end_pause(&mut self, now: DateTime<Utc>) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_ms += (now - paused_at).num_milliseconds();
+        }
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn live_sounds(&self, guild_id: GuildId) -> impl Iterator<Item = &StoredSound> {
+        self.sounds.iter().filter(move |stored| {
+            stored.sound.guild_id == guild_id.0 as i64 && stored.deleted_at.is_none()
+        })
+    }
+
+    fn find(&mut self, guild_id: GuildId, name: &SoundName) -> Option<&mut StoredSound> {
+        self.sounds.iter_mut().find(|stored| {
+            stored.sound.guild_id == guild_id.0 as i64
+                && stored.deleted_at.is_none()
+                && name_key(&stored.sound.name) == name.key()
+        })
+    }
+
+    fn insert_sound(&mut self, sound: &NewSound) -> Result<i32, Error> {
+        if self.find(sound.guild_id, &sound.name).is_some() {
+            bail!("A sound named `{}` already exists.", sound.name);
+        }
+
+        let id = self.next_id();
+        self.sounds.push(StoredSound {
+            sound: Sound {
+                id,
+                guild_id: sound.guild_id.0 as i64,
+                name: sound.name.to_string(),
+                source: sound.source.clone(),
+                uploader_id: sound.uploader_id.0 as i64,
+                length: sound.length,
+                created_at: sound.created_at.unwrap_or_else(Utc::now),
+                file_hash: Some(sound.file_hash.clone()),
+                volume: sound.volume,
+            },
+            file_size: Some(sound.file_size),
+            metadata: sound.metadata.clone(),
+            tags: vec![],
+            deleted_at: None,
+        });
+
+        Ok(id)
+    }
+
+    fn remove_sound(&mut self, guild_id: GuildId, name: &SoundName) -> bool {
+        match self.find(guild_id, name) {
+            Some(stored) => {
+                stored.deleted_at = Some(Utc::now());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sound_mut(&mut self, sound_id: i32) -> Option<&mut StoredSound> {
+        self.sounds
+            .iter_mut()
+            .find(|stored| stored.sound.id == sound_id)
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn migrate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn revert_migration(&self) -> Result<Option<(i64, String)>, Error> {
+        Ok(None)
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn close(&self) {}
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    async fn ensure_guild(&self, guild_id: GuildId) -> Result<(), Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .guilds
+            .entry(guild_id)
+            .or_default();
+
+        Ok(())
+    }
+
+    async fn guild_limits(&self, guild_id: GuildId) -> Result<GuildLimits, Error> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state.guilds.get(&guild_id).copied().unwrap_or_default())
+    }
+
+    async fn set_guild_limits(&self, guild_id: GuildId, limits: &GuildLimits) -> Result<(), Error> {
+        self.state.lock().unwrap().guilds.insert(guild_id, *limits);
+
+        Ok(())
+    }
+
+    async fn usage(&self, guild_id: GuildId) -> Result<Usage, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut sounds = 0;
+        let mut files = HashMap::new();
+        for stored in state.live_sounds(guild_id) {
+            sounds += 1;
+            if let (Some(hash), Some(size)) = (&stored.sound.file_hash, stored.file_size) {
+                files.insert(hash, size);
+            }
+        }
+
+        Ok(Usage {
+            sounds,
+            total_size: files.values().sum(),
+        })
+    }
+
+    async fn guild_sounds(&self, guild_id: GuildId) -> Result<Vec<Sound>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut sounds: Vec<Sound> = state
+            .live_sounds(guild_id)
+            .map(|stored| stored.sound.clone())
+            .collect();
+        sounds.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(sounds)
+    }
+
+    async fn all_sounds(&self) -> Result<Vec<Sound>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut sounds: Vec<Sound> = state
+            .sounds
+            .iter()
+            .filter(|stored| stored.deleted_at.is_none())
+            .map(|stored| stored.sound.clone())
+            .collect();
+        sounds.sort_by(|a, b| (a.guild_id, &a.name).cmp(&(b.guild_id, &b.name)));
+
+        Ok(sounds)
+    }
+
+    async fn legacy_files(&self) -> Result<Vec<LegacyFile>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut files: Vec<LegacyFile> = state
+            .sounds
+            .iter()
+            .filter(|stored| stored.deleted_at.is_none() && stored.sound.file_hash.is_none())
+            .map(|stored| LegacyFile {
+                sound_id: stored.sound.id,
+                guild_id: stored.sound.guild_id,
+                file_name: stored.sound.name.clone(),
+            })
+            .collect();
+        files.sort_by(|a, b| (a.guild_id, &a.file_name).cmp(&(b.guild_id, &b.file_name)));
+
+        Ok(files)
+    }
+
+    async fn autocomplete_sound(
+        &self,
+        guild_id: GuildId,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let prefix = name_key(prefix);
+        let mut names: Vec<String> = state
+            .live_sounds(guild_id)
+            .filter(|stored| name_key(&stored.sound.name).starts_with(&prefix))
+            .map(|stored| stored.sound.name.clone())
+            .collect();
+        names.sort();
+        names.truncate(25);
+
+        Ok(names)
+    }
+
+    async fn find_sound(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Option<Sound>, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state
+            .find(guild_id, name)
+            .map(|stored| stored.sound.clone()))
+    }
+
+    async fn sound_ids_named(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Vec<i32>, Error> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .sounds
+            .iter()
+            .filter(|stored| {
+                stored.sound.guild_id == guild_id.0 as i64
+                    && name_key(&stored.sound.name) == name.key()
+            })
+            .map(|stored| stored.sound.id)
+            .collect())
+    }
+
+    async fn add_sound(&self, sound: &NewSound) -> Result<i32, Error> {
+        self.state.lock().unwrap().insert_sound(sound)
+    }
+
+    async fn rename_sound(
+        &self,
+        guild_id: GuildId,
+        old_name: &SoundName,
+        new_name: &SoundName,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let old_id = match state.find(guild_id, old_name) {
+            Some(stored) => stored.sound.id,
+            None => return Ok(false),
+        };
+        if let Some(stored) = state.find(guild_id, new_name) {
+            if stored.sound.id != old_id {
+                bail!("A sound named `{new_name}` already exists.");
+            }
+        }
+
+        state.sound_mut(old_id).unwrap().sound.name = new_name.to_string();
+
+        Ok(true)
+    }
+
+    async fn remove_sound(&self, guild_id: GuildId, name: &SoundName) -> Result<bool, Error> {
+        Ok(self.state.lock().unwrap().remove_sound(guild_id, name))
+    }
+
+    async fn set_sound_length(&self, sound_id: i32, length: i32) -> Result<(), Error> {
+        if let Some(stored) = self.state.lock().unwrap().sound_mut(sound_id) {
+            stored.sound.length = length;
+        }
+
+        Ok(())
+    }
+
+    async fn set_sound_file(
+        &self,
+        sound_id: i32,
+        file_hash: &str,
+        file_size: i64,
+    ) -> Result<(), Error> {
+        if let Some(stored) = self.state.lock().unwrap().sound_mut(sound_id) {
+            stored.sound.file_hash = Some(file_hash.to_owned());
+            stored.file_size = Some(file_size);
+        }
+
+        Ok(())
+    }
+
+    async fn set_sound_volume(&self, sound_id: i32, volume: i32) -> Result<(), Error> {
+        if let Some(stored) = self.state.lock().unwrap().sound_mut(sound_id) {
+            stored.sound.volume = volume;
+        }
+
+        Ok(())
+    }
+
+    async fn sound_metadata(&self, sound_id: i32) -> Result<Option<SoundMetadata>, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state
+            .sound_mut(sound_id)
+            .and_then(|stored| stored.metadata.clone()))
+    }
+
+    async fn set_sound_metadata(
+        &self,
+        sound_id: i32,
+        metadata: &SoundMetadata,
+    ) -> Result<(), Error> {
+        if let Some(stored) = self.state.lock().unwrap().sound_mut(sound_id) {
+            stored.metadata = Some(metadata.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn guild_tags(&self, guild_id: GuildId) -> Result<Vec<(i32, String)>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut tags: Vec<(i32, String)> = state
+            .live_sounds(guild_id)
+            .flat_map(|stored| {
+                stored
+                    .tags
+                    .iter()
+                    .map(move |tag| (stored.sound.id, tag.clone()))
+            })
+            .collect();
+        tags.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(tags)
+    }
+
+    async fn set_sound_tags(&self, sound_id: i32, tags: &[String]) -> Result<(), Error> {
+        if let Some(stored) = self.state.lock().unwrap().sound_mut(sound_id) {
+            stored.tags = tags.to_vec();
+        }
+
+        Ok(())
+    }
+
+    async fn import_sounds(
+        &self,
+        replaced: &[(GuildId, SoundName)],
+        sounds: &[NewSound],
+        playbacks: &[NewPlayback],
+    ) -> Result<Vec<i32>, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        // work on a copy, so nothing changes if anything fails.
+        let mut new_state = state.clone();
+
+        for (guild_id, name) in replaced {
+            new_state.remove_sound(*guild_id, name);
+        }
+
+        let mut sound_ids = vec![];
+        for sound in sounds {
+            new_state.guilds.entry(sound.guild_id).or_default();
+            sound_ids.push(new_state.insert_sound(sound)?);
+        }
+
+        for playback in playbacks {
+            let id = new_state.next_id();
+            new_state.playbacks.push(StoredPlayback {
+                id,
+                sound_id: sound_ids[playback.sound],
+                player_id: playback.player_id,
+                created_at: playback.created_at,
+                stopper_id: playback.stopper_id,
+                stopped_at: playback.stopped_at,
+                stop_reason: None,
+                paused_at: None,
+                paused_ms: 0,
+            });
+        }
+
+        *state = new_state;
+        Ok(sound_ids)
+    }
+
+    async fn referenced_files(&self) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut hashes: Vec<String> = state
+            .sounds
+            .iter()
+            .filter(|stored| stored.deleted_at.is_none())
+            .filter_map(|stored| stored.sound.file_hash.clone())
+            .collect();
+        hashes.sort();
+        hashes.dedup();
+
+        Ok(hashes)
+    }
+
+    async fn files_without_size(&self) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut hashes: Vec<String> = state
+            .sounds
+            .iter()
+            .filter(|stored| stored.file_size.is_none())
+            .filter_map(|stored| stored.sound.file_hash.clone())
+            .collect();
+        hashes.sort();
+        hashes.dedup();
+
+        Ok(hashes)
+    }
+
+    async fn set_file_size(&self, file_hash: &str, file_size: i64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        for stored in &mut state.sounds {
+            if stored.sound.file_hash.as_deref() == Some(file_hash) {
+                stored.file_size = Some(file_size);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn purge_removed(&self, older_than: i32) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let cutoff = Utc::now() - Duration::days(older_than as i64);
+        let (purged, kept): (Vec<StoredSound>, Vec<StoredSound>) =
+            state.sounds.drain(..).partition(
+                |stored| matches!(stored.deleted_at, Some(deleted_at) if deleted_at < cutoff),
+            );
+        state.sounds = kept;
+        state.playbacks.retain(|playback| {
+            !purged
+                .iter()
+                .any(|stored| stored.sound.id == playback.sound_id)
+        });
+
+        Ok(purged.len() as u64)
+    }
+
+    async fn add_playback(&self, sound_id: i32, player_id: UserId) -> Result<i32, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.sound_mut(sound_id).is_none() {
+            bail!("There's no sound with id {sound_id}.");
+        }
+
+        let id = state.next_id();
+        state.playbacks.push(StoredPlayback {
+            id,
+            sound_id,
+            player_id,
+            created_at: Utc::now(),
+            stopper_id: None,
+            stopped_at: None,
+            stop_reason: None,
+            paused_at: None,
+            paused_ms: 0,
+        });
+
+        Ok(id)
+    }
+
+    async fn delete_playback(&self, playback_id: i32) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.playbacks.retain(|stored| stored.id != playback_id);
+
+        Ok(())
+    }
+
+    async fn stop_playbacks(&self, playback_ids: &[i32], stopper_id: UserId) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Utc::now();
+        for playback in &mut state.playbacks {
+            if playback_ids.contains(&playback.id) {
+                playback.stopper_id = Some(stopper_id);
+                playback.stopped_at = Some(now);
+                playback.end_pause(now);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn end_playbacks(&self, playback_ids: &[i32], reason: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Utc::now();
+        for playback in &mut state.playbacks {
+            if playback_ids.contains(&playback.id) {
+                playback.stop_reason = Some(reason.to_owned());
+                playback.stopped_at = Some(now);
+                playback.end_pause(now);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn pause_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Utc::now();
+        for playback in &mut state.playbacks {
+            if playback_ids.contains(&playback.id)
+                && playback.paused_at.is_none()
+                && playback.stopped_at.is_none()
+            {
+                playback.paused_at = Some(now);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn resume_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Utc::now();
+        for playback in &mut state.playbacks {
+            if playback_ids.contains(&playback.id) {
+                playback.end_pause(now);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn history(
+        &self,
+        guild_id: GuildId,
+        name: Option<&SoundName>,
+    ) -> Result<Vec<Playback>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut history: Vec<Playback> = state
+            .playbacks
+            .iter()
+            .filter_map(|playback| {
+                let sound = &state
+                    .sounds
+                    .iter()
+                    .find(|stored| stored.sound.id == playback.sound_id)?
+                    .sound;
+                if sound.guild_id != guild_id.0 as i64 {
+                    return None;
+                }
+                if let Some(name) = name {
+                    if name_key(&sound.name) != name.key() {
+                        return None;
+                    }
+                }
+
+                Some(Playback {
+                    id: playback.id,
+                    name: sound.name.clone(),
+                    player_id: playback.player_id.0 as i64,
+                    created_at: playback.created_at,
+                    stopper_id: playback.stopper_id.map(|id| id.0 as i64),
+                    stopped_at: playback.stopped_at,
+                    stop_reason: playback.stop_reason.clone(),
+                    paused_ms: playback.paused_ms,
+                })
+            })
+            .collect();
+        // ids break ties, since several plays can happen within the clock's resolution.
+        history.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+
+        Ok(history)
+    }
+
+    async fn most_played(
+        &self,
+        guild_id: GuildId,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut plays: Vec<(String, i64)> = state
+            .live_sounds(guild_id)
+            .map(|stored| {
+                let count = state
+                    .playbacks
+                    .iter()
+                    .filter(|playback| playback.sound_id == stored.sound.id)
+                    .count();
+                (stored.sound.name.clone(), count as i64)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        plays.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        plays.truncate(limit as usize);
+
+        Ok(plays)
+    }
+
+    async fn set_api_token(&self, user_id: UserId, token_hash: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.api_tokens.insert(user_id, token_hash.to_owned());
+
+        Ok(())
+    }
+
+    async fn remove_api_token(&self, user_id: UserId) -> Result<bool, Error> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .api_tokens
+            .remove(&user_id)
+            .is_some())
+    }
+
+    async fn api_token_user(&self, token_hash: &str) -> Result<Option<UserId>, Error> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .api_tokens
+            .iter()
+            .find(|(_, hash)| *hash == token_hash)
+            .map(|(user_id, _)| *user_id))
+    }
+}
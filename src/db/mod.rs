@@ -0,0 +1,336 @@
+use std::fmt::Debug;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use sqlx::migrate::{Migrate, Migrator};
+
+use crate::media::SoundMetadata;
+use crate::quota::{GuildLimits, Usage};
+use crate::sound_name::SoundName;
+use crate::Error;
+
+#[cfg(test)]
+mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(test)]
+pub use self::memory::MemoryRepository;
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresRepository;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteRepository;
+
+#[cfg(not(any(feature = "postgres", feature = "sqlite")))]
+compile_error!("At least one of the `postgres` and `sqlite` features must be enabled.");
+
+/// Volume of sounds that haven't been made louder or quieter, in percent of their files' own.
+pub const DEFAULT_VOLUME: i32 = 100;
+
+/// A sound as stored in the database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Sound {
+    pub id: i32,
+    pub guild_id: i64,
+    pub name: String,
+    pub source: String,
+    pub uploader_id: i64,
+    /// Length of the sound in milliseconds.
+    pub length: i32,
+    pub created_at: DateTime<Utc>,
+    /// Null until the sound's file has been stored.
+    pub file_hash: Option<String>,
+    /// How loud the sound plays, in percent of its file's own volume.
+    pub volume: i32,
+}
+
+/// A sound to add to the database.
+#[derive(Debug, Clone)]
+pub struct NewSound {
+    pub guild_id: GuildId,
+    pub name: SoundName,
+    pub source: String,
+    pub uploader_id: UserId,
+    pub length: i32,
+    /// When the sound was added, if not now.
+    pub created_at: Option<DateTime<Utc>>,
+    pub file_hash: String,
+    pub file_size: i64,
+    pub metadata: Option<SoundMetadata>,
+    /// How loud the sound plays, in percent of its file's own volume.
+    pub volume: i32,
+}
+
+/// A sound whose file is still stored under its name, from before files were stored by hash.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LegacyFile {
+    pub sound_id: i32,
+    pub guild_id: i64,
+    /// What the file is called, which is the sound's name from before names had to follow the
+    /// rules of [`SoundName`].
+    pub file_name: String,
+}
+
+/// A play of a sound, with the sound's name.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Playback {
+    pub id: i32,
+    pub name: String,
+    pub player_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub stopper_id: Option<i64>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    /// Why the playback was stopped, if nobody stopped it.
+    pub stop_reason: Option<String>,
+    /// How long the playback was paused in milliseconds, not counting a pause that's still going.
+    pub paused_ms: i64,
+}
+
+impl Playback {
+    /// How long the sound played before it was stopped, not counting pauses.
+    pub fn duration(&self) -> Option<Duration> {
+        self.stopped_at
+            .map(|stopped_at| stopped_at - self.created_at - Duration::milliseconds(self.paused_ms))
+    }
+}
+
+/// A past play of a sound being imported with [`Repository::import_sounds`].
+#[derive(Debug, Clone)]
+pub struct NewPlayback {
+    /// Index of the played sound in the sounds being imported.
+    pub sound: usize,
+    pub player_id: UserId,
+    pub created_at: DateTime<Utc>,
+    pub stopper_id: Option<UserId>,
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+/// How many connections a database pool holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub connections: u32,
+    pub idle: usize,
+}
+
+/// Everything Bernie keeps in its database.
+///
+/// Names are always compared regardless of case, and only live sounds (ones that haven't been
+/// removed) are considered unless stated otherwise.
+#[async_trait]
+pub trait Repository: Debug + Send + Sync {
+    /// Apply all pending migrations.
+    async fn migrate(&self) -> Result<(), Error>;
+
+    /// Revert the latest applied migration, returning its version and description.
+    async fn revert_migration(&self) -> Result<Option<(i64, String)>, Error>;
+
+    /// Make sure the database can be queried.
+    async fn ping(&self) -> Result<(), Error>;
+
+    /// Get the state of the connection pool, if there is one.
+    fn pool_stats(&self) -> Option<PoolStats>;
+
+    /// Close every connection, waiting for queries in progress to finish.
+    async fn close(&self);
+
+    /// Create `guild_id` if it doesn't exist yet.
+    async fn ensure_guild(&self, guild_id: GuildId) -> Result<(), Error>;
+
+    async fn guild_limits(&self, guild_id: GuildId) -> Result<GuildLimits, Error>;
+
+    /// Replace the limits of `guild_id`, creating it if needed.
+    async fn set_guild_limits(&self, guild_id: GuildId, limits: &GuildLimits) -> Result<(), Error>;
+
+    async fn usage(&self, guild_id: GuildId) -> Result<Usage, Error>;
+
+    /// Get every sound in `guild_id`, ordered by name.
+    async fn guild_sounds(&self, guild_id: GuildId) -> Result<Vec<Sound>, Error>;
+
+    /// Get every sound, ordered by guild and name.
+    async fn all_sounds(&self) -> Result<Vec<Sound>, Error>;
+
+    /// Get every sound whose file hasn't been stored yet, ordered by guild and file name.
+    async fn legacy_files(&self) -> Result<Vec<LegacyFile>, Error>;
+
+    /// Get up to 25 names of sounds in `guild_id` starting with `prefix`, ordered by name.
+    async fn autocomplete_sound(
+        &self,
+        guild_id: GuildId,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error>;
+
+    async fn find_sound(&self, guild_id: GuildId, name: &SoundName)
+        -> Result<Option<Sound>, Error>;
+
+    /// Get the ids of the sounds in `guild_id` named `name`, removed ones included.
+    async fn sound_ids_named(&self, guild_id: GuildId, name: &SoundName)
+        -> Result<Vec<i32>, Error>;
+
+    /// Add a sound, returning its id. Fails with a readable error if the name is taken.
+    async fn add_sound(&self, sound: &NewSound) -> Result<i32, Error>;
+
+    /// Rename a sound, returning whether it exists. Fails with a readable error if `new_name` is
+    /// taken.
+    async fn rename_sound(
+        &self,
+        guild_id: GuildId,
+        old_name: &SoundName,
+        new_name: &SoundName,
+    ) -> Result<bool, Error>;
+
+    /// Remove a sound, returning whether it exists. Removed sounds are kept until they're purged.
+    async fn remove_sound(&self, guild_id: GuildId, name: &SoundName) -> Result<bool, Error>;
+
+    async fn set_sound_length(&self, sound_id: i32, length: i32) -> Result<(), Error>;
+
+    async fn set_sound_file(
+        &self,
+        sound_id: i32,
+        file_hash: &str,
+        file_size: i64,
+    ) -> Result<(), Error>;
+
+    async fn set_sound_volume(&self, sound_id: i32, volume: i32) -> Result<(), Error>;
+
+    /// Get what yt-dlp said about a sound's source, if anything.
+    async fn sound_metadata(&self, sound_id: i32) -> Result<Option<SoundMetadata>, Error>;
+
+    async fn set_sound_metadata(
+        &self,
+        sound_id: i32,
+        metadata: &SoundMetadata,
+    ) -> Result<(), Error>;
+
+    /// Get the tags of every sound in `guild_id` as `(sound id, tag)`, ordered by tag.
+    async fn guild_tags(&self, guild_id: GuildId) -> Result<Vec<(i32, String)>, Error>;
+
+    /// Replace the tags of a sound.
+    async fn set_sound_tags(&self, sound_id: i32, tags: &[String]) -> Result<(), Error>;
+
+    /// Add many sounds at once, along with their past plays, creating guilds as needed.
+    ///
+    /// The sounds named in `replaced` are removed first. Everything happens in one transaction, so
+    /// if anything fails, nothing changes. Returns the ids of the added sounds in order.
+    async fn import_sounds(
+        &self,
+        replaced: &[(GuildId, SoundName)],
+        sounds: &[NewSound],
+        playbacks: &[NewPlayback],
+    ) -> Result<Vec<i32>, Error>;
+
+    /// Get the hashes of every file a sound uses.
+    async fn referenced_files(&self) -> Result<Vec<String>, Error>;
+
+    /// Get the hashes of files whose size hasn't been recorded, including those of removed sounds.
+    async fn files_without_size(&self) -> Result<Vec<String>, Error>;
+
+    async fn set_file_size(&self, file_hash: &str, file_size: i64) -> Result<(), Error>;
+
+    /// Permanently delete sounds removed at least `older_than` days ago and their plays,
+    /// returning how many sounds were deleted.
+    async fn purge_removed(&self, older_than: i32) -> Result<u64, Error>;
+
+    /// Record a play of a sound, returning its id.
+    async fn add_playback(&self, sound_id: i32, player_id: UserId) -> Result<i32, Error>;
+
+    /// Delete a playback, for plays that never started.
+    async fn delete_playback(&self, playback_id: i32) -> Result<(), Error>;
+
+    /// Record playbacks as stopped by `stopper_id`, ending their pauses.
+    async fn stop_playbacks(&self, playback_ids: &[i32], stopper_id: UserId) -> Result<(), Error>;
+
+    /// Record playbacks as stopped by Bernie itself, for `reason`, ending their pauses.
+    async fn end_playbacks(&self, playback_ids: &[i32], reason: &str) -> Result<(), Error>;
+
+    /// Record playbacks as paused, unless they already are.
+    async fn pause_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error>;
+
+    /// Record playbacks as resumed, adding how long they were paused to their pauses.
+    async fn resume_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error>;
+
+    /// Get the plays of sounds in `guild_id`, including removed ones, newest first.
+    /// Only plays of sounds named `name` are included if it's given.
+    async fn history(
+        &self,
+        guild_id: GuildId,
+        name: Option<&SoundName>,
+    ) -> Result<Vec<Playback>, Error>;
+
+    /// Get the names of up to `limit` of the most played sounds in `guild_id`, along with how many
+    /// times they've been played, most played first.
+    async fn most_played(&self, guild_id: GuildId, limit: i64)
+        -> Result<Vec<(String, i64)>, Error>;
+
+    /// Replace the API token of `user_id`, given the SHA-256 hash of the new one.
+    async fn set_api_token(&self, user_id: UserId, token_hash: &str) -> Result<(), Error>;
+
+    /// Remove the API token of `user_id`, returning whether they had one.
+    async fn remove_api_token(&self, user_id: UserId) -> Result<bool, Error>;
+
+    /// Get who the API token with the SHA-256 hash `token_hash` belongs to, if anyone.
+    async fn api_token_user(&self, token_hash: &str) -> Result<Option<UserId>, Error>;
+}
+
+/// Connect to the database at `url`, which is either a `postgres://` or a `sqlite:` URL.
+pub async fn connect(url: &str) -> Result<Box<dyn Repository>, Error> {
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        return Ok(Box::new(PostgresRepository::connect(url).await?));
+    }
+    #[cfg(feature = "sqlite")]
+    if url.starts_with("sqlite:") {
+        return Ok(Box::new(SqliteRepository::connect(url).await?));
+    }
+
+    bail!("Unsupported database URL: {url:?}. Is the database's feature enabled?")
+}
+
+/// Turn a unique violation from adding or renaming a sound into a readable error.
+fn name_taken(error: sqlx::Error, name: &SoundName) -> Error {
+    match &error {
+        sqlx::Error::Database(db_error)
+            if matches!(
+                // postgres' unique_violation and sqlite's SQLITE_CONSTRAINT_UNIQUE.
+                db_error.code().as_deref(),
+                Some("23505") | Some("2067")
+            ) =>
+        {
+            anyhow!("A sound named `{name}` already exists.")
+        }
+        _ => error.into(),
+    }
+}
+
+/// Revert the latest migration of `migrator` applied through `conn`.
+async fn revert_latest<C: Migrate>(
+    conn: &mut C,
+    migrator: &Migrator,
+) -> Result<Option<(i64, String)>, Error> {
+    conn.ensure_migrations_table().await?;
+
+    let latest = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .max_by_key(|migration| migration.version);
+
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+
+    let migration = migrator
+        .iter()
+        .find(|migration| {
+            migration.version == latest.version && migration.migration_type.is_down_migration()
+        })
+        .ok_or_else(|| anyhow!("Migration {} can't be reverted.", latest.version))?;
+
+    conn.revert(migration).await?;
+
+    Ok(Some((migration.version, migration.description.to_string())))
+}
@@ -0,0 +1,805 @@
+use async_trait::async_trait;
+use poise::serenity_prelude::{GuildId, UserId};
+use sqlx::migrate::Migrator;
+use sqlx::{Executor, PgPool, Postgres};
+
+use super::{
+    name_taken, revert_latest, LegacyFile, NewPlayback, NewSound, Playback, PoolStats, Repository,
+    Sound,
+};
+use crate::media::SoundMetadata;
+use crate::quota::{GuildLimits, Usage};
+use crate::sound_name::SoundName;
+use crate::Error;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Debug)]
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        Ok(Self::new(PgPool::connect(url).await?))
+    }
+}
+
+async fn ensure_guild<'c, E>(executor: E, guild_id: GuildId) -> Result<(), Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query!(
+        "insert into guilds \
+        values($1) \
+        on conflict do nothing",
+        guild_id.0 as i64
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_sound<'c, E>(executor: E, sound: &NewSound) -> Result<i32, Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let metadata = sound
+        .metadata
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+
+    sqlx::query!(
+        "insert into sounds(guild_id, name, source, uploader_id, length, created_at, file_hash, file_size, sound_metadata, volume) \
+        values($1, $2, $3, $4, $5, coalesce($6, current_timestamp), $7, $8, $9, $10) \
+        returning id",
+        sound.guild_id.0 as i64,
+        sound.name.as_str(),
+        sound.source,
+        sound.uploader_id.0 as i64,
+        sound.length,
+        sound.created_at,
+        sound.file_hash,
+        sound.file_size,
+        metadata,
+        sound.volume
+    )
+    .map(|record| record.id)
+    .fetch_one(executor)
+    .await
+    .map_err(|error| name_taken(error, &sound.name))
+}
+
+async fn remove_sound<'c, E>(
+    executor: E,
+    guild_id: GuildId,
+    name: &SoundName,
+) -> Result<bool, Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        "update sounds set deleted_at = current_timestamp \
+        where guild_id = $1 and lower(name) = lower($2) and deleted_at is null",
+        guild_id.0 as i64,
+        name.as_str()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[async_trait]
+impl Repository for PostgresRepository {
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn migrate(&self) -> Result<(), Error> {
+        MIGRATOR.run(&self.pool).await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn revert_migration(&self) -> Result<Option<(i64, String)>, Error> {
+        let mut conn = self.pool.acquire().await?;
+
+        revert_latest(&mut *conn, &MIGRATOR).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn ping(&self) -> Result<(), Error> {
+        self.pool.execute("select 1").await?;
+
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            connections: self.pool.size(),
+            idle: self.pool.num_idle(),
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn ensure_guild(&self, guild_id: GuildId) -> Result<(), Error> {
+        ensure_guild(&self.pool, guild_id).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_limits(&self, guild_id: GuildId) -> Result<GuildLimits, Error> {
+        let limits = sqlx::query_as!(
+            GuildLimits,
+            "select max_length, max_size, max_total_size, max_sounds from guilds \
+            where id = $1",
+            guild_id.0 as i64
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(limits.unwrap_or_default())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_guild_limits(&self, guild_id: GuildId, limits: &GuildLimits) -> Result<(), Error> {
+        sqlx::query!(
+            "insert into guilds(id, max_length, max_size, max_total_size, max_sounds) \
+            values($1, $2, $3, $4, $5) \
+            on conflict (id) do update \
+            set max_length = $2, max_size = $3, max_total_size = $4, max_sounds = $5",
+            guild_id.0 as i64,
+            limits.max_length,
+            limits.max_size,
+            limits.max_total_size,
+            limits.max_sounds
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn usage(&self, guild_id: GuildId) -> Result<Usage, Error> {
+        let usage = sqlx::query_as!(
+            Usage,
+            "select \
+                (select count(*) from sounds \
+                where guild_id = $1 and deleted_at is null) as \"sounds!\", \
+                (select coalesce(sum(file_size), 0)::bigint from (\
+                    select distinct file_hash, file_size from sounds \
+                    where guild_id = $1 and deleted_at is null and file_hash is not null\
+                ) as files) as \"total_size!\"",
+            guild_id.0 as i64
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_sounds(&self, guild_id: GuildId) -> Result<Vec<Sound>, Error> {
+        let sounds = sqlx::query_as!(
+            Sound,
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where guild_id = $1 and deleted_at is null \
+            order by name",
+            guild_id.0 as i64
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sounds)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn all_sounds(&self) -> Result<Vec<Sound>, Error> {
+        let sounds = sqlx::query_as!(
+            Sound,
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where deleted_at is null \
+            order by guild_id, name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sounds)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn legacy_files(&self) -> Result<Vec<LegacyFile>, Error> {
+        let files = sqlx::query_as!(
+            LegacyFile,
+            "select id as sound_id, guild_id, coalesce(legacy_name, name) as \"file_name!\" \
+            from sounds \
+            where file_hash is null and deleted_at is null \
+            order by guild_id, 3"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn autocomplete_sound(
+        &self,
+        guild_id: GuildId,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error> {
+        let names = sqlx::query!(
+            "select name from sounds \
+            where guild_id = $1 and starts_with(lower(name), lower($2)) and deleted_at is null \
+            order by name \
+            limit 25",
+            guild_id.0 as i64,
+            prefix
+        )
+        .map(|record| record.name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(names)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn find_sound(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Option<Sound>, Error> {
+        let sound = sqlx::query_as!(
+            Sound,
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where guild_id = $1 and lower(name) = lower($2) and deleted_at is null",
+            guild_id.0 as i64,
+            name.as_str()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(sound)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn sound_ids_named(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Vec<i32>, Error> {
+        let ids = sqlx::query!(
+            "select id from sounds \
+            where guild_id = $1 and lower(name) = lower($2)",
+            guild_id.0 as i64,
+            name.as_str()
+        )
+        .map(|record| record.id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn add_sound(&self, sound: &NewSound) -> Result<i32, Error> {
+        insert_sound(&self.pool, sound).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn rename_sound(
+        &self,
+        guild_id: GuildId,
+        old_name: &SoundName,
+        new_name: &SoundName,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "update sounds set name = $1 \
+            where guild_id = $2 and lower(name) = lower($3) and deleted_at is null",
+            new_name.as_str(),
+            guild_id.0 as i64,
+            old_name.as_str()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|error| name_taken(error, new_name))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn remove_sound(&self, guild_id: GuildId, name: &SoundName) -> Result<bool, Error> {
+        remove_sound(&self.pool, guild_id, name).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_length(&self, sound_id: i32, length: i32) -> Result<(), Error> {
+        sqlx::query!(
+            "update sounds set length = $1 \
+            where id = $2",
+            length,
+            sound_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_file(
+        &self,
+        sound_id: i32,
+        file_hash: &str,
+        file_size: i64,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "update sounds set file_hash = $1, file_size = $2 \
+            where id = $3",
+            file_hash,
+            file_size,
+            sound_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_volume(&self, sound_id: i32, volume: i32) -> Result<(), Error> {
+        sqlx::query!(
+            "update sounds set volume = $1 \
+            where id = $2",
+            volume,
+            sound_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn sound_metadata(&self, sound_id: i32) -> Result<Option<SoundMetadata>, Error> {
+        let metadata = sqlx::query!(
+            "select sound_metadata from sounds \
+            where id = $1",
+            sound_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .and_then(|record| record.sound_metadata);
+
+        Ok(metadata.map(serde_json::from_value).transpose()?)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_metadata(
+        &self,
+        sound_id: i32,
+        metadata: &SoundMetadata,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "update sounds set sound_metadata = $1 \
+            where id = $2",
+            serde_json::to_value(metadata)?,
+            sound_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_tags(&self, guild_id: GuildId) -> Result<Vec<(i32, String)>, Error> {
+        let tags = sqlx::query!(
+            "select sound_tags.sound_id, sound_tags.tag \
+            from sound_tags \
+            inner join sounds on sounds.id = sound_tags.sound_id \
+            where sounds.guild_id = $1 and sounds.deleted_at is null \
+            order by sound_tags.tag",
+            guild_id.0 as i64
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.sound_id, row.tag))
+        .collect();
+
+        Ok(tags)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_tags(&self, sound_id: i32, tags: &[String]) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!("delete from sound_tags where sound_id = $1", sound_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query!(
+            "insert into sound_tags(sound_id, tag) \
+            select $1, unnest($2::text[])",
+            sound_id,
+            tags
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn import_sounds(
+        &self,
+        replaced: &[(GuildId, SoundName)],
+        sounds: &[NewSound],
+        playbacks: &[NewPlayback],
+    ) -> Result<Vec<i32>, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for (guild_id, name) in replaced {
+            remove_sound(&mut transaction, *guild_id, name).await?;
+        }
+
+        let mut sound_ids = vec![];
+        for sound in sounds {
+            ensure_guild(&mut transaction, sound.guild_id).await?;
+            sound_ids.push(insert_sound(&mut transaction, sound).await?);
+        }
+
+        for playback in playbacks {
+            sqlx::query!(
+                "insert into playbacks(sound_id, player_id, created_at, stopper_id, stopped_at) \
+                values($1, $2, $3, $4, $5)",
+                sound_ids[playback.sound],
+                playback.player_id.0 as i64,
+                playback.created_at,
+                playback.stopper_id.map(|id| id.0 as i64),
+                playback.stopped_at
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(sound_ids)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn referenced_files(&self) -> Result<Vec<String>, Error> {
+        let hashes = sqlx::query!(
+            "select distinct file_hash as \"file_hash!\" from sounds \
+            where deleted_at is null and file_hash is not null"
+        )
+        .map(|record| record.file_hash)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn files_without_size(&self) -> Result<Vec<String>, Error> {
+        let hashes = sqlx::query!(
+            "select distinct file_hash as \"file_hash!\" from sounds \
+            where file_size is null and file_hash is not null"
+        )
+        .map(|record| record.file_hash)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_file_size(&self, file_hash: &str, file_size: i64) -> Result<(), Error> {
+        sqlx::query!(
+            "update sounds set file_size = $1 \
+            where file_hash = $2",
+            file_size,
+            file_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn purge_removed(&self, older_than: i32) -> Result<u64, Error> {
+        let result = sqlx::query!(
+            "delete from sounds \
+            where deleted_at < current_timestamp - make_interval(days => $1)",
+            older_than
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn add_playback(&self, sound_id: i32, player_id: UserId) -> Result<i32, Error> {
+        let playback_id = sqlx::query!(
+            "insert into playbacks(sound_id, player_id) \
+            values($1, $2) \
+            returning id",
+            sound_id,
+            player_id.0 as i64
+        )
+        .map(|record| record.id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(playback_id)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn delete_playback(&self, playback_id: i32) -> Result<(), Error> {
+        sqlx::query!(
+            "delete from playbacks \
+            where id = $1",
+            playback_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn stop_playbacks(&self, playback_ids: &[i32], stopper_id: UserId) -> Result<(), Error> {
+        sqlx::query!(
+            "update playbacks set stopper_id = $1, stopped_at = current_timestamp, \
+                paused_ms = paused_ms \
+                    + coalesce((extract(epoch from current_timestamp - paused_at) * 1000)::bigint, 0), \
+                paused_at = null \
+            from (select unnest($2::int[]) as id) as stopped \
+            where playbacks.id = stopped.id",
+            stopper_id.0 as i64,
+            playback_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn end_playbacks(&self, playback_ids: &[i32], reason: &str) -> Result<(), Error> {
+        sqlx::query!(
+            "update playbacks set stop_reason = $1, stopped_at = current_timestamp, \
+                paused_ms = paused_ms \
+                    + coalesce((extract(epoch from current_timestamp - paused_at) * 1000)::bigint, 0), \
+                paused_at = null \
+            from (select unnest($2::int[]) as id) as stopped \
+            where playbacks.id = stopped.id",
+            reason,
+            playback_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn pause_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        sqlx::query!(
+            "update playbacks set paused_at = current_timestamp \
+            from (select unnest($1::int[]) as id) as paused \
+            where playbacks.id = paused.id and paused_at is null and stopped_at is null",
+            playback_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn resume_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        sqlx::query!(
+            "update playbacks \
+            set paused_ms = paused_ms \
+                    + (extract(epoch from current_timestamp - paused_at) * 1000)::bigint, \
+                paused_at = null \
+            from (select unnest($1::int[]) as id) as resumed \
+            where playbacks.id = resumed.id and paused_at is not null",
+            playback_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn history(
+        &self,
+        guild_id: GuildId,
+        name: Option<&SoundName>,
+    ) -> Result<Vec<Playback>, Error> {
+        // language=PostgreSQL
+        let history = sqlx::query_as!(
+            Playback,
+            "select playbacks.id, sounds.name, playbacks.player_id, playbacks.created_at, \
+                playbacks.stopper_id, playbacks.stopped_at, playbacks.stop_reason, playbacks.paused_ms \
+            from playbacks \
+            inner join sounds on sounds.id = playbacks.sound_id \
+            where sounds.guild_id = $1 and ($2::text is null or lower(sounds.name) = lower($2)) \
+            order by playbacks.created_at desc",
+            guild_id.0 as i64,
+            name.map(SoundName::as_str)
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(history)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn most_played(
+        &self,
+        guild_id: GuildId,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>, Error> {
+        let plays = sqlx::query!(
+            "select sounds.name, count(*) as \"plays!\" \
+            from playbacks \
+            inner join sounds on sounds.id = playbacks.sound_id \
+            where sounds.guild_id = $1 and sounds.deleted_at is null \
+            group by sounds.id \
+            order by count(*) desc, sounds.name \
+            limit $2",
+            guild_id.0 as i64,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.name, row.plays))
+        .collect();
+
+        Ok(plays)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, token_hash))]
+    async fn set_api_token(&self, user_id: UserId, token_hash: &str) -> Result<(), Error> {
+        sqlx::query!(
+            "insert into api_tokens(user_id, token_hash) \
+            values($1, $2) \
+            on conflict (user_id) do update \
+            set token_hash = $2, created_at = current_timestamp",
+            user_id.0 as i64,
+            token_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn remove_api_token(&self, user_id: UserId) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "delete from api_tokens \
+            where user_id = $1",
+            user_id.0 as i64
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn api_token_user(&self, token_hash: &str) -> Result<Option<UserId>, Error> {
+        let user_id = sqlx::query_scalar!(
+            "select user_id from api_tokens \
+            where token_hash = $1",
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id.map(|user_id| UserId(user_id as u64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::migrate::Migrate;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::storage::convert_legacy_files;
+    use crate::store::{MemoryStore, SoundStore};
+
+    /// Version of the migration that makes names follow the rules of `SoundName`.
+    const SOUND_NAME_RULES: i64 = 20220305000000;
+
+    /// Connect to the database in `BERNIE_TEST_DATABASE_URL`, in a schema of its own.
+    async fn pool(schema: &str) -> PgPool {
+        let url = std::env::var("BERNIE_TEST_DATABASE_URL").unwrap();
+        // one connection, so the search path applies to every query.
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .unwrap();
+        let statements = format!(
+            "drop schema if exists {schema} cascade; \
+            create schema {schema}; \
+            set search_path to {schema}"
+        );
+        pool.execute(&*statements).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database in BERNIE_TEST_DATABASE_URL"]
+    async fn upgrading_converts_files_of_renamed_sounds() {
+        let pool = pool("upgrading_converts_files_of_renamed_sounds").await;
+        let mut conn = pool.acquire().await.unwrap();
+        conn.ensure_migrations_table().await.unwrap();
+        for migration in MIGRATOR.iter() {
+            if migration.version < SOUND_NAME_RULES && !migration.migration_type.is_down_migration()
+            {
+                conn.apply(migration).await.unwrap();
+            }
+        }
+        conn.execute(
+            "insert into guilds (id) values (1); \
+            insert into sounds (guild_id, name, source, uploader_id, length) values \
+                (1, 'Bonk', 'https://example.com/Bonk', 100, 1000), \
+                (1, 'bonk', 'https://example.com/bonk', 100, 1000)",
+        )
+        .await
+        .unwrap();
+        drop(conn);
+        let storage_dir = tempfile::tempdir().unwrap();
+        let guild_dir = storage_dir.path().join("1");
+        tokio::fs::create_dir(&guild_dir).await.unwrap();
+        for name in ["Bonk", "bonk"] {
+            tokio::fs::write(guild_dir.join(name), name).await.unwrap();
+        }
+
+        let db = PostgresRepository::new(pool);
+        db.migrate().await.unwrap();
+        let store = MemoryStore::new();
+        let converted = convert_legacy_files(&db, storage_dir.path(), &store)
+            .await
+            .unwrap();
+
+        assert_eq!(converted, 2);
+        let mut contents = Vec::new();
+        for sound in db.all_sounds().await.unwrap() {
+            let file = store.get(&sound.file_hash.unwrap()).await.unwrap();
+            contents.push((sound.name, String::from_utf8(file).unwrap()));
+        }
+        assert_eq!(
+            contents,
+            [
+                ("Bonk".to_owned(), "Bonk".to_owned()),
+                ("bonk (2)".to_owned(), "bonk".to_owned())
+            ]
+        );
+        assert!(db.legacy_files().await.unwrap().is_empty());
+    }
+}
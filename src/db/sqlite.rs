@@ -0,0 +1,843 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::types::Json;
+use sqlx::{Executor, Sqlite};
+
+use super::{
+    name_taken, revert_latest, LegacyFile, NewPlayback, NewSound, Playback, PoolStats, Repository,
+    Sound,
+};
+use crate::media::SoundMetadata;
+use crate::quota::{GuildLimits, Usage};
+use crate::sound_name::{name_key, SoundName};
+use crate::Error;
+
+// the query macros can only check queries against one kind of database, and that's postgres, so
+// queries here are checked at runtime instead.
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// SQL for the current time, in the format timestamps are stored in.
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+/// Milliseconds since a playback was paused, or 0 if it isn't paused.
+const PAUSED_FOR: &str =
+    "coalesce(cast(round((julianday('now') - julianday(paused_at)) * 86400000) as integer), 0)";
+
+#[derive(Debug)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Connect to the database at `url`, creating it if it doesn't exist.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+
+        Ok(Self::new(pool))
+    }
+
+    /// Work out the keys of sounds added before keys were stored. Live sounds whose keys clash
+    /// are left without one and logged, since SQLite let them share a name that differs only in
+    /// the case of non-ASCII letters.
+    async fn fill_name_keys(&self) -> Result<(), Error> {
+        let sounds: Vec<(i32, String)> =
+            sqlx::query_as("select id, name from sounds where name_key is null")
+                .fetch_all(&self.pool)
+                .await?;
+
+        for (id, name) in sounds {
+            let result = sqlx::query("update sounds set name_key = ?1 where id = ?2")
+                .bind(name_key(&name))
+                .bind(id)
+                .execute(&self.pool)
+                .await;
+            if let Err(error) = result {
+                tracing::warn!("Couldn't fill in the name key of sound {id}, {name:?}: {error}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Format `timestamp` the way timestamps are stored, so they compare correctly as text.
+fn timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+async fn ensure_guild<'c, E>(executor: E, guild_id: GuildId) -> Result<(), Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    sqlx::query(
+        "insert into guilds(id) \
+        values(?1) \
+        on conflict do nothing",
+    )
+    .bind(guild_id.0 as i64)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_sound<'c, E>(executor: E, sound: &NewSound) -> Result<i32, Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    sqlx::query_scalar(&format!(
+        "insert into sounds(guild_id, name, name_key, source, uploader_id, length, created_at, file_hash, file_size, sound_metadata, volume) \
+        values(?1, ?2, ?3, ?4, ?5, ?6, coalesce(?7, {NOW}), ?8, ?9, ?10, ?11) \
+        returning id"
+    ))
+    .bind(sound.guild_id.0 as i64)
+    .bind(sound.name.as_str())
+    .bind(sound.name.key())
+    .bind(&sound.source)
+    .bind(sound.uploader_id.0 as i64)
+    .bind(sound.length)
+    .bind(sound.created_at.map(timestamp))
+    .bind(&sound.file_hash)
+    .bind(sound.file_size)
+    .bind(sound.metadata.as_ref().map(Json))
+    .bind(sound.volume)
+    .fetch_one(executor)
+    .await
+    .map_err(|error| name_taken(error, &sound.name))
+}
+
+async fn remove_sound<'c, E>(
+    executor: E,
+    guild_id: GuildId,
+    name: &SoundName,
+) -> Result<bool, Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    let result = sqlx::query(&format!(
+        "update sounds set deleted_at = {NOW} \
+        where guild_id = ?1 and name_key = ?2 and deleted_at is null"
+    ))
+    .bind(guild_id.0 as i64)
+    .bind(name.key())
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn migrate(&self) -> Result<(), Error> {
+        MIGRATOR.run(&self.pool).await?;
+        self.fill_name_keys().await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn revert_migration(&self) -> Result<Option<(i64, String)>, Error> {
+        let mut conn = self.pool.acquire().await?;
+
+        revert_latest(&mut *conn, &MIGRATOR).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn ping(&self) -> Result<(), Error> {
+        self.pool.execute("select 1").await?;
+
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            connections: self.pool.size(),
+            idle: self.pool.num_idle(),
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn ensure_guild(&self, guild_id: GuildId) -> Result<(), Error> {
+        ensure_guild(&self.pool, guild_id).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_limits(&self, guild_id: GuildId) -> Result<GuildLimits, Error> {
+        let limits = sqlx::query_as(
+            "select max_length, max_size, max_total_size, max_sounds from guilds \
+            where id = ?1",
+        )
+        .bind(guild_id.0 as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(limits.unwrap_or_default())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_guild_limits(&self, guild_id: GuildId, limits: &GuildLimits) -> Result<(), Error> {
+        sqlx::query(
+            "insert into guilds(id, max_length, max_size, max_total_size, max_sounds) \
+            values(?1, ?2, ?3, ?4, ?5) \
+            on conflict (id) do update \
+            set max_length = ?2, max_size = ?3, max_total_size = ?4, max_sounds = ?5",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(limits.max_length)
+        .bind(limits.max_size)
+        .bind(limits.max_total_size)
+        .bind(limits.max_sounds)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn usage(&self, guild_id: GuildId) -> Result<Usage, Error> {
+        let usage = sqlx::query_as(
+            "select \
+                (select count(*) from sounds \
+                where guild_id = ?1 and deleted_at is null) as sounds, \
+                (select coalesce(sum(file_size), 0) from (\
+                    select distinct file_hash, file_size from sounds \
+                    where guild_id = ?1 and deleted_at is null and file_hash is not null\
+                )) as total_size",
+        )
+        .bind(guild_id.0 as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_sounds(&self, guild_id: GuildId) -> Result<Vec<Sound>, Error> {
+        let sounds = sqlx::query_as(
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where guild_id = ?1 and deleted_at is null \
+            order by name",
+        )
+        .bind(guild_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sounds)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn all_sounds(&self) -> Result<Vec<Sound>, Error> {
+        let sounds = sqlx::query_as(
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where deleted_at is null \
+            order by guild_id, name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sounds)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn legacy_files(&self) -> Result<Vec<LegacyFile>, Error> {
+        // SQLite databases start out with names that follow the rules, so files keep their names.
+        let files = sqlx::query_as(
+            "select id as sound_id, guild_id, name as file_name \
+            from sounds \
+            where file_hash is null and deleted_at is null \
+            order by guild_id, name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn autocomplete_sound(
+        &self,
+        guild_id: GuildId,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error> {
+        // sqlite has no `starts_with`, and `like` would treat `%` and `_` in `prefix` specially.
+        let names = sqlx::query_scalar(
+            "select name from sounds \
+            where guild_id = ?1 and substr(name_key, 1, length(?2)) = ?2 \
+                and deleted_at is null \
+            order by name \
+            limit 25",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(name_key(prefix))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(names)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn find_sound(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Option<Sound>, Error> {
+        let sound = sqlx::query_as(
+            "select id, guild_id, name, source, uploader_id, length, created_at, file_hash, volume \
+            from sounds \
+            where guild_id = ?1 and name_key = ?2 and deleted_at is null",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(name.key())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(sound)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn sound_ids_named(
+        &self,
+        guild_id: GuildId,
+        name: &SoundName,
+    ) -> Result<Vec<i32>, Error> {
+        let ids = sqlx::query_scalar(
+            "select id from sounds \
+            where guild_id = ?1 and name_key = ?2",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(name.key())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn add_sound(&self, sound: &NewSound) -> Result<i32, Error> {
+        insert_sound(&self.pool, sound).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn rename_sound(
+        &self,
+        guild_id: GuildId,
+        old_name: &SoundName,
+        new_name: &SoundName,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "update sounds set name = ?1, name_key = ?2 \
+            where guild_id = ?3 and name_key = ?4 and deleted_at is null",
+        )
+        .bind(new_name.as_str())
+        .bind(new_name.key())
+        .bind(guild_id.0 as i64)
+        .bind(old_name.key())
+        .execute(&self.pool)
+        .await
+        .map_err(|error| name_taken(error, new_name))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn remove_sound(&self, guild_id: GuildId, name: &SoundName) -> Result<bool, Error> {
+        remove_sound(&self.pool, guild_id, name).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_length(&self, sound_id: i32, length: i32) -> Result<(), Error> {
+        sqlx::query(
+            "update sounds set length = ?1 \
+            where id = ?2",
+        )
+        .bind(length)
+        .bind(sound_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_file(
+        &self,
+        sound_id: i32,
+        file_hash: &str,
+        file_size: i64,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "update sounds set file_hash = ?1, file_size = ?2 \
+            where id = ?3",
+        )
+        .bind(file_hash)
+        .bind(file_size)
+        .bind(sound_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_volume(&self, sound_id: i32, volume: i32) -> Result<(), Error> {
+        sqlx::query(
+            "update sounds set volume = ?1 \
+            where id = ?2",
+        )
+        .bind(volume)
+        .bind(sound_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn sound_metadata(&self, sound_id: i32) -> Result<Option<SoundMetadata>, Error> {
+        let metadata: Option<Option<Json<SoundMetadata>>> = sqlx::query_scalar(
+            "select sound_metadata from sounds \
+            where id = ?1",
+        )
+        .bind(sound_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(metadata.flatten().map(|Json(metadata)| metadata))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_metadata(
+        &self,
+        sound_id: i32,
+        metadata: &SoundMetadata,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "update sounds set sound_metadata = ?1 \
+            where id = ?2",
+        )
+        .bind(Json(metadata))
+        .bind(sound_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn guild_tags(&self, guild_id: GuildId) -> Result<Vec<(i32, String)>, Error> {
+        let tags = sqlx::query_as(
+            "select sound_tags.sound_id, sound_tags.tag \
+            from sound_tags \
+            inner join sounds on sounds.id = sound_tags.sound_id \
+            where sounds.guild_id = ?1 and sounds.deleted_at is null \
+            order by sound_tags.tag",
+        )
+        .bind(guild_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_sound_tags(&self, sound_id: i32, tags: &[String]) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query("delete from sound_tags where sound_id = ?1")
+            .bind(sound_id)
+            .execute(&mut transaction)
+            .await?;
+        // sqlite can't bind arrays, so insert them one at a time.
+        for tag in tags {
+            sqlx::query("insert into sound_tags(sound_id, tag) values(?1, ?2)")
+                .bind(sound_id)
+                .bind(tag)
+                .execute(&mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn import_sounds(
+        &self,
+        replaced: &[(GuildId, SoundName)],
+        sounds: &[NewSound],
+        playbacks: &[NewPlayback],
+    ) -> Result<Vec<i32>, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for (guild_id, name) in replaced {
+            remove_sound(&mut transaction, *guild_id, name).await?;
+        }
+
+        let mut sound_ids = vec![];
+        for sound in sounds {
+            ensure_guild(&mut transaction, sound.guild_id).await?;
+            sound_ids.push(insert_sound(&mut transaction, sound).await?);
+        }
+
+        for playback in playbacks {
+            sqlx::query(
+                "insert into playbacks(sound_id, player_id, created_at, stopper_id, stopped_at) \
+                values(?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(sound_ids[playback.sound])
+            .bind(playback.player_id.0 as i64)
+            .bind(timestamp(playback.created_at))
+            .bind(playback.stopper_id.map(|id| id.0 as i64))
+            .bind(playback.stopped_at.map(timestamp))
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(sound_ids)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn referenced_files(&self) -> Result<Vec<String>, Error> {
+        let hashes = sqlx::query_scalar(
+            "select distinct file_hash from sounds \
+            where deleted_at is null and file_hash is not null",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn files_without_size(&self) -> Result<Vec<String>, Error> {
+        let hashes = sqlx::query_scalar(
+            "select distinct file_hash from sounds \
+            where file_size is null and file_hash is not null",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_file_size(&self, file_hash: &str, file_size: i64) -> Result<(), Error> {
+        sqlx::query(
+            "update sounds set file_size = ?1 \
+            where file_hash = ?2",
+        )
+        .bind(file_size)
+        .bind(file_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn purge_removed(&self, older_than: i32) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "delete from sounds \
+            where deleted_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', printf('-%d days', ?1))",
+        )
+        .bind(older_than)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn add_playback(&self, sound_id: i32, player_id: UserId) -> Result<i32, Error> {
+        let playback_id = sqlx::query_scalar(
+            "insert into playbacks(sound_id, player_id) \
+            values(?1, ?2) \
+            returning id",
+        )
+        .bind(sound_id)
+        .bind(player_id.0 as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(playback_id)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn delete_playback(&self, playback_id: i32) -> Result<(), Error> {
+        sqlx::query(
+            "delete from playbacks \
+            where id = ?1",
+        )
+        .bind(playback_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn stop_playbacks(&self, playback_ids: &[i32], stopper_id: UserId) -> Result<(), Error> {
+        // sqlite can't bind arrays, so update them one at a time.
+        let mut transaction = self.pool.begin().await?;
+
+        for playback_id in playback_ids {
+            sqlx::query(&format!(
+                "update playbacks set stopper_id = ?1, stopped_at = {NOW}, \
+                    paused_ms = paused_ms + {PAUSED_FOR}, paused_at = null \
+                where id = ?2"
+            ))
+            .bind(stopper_id.0 as i64)
+            .bind(playback_id)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn end_playbacks(&self, playback_ids: &[i32], reason: &str) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for playback_id in playback_ids {
+            sqlx::query(&format!(
+                "update playbacks set stop_reason = ?1, stopped_at = {NOW}, \
+                    paused_ms = paused_ms + {PAUSED_FOR}, paused_at = null \
+                where id = ?2"
+            ))
+            .bind(reason)
+            .bind(playback_id)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn pause_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for playback_id in playback_ids {
+            sqlx::query(&format!(
+                "update playbacks set paused_at = {NOW} \
+                where id = ?1 and paused_at is null and stopped_at is null"
+            ))
+            .bind(playback_id)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn resume_playbacks(&self, playback_ids: &[i32]) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for playback_id in playback_ids {
+            sqlx::query(&format!(
+                "update playbacks set paused_ms = paused_ms + {PAUSED_FOR}, paused_at = null \
+                where id = ?1 and paused_at is not null"
+            ))
+            .bind(playback_id)
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn history(
+        &self,
+        guild_id: GuildId,
+        name: Option<&SoundName>,
+    ) -> Result<Vec<Playback>, Error> {
+        let history = sqlx::query_as(
+            "select playbacks.id, sounds.name, playbacks.player_id, playbacks.created_at, \
+                playbacks.stopper_id, playbacks.stopped_at, playbacks.stop_reason, playbacks.paused_ms \
+            from playbacks \
+            inner join sounds on sounds.id = playbacks.sound_id \
+            where sounds.guild_id = ?1 and (?2 is null or sounds.name_key = ?2) \
+            order by playbacks.created_at desc",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(name.map(SoundName::key))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(history)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn most_played(
+        &self,
+        guild_id: GuildId,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>, Error> {
+        let plays = sqlx::query_as(
+            "select sounds.name, count(*) \
+            from playbacks \
+            inner join sounds on sounds.id = playbacks.sound_id \
+            where sounds.guild_id = ?1 and sounds.deleted_at is null \
+            group by sounds.id \
+            order by count(*) desc, sounds.name \
+            limit ?2",
+        )
+        .bind(guild_id.0 as i64)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(plays)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, token_hash))]
+    async fn set_api_token(&self, user_id: UserId, token_hash: &str) -> Result<(), Error> {
+        sqlx::query(
+            "insert into api_tokens(user_id, token_hash) \
+            values(?1, ?2) \
+            on conflict (user_id) do update \
+            set token_hash = ?2, created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')",
+        )
+        .bind(user_id.0 as i64)
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn remove_api_token(&self, user_id: UserId) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "delete from api_tokens \
+            where user_id = ?1",
+        )
+        .bind(user_id.0 as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn api_token_user(&self, token_hash: &str) -> Result<Option<UserId>, Error> {
+        let user_id: Option<i64> = sqlx::query_scalar(
+            "select user_id from api_tokens \
+            where token_hash = ?1",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id.map(|user_id| UserId(user_id as u64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DEFAULT_VOLUME;
+
+    async fn repository(dir: &tempfile::TempDir) -> SqliteRepository {
+        let url = format!("sqlite://{}", dir.path().join("bernie.db").display());
+        let db = SqliteRepository::connect(&url).await.unwrap();
+        db.migrate().await.unwrap();
+        db.ensure_guild(GuildId(1)).await.unwrap();
+        db
+    }
+
+    fn sound(name: &str) -> NewSound {
+        NewSound {
+            guild_id: GuildId(1),
+            name: SoundName::new(name).unwrap(),
+            source: "https://example.com/sound".to_owned(),
+            uploader_id: UserId(100),
+            length: 1000,
+            created_at: None,
+            file_hash: "hash".to_owned(),
+            file_size: 1024,
+            metadata: None,
+            volume: DEFAULT_VOLUME,
+        }
+    }
+
+    #[tokio::test]
+    async fn names_are_unique_regardless_of_case_beyond_ascii() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = repository(&dir).await;
+
+        db.add_sound(&sound("Été")).await.unwrap();
+        let error = db.add_sound(&sound("éTÉ")).await.unwrap_err();
+        assert_eq!(error.to_string(), "A sound named `éTÉ` already exists.");
+
+        let found = db
+            .find_sound(GuildId(1), &SoundName::new("ÉTÉ").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(found.unwrap().name, "Été");
+        assert_eq!(
+            db.autocomplete_sound(GuildId(1), "ÉT").await.unwrap(),
+            ["Été"]
+        );
+
+        let renamed = db
+            .rename_sound(
+                GuildId(1),
+                &SoundName::new("été").unwrap(),
+                &SoundName::new("Öl").unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(renamed);
+        assert!(db
+            .remove_sound(GuildId(1), &SoundName::new("öL").unwrap())
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn keys_of_sounds_from_before_keys_were_stored_are_filled_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = repository(&dir).await;
+
+        db.add_sound(&sound("Été")).await.unwrap();
+        sqlx::query("update sounds set name_key = null")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        db.migrate().await.unwrap();
+
+        let found = db
+            .find_sound(GuildId(1), &SoundName::new("ÉTÉ").unwrap())
+            .await
+            .unwrap();
+        assert!(found.is_some());
+    }
+}
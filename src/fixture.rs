@@ -0,0 +1,115 @@
+//! Shared setup for tests: [`Data`] backed by an in-memory database and store, and fake voice and
+//! media layers, along with ids and sounds to fill it with.
+
+use std::time::Duration;
+
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId, UserId};
+
+use crate::cooldown::CooldownSettings;
+use crate::db::{MemoryRepository, NewSound, DEFAULT_VOLUME};
+use crate::media::{FakeMedia, TimeLimited};
+use crate::quota::{GuildLimits, Limits};
+use crate::services::sounds;
+use crate::sound_name::SoundName;
+use crate::store::MemoryStore;
+use crate::voice::FakeVoice;
+use crate::Data;
+
+pub const GUILD: GuildId = GuildId(1);
+pub const OTHER_GUILD: GuildId = GuildId(2);
+pub const CHANNEL: ChannelId = ChannelId(10);
+pub const ALICE: UserId = UserId(100);
+pub const BOB: UserId = UserId(101);
+pub const DJ: RoleId = RoleId(1000);
+
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub const NO_LIMITS: Limits = Limits {
+    max_length: None,
+    max_size: None,
+    max_total_size: None,
+    max_sounds: None,
+};
+
+pub fn name(name: &str) -> SoundName {
+    SoundName::new(name).unwrap()
+}
+
+/// Data backed by fakes, along with the ones tests look into.
+pub struct Setup {
+    pub data: Data,
+    pub store: MemoryStore,
+    pub voice: FakeVoice,
+    pub media: FakeMedia,
+}
+
+pub fn setup() -> Setup {
+    setup_with_cooldowns(CooldownSettings::none())
+}
+
+pub fn setup_with_cooldowns(cooldowns: CooldownSettings) -> Setup {
+    let store = MemoryStore::new();
+    let voice = FakeVoice::new();
+    let media = FakeMedia::new();
+    let data = Data::new(
+        Box::new(MemoryRepository::new()),
+        std::env::temp_dir(),
+        Box::new(store.clone()),
+        NO_LIMITS,
+        cooldowns,
+        Box::new(voice.clone()),
+        Box::new(TimeLimited::new(media.clone(), DOWNLOAD_TIMEOUT)),
+        Box::new(media.clone()),
+    );
+
+    Setup {
+        data,
+        store,
+        voice,
+        media,
+    }
+}
+
+pub async fn set_limits(data: &Data, guild_id: GuildId, limits: GuildLimits) {
+    data.db.set_guild_limits(guild_id, &limits).await.unwrap();
+}
+
+/// A one second sound named `sound_name` in `guild_id`, uploaded by [`ALICE`].
+pub fn new_sound(guild_id: GuildId, sound_name: &str, file_hash: &str) -> NewSound {
+    NewSound {
+        guild_id,
+        name: name(sound_name),
+        source: format!("https://example.com/{sound_name}"),
+        uploader_id: ALICE,
+        length: 1000,
+        created_at: None,
+        file_hash: file_hash.to_owned(),
+        file_size: 1024,
+        metadata: None,
+        volume: DEFAULT_VOLUME,
+    }
+}
+
+/// Add a sound and its file, the way `/add` would after downloading it. The file holds the
+/// sound's name.
+pub async fn seed(setup: &Setup, guild_id: GuildId, sound_name: &str) -> i32 {
+    let file_hash = format!("hash-of-{sound_name}");
+    setup.store.insert(&file_hash, sound_name.as_bytes());
+
+    setup
+        .data
+        .db
+        .add_sound(&new_sound(guild_id, sound_name, &file_hash))
+        .await
+        .unwrap()
+}
+
+/// Get the names of the sounds in `guild_id`, the way `/list` shows them.
+pub async fn names(data: &Data, guild_id: GuildId) -> Vec<String> {
+    sounds::list(data, guild_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|sound| sound.name)
+        .collect()
+}
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::extract::{ContentLengthLimit, Extension, Multipart, Path, Query};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::Html;
+use axum::routing::{get, patch, post};
+use axum::{AddExtensionLayer, Json, Router};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use super::auth::{self, Auth, Session, User};
+use super::ApiError;
+use crate::quota::{Limits, Usage};
+use crate::services::{playbacks, sounds};
+use crate::sound_name::SoundName;
+use crate::Data;
+
+/// Largest file that can be uploaded, in bytes. Guild limits on sizes still apply to the sound
+/// once it's been probed.
+const MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+
+/// The dashboard, its sign in flow and the API it uses. Guild routes go through the same services
+/// and checks as slash commands: whoever's signed in and Bernie both have to be in the guild.
+pub fn routes(auth: Arc<Auth>) -> Router {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/login", get(auth::login))
+        .route("/auth/callback", get(auth::callback))
+        .route("/logout", post(auth::logout))
+        .route("/api/me", get(me))
+        .route("/api/guilds/:guild_id/sounds", get(list).post(add))
+        .route(
+            "/api/guilds/:guild_id/sounds/:name",
+            patch(update).delete(remove),
+        )
+        .route("/api/guilds/:guild_id/sounds/:name/file", get(file))
+        .route("/api/guilds/:guild_id/history", get(history))
+        .route("/api/guilds/:guild_id/stats", get(stats))
+        .layer(AddExtensionLayer::new(auth))
+}
+
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+/// Discord ids are sent as strings, since they don't fit in JavaScript's numbers.
+#[derive(Debug, Serialize)]
+struct Me {
+    id: String,
+    name: String,
+    /// The guilds shared with Bernie.
+    guilds: Vec<GuildInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct GuildInfo {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SoundInfo {
+    id: i32,
+    name: String,
+    source: String,
+    uploader_id: String,
+    /// Length in milliseconds.
+    length: i32,
+    created_at: DateTime<Utc>,
+    tags: Vec<String>,
+    /// How loud the sound plays, in percent of its file's own volume.
+    volume: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct Added {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PlaybackInfo {
+    id: i32,
+    name: String,
+    player_id: String,
+    created_at: DateTime<Utc>,
+    stopper_id: Option<String>,
+    stopped_at: Option<DateTime<Utc>>,
+    stop_reason: Option<String>,
+    /// How long the sound played before it was stopped in milliseconds, not counting pauses.
+    duration_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    usage: Usage,
+    limits: Limits,
+    most_played: Vec<Played>,
+}
+
+#[derive(Debug, Serialize)]
+struct Played {
+    name: String,
+    plays: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    q: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryFilter {
+    name: Option<String>,
+}
+
+/// Changes to a sound. Tags replace the sound's tags, after it's been renamed.
+#[derive(Debug, Deserialize)]
+struct Update {
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+    volume: Option<i32>,
+}
+
+/// Get the guilds both `session` and Bernie are in.
+fn shared_guilds(data: &Data, session: &Session) -> Vec<GuildId> {
+    let bot_guilds = data
+        .cache
+        .get()
+        .map(|cache| cache.guilds())
+        .unwrap_or_default();
+
+    bot_guilds
+        .into_iter()
+        .filter(|guild_id| session.guilds.contains_key(guild_id))
+        .collect()
+}
+
+/// Check that `session` can use the sounds of `guild_id`, like the check on slash commands.
+async fn guild(data: &Data, session: &Session, guild_id: u64) -> Result<GuildId, ApiError> {
+    let guild_id = GuildId(guild_id);
+    if !shared_guilds(data, session).contains(&guild_id) {
+        return Err(ApiError::Forbidden);
+    }
+
+    data.db.ensure_guild(guild_id).await?;
+
+    Ok(guild_id)
+}
+
+async fn me(User(session): User, Extension(data): Extension<Arc<Data>>) -> Json<Me> {
+    let mut guilds: Vec<GuildInfo> = shared_guilds(&data, &session)
+        .into_iter()
+        .map(|guild_id| GuildInfo {
+            id: guild_id.0.to_string(),
+            name: session.guilds[&guild_id].clone(),
+        })
+        .collect();
+    guilds.sort_by_cached_key(|guild| guild.name.to_lowercase());
+
+    Json(Me {
+        id: session.user_id.0.to_string(),
+        name: session.user_name,
+        guilds,
+    })
+}
+
+async fn list(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path(guild_id): Path<u64>,
+    Query(search): Query<Search>,
+) -> Result<Json<Vec<SoundInfo>>, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+
+    let found = match search.q.filter(|query| !query.trim().is_empty()) {
+        Some(query) => sounds::search(&data, guild_id, &query).await?,
+        None => sounds::list_tagged(&data, guild_id).await?,
+    };
+
+    let found = found
+        .into_iter()
+        .map(|tagged| SoundInfo {
+            id: tagged.sound.id,
+            name: tagged.sound.name,
+            source: tagged.sound.source,
+            uploader_id: tagged.sound.uploader_id.to_string(),
+            length: tagged.sound.length,
+            created_at: tagged.sound.created_at,
+            tags: tagged.tags,
+            volume: tagged.sound.volume,
+        })
+        .collect();
+
+    Ok(Json(found))
+}
+
+/// Add a sound from a form with an optional `name`, and either a `source` to download it from or
+/// an uploaded `file`.
+async fn add(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path(guild_id): Path<u64>,
+    ContentLengthLimit(mut form): ContentLengthLimit<Multipart, MAX_UPLOAD_BYTES>,
+) -> Result<Json<Added>, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+
+    let mut name = None;
+    let mut source = None;
+    let mut upload = None;
+    while let Some(field) = form.next_field().await? {
+        let field_name = field.name().map(str::to_owned);
+        match field_name.as_deref() {
+            Some("name") => {
+                let text = field.text().await?;
+                if !text.trim().is_empty() {
+                    name = Some(SoundName::new(&text)?);
+                }
+            }
+            Some("source") => {
+                let text = field.text().await?;
+                if !text.trim().is_empty() {
+                    source = Some(text.trim().to_owned());
+                }
+            }
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or("upload").to_owned();
+                let contents = field.bytes().await?;
+                if !contents.is_empty() {
+                    let file = NamedTempFile::new()?;
+                    tokio::fs::write(file.path(), &contents).await?;
+                    upload = Some((file_name, file));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (id, name) = match (source, upload) {
+        (Some(source), None) => sounds::add(&data, guild_id, session.user_id, name, source).await?,
+        (None, Some((file_name, file))) => {
+            sounds::add_upload(&data, guild_id, session.user_id, name, file_name, file).await?
+        }
+        _ => {
+            return Err(anyhow!("Give either a link to download the sound from, or a file.").into())
+        }
+    };
+
+    Ok(Json(Added {
+        id,
+        name: name.into_string(),
+    }))
+}
+
+async fn update(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path((guild_id, name)): Path<(u64, String)>,
+    Json(update): Json<Update>,
+) -> Result<StatusCode, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+    let mut name = SoundName::new(&name)?;
+
+    if let Some(new_name) = update.name {
+        let new_name = SoundName::new(&new_name)?;
+        sounds::rename(&data, guild_id, &name, &new_name).await?;
+        name = new_name;
+    }
+    if let Some(tags) = update.tags {
+        sounds::tag(&data, guild_id, &name, &tags).await?;
+    }
+    if let Some(volume) = update.volume {
+        sounds::set_volume(&data, guild_id, &name, volume).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path((guild_id, name)): Path<(u64, String)>,
+) -> Result<StatusCode, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+    let name = SoundName::new(&name)?;
+
+    sounds::remove(&data, guild_id, &name).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a sound's file, to preview it in the browser.
+async fn file(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path((guild_id, name)): Path<(u64, String)>,
+) -> Result<(HeaderMap, Vec<u8>), ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+    let name = SoundName::new(&name)?;
+
+    let (_, contents) = sounds::file(&data, guild_id, &name).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+
+    Ok((headers, contents))
+}
+
+async fn history(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path(guild_id): Path<u64>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<Vec<PlaybackInfo>>, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+    let name = match filter.name.filter(|name| !name.trim().is_empty()) {
+        Some(name) => Some(SoundName::new(&name)?),
+        None => None,
+    };
+
+    let playbacks = playbacks::history(&data, guild_id, name.as_ref())
+        .await?
+        .into_iter()
+        .map(|playback| PlaybackInfo {
+            id: playback.id,
+            name: playback.name,
+            player_id: playback.player_id.to_string(),
+            created_at: playback.created_at,
+            stopper_id: playback.stopper_id.map(|id| id.to_string()),
+            stopped_at: playback.stopped_at,
+            duration_ms: playback
+                .duration()
+                .map(|duration| duration.num_milliseconds()),
+            stop_reason: playback.stop_reason,
+        })
+        .collect();
+
+    Ok(Json(playbacks))
+}
+
+async fn stats(
+    User(session): User,
+    Extension(data): Extension<Arc<Data>>,
+    Path(guild_id): Path<u64>,
+) -> Result<Json<Stats>, ApiError> {
+    let guild_id = guild(&data, &session, guild_id).await?;
+
+    let stats = sounds::stats(&data, guild_id).await?;
+
+    Ok(Json(Stats {
+        usage: stats.usage,
+        limits: stats.limits,
+        most_played: stats
+            .most_played
+            .into_iter()
+            .map(|(name, plays)| Played { name, plays })
+            .collect(),
+    }))
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use axum::async_trait;
+use axum::extract::{Extension, FromRequest, Query, RequestParts};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::Url;
+use serde::Deserialize;
+
+use super::ApiError;
+use crate::config::DashboardConfig;
+use crate::Error;
+
+const AUTHORIZE_URL: &str = "https://discord.com/api/oauth2/authorize";
+const TOKEN_URL: &str = "https://discord.com/api/oauth2/token";
+const API_URL: &str = "https://discord.com/api";
+
+/// Cookie holding the id of someone's session.
+const SESSION_COOKIE: &str = "bernie_session";
+
+/// Cookie holding the random state sent to Discord on sign in, to check it comes back unchanged.
+const STATE_COOKIE: &str = "bernie_oauth_state";
+
+/// How long people stay signed in. Their guilds are only fetched on sign in, so this also bounds
+/// how long someone who left a guild can still manage its sounds.
+fn session_length() -> Duration {
+    Duration::days(1)
+}
+
+/// Signs people in with Discord and keeps track of their sessions.
+///
+/// Sessions are only kept in memory, so everyone has to sign in again after a restart.
+pub struct Auth {
+    config: DashboardConfig,
+    client: reqwest::Client,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+/// Someone signed in to the dashboard.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: UserId,
+    pub user_name: String,
+    /// The guilds they were in when they signed in, with their names.
+    pub guilds: HashMap<GuildId, String>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordGuild {
+    id: String,
+    name: String,
+}
+
+impl Auth {
+    pub fn new(config: DashboardConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn redirect_uri(&self) -> String {
+        format!("{}/auth/callback", self.config.public_url)
+    }
+
+    /// Attributes for cookies, only sent over HTTPS if the dashboard is served over it.
+    fn cookie_attributes(&self, max_age: i64) -> String {
+        let secure = if self.config.public_url.starts_with("https://") {
+            "; Secure"
+        } else {
+            ""
+        };
+        format!("Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}")
+    }
+
+    /// Get the unexpired session with the id `token`.
+    fn session(&self, token: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+
+        sessions
+            .get(token)
+            .filter(|session| session.expires_at > Utc::now())
+            .cloned()
+    }
+
+    /// Swap an authorization code from Discord for a new session, returning its id.
+    async fn sign_in(&self, code: &str) -> Result<String, Error> {
+        let redirect_uri = self.redirect_uri();
+        let token: TokenResponse = self
+            .client
+            .post(TOKEN_URL)
+            .form(&[
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let user: DiscordUser = self.get(&token, "/users/@me").await?;
+        let guilds: Vec<DiscordGuild> = self.get(&token, "/users/@me/guilds").await?;
+
+        let session = Session {
+            user_id: UserId(user.id.parse()?),
+            user_name: user.username,
+            guilds: guilds
+                .into_iter()
+                .map(|guild| Ok((GuildId(guild.id.parse()?), guild.name)))
+                .collect::<Result<_, Error>>()?,
+            expires_at: Utc::now() + session_length(),
+        };
+
+        let id = random_token();
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Utc::now();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(id.clone(), session);
+
+        Ok(id)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        token: &TokenResponse,
+        path: &str,
+    ) -> Result<T, Error> {
+        let response = self
+            .client
+            .get(format!("{API_URL}{path}"))
+            .bearer_auth(&token.access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response)
+    }
+}
+
+/// The session of whoever made a request, from its session cookie.
+pub struct User(pub Session);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for User {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(auth) = Extension::<Arc<Auth>>::from_request(req)
+            .await
+            .map_err(|_| ApiError::Unauthorized)?;
+
+        req.headers()
+            .and_then(|headers| cookie(headers, SESSION_COOKIE))
+            .and_then(|token| auth.session(token))
+            .map(User)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+/// Send people to Discord to sign in, asking for their name and guilds.
+pub async fn login(
+    Extension(auth): Extension<Arc<Auth>>,
+) -> Result<(StatusCode, HeaderMap), ApiError> {
+    let state = random_token();
+    let url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("client_id", auth.config.client_id.as_str()),
+            ("redirect_uri", auth.redirect_uri().as_str()),
+            ("response_type", "code"),
+            ("scope", "identify guilds"),
+            ("state", state.as_str()),
+            ("prompt", "none"),
+        ],
+    )?;
+
+    let cookie = format!("{STATE_COOKIE}={state}; {}", auth.cookie_attributes(600));
+    Ok(redirect(url.as_str(), &[cookie])?)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+}
+
+/// Where Discord sends people back to once they've signed in, or decided not to.
+pub async fn callback(
+    Extension(auth): Extension<Arc<Auth>>,
+    Query(callback): Query<Callback>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, HeaderMap), ApiError> {
+    let expected_state = cookie(&headers, STATE_COOKIE);
+    if expected_state.is_none() || expected_state != callback.state.as_deref() {
+        return Err(
+            anyhow!("Signing in took too long, or was started somewhere else. Try again.").into(),
+        );
+    }
+    let clear_state = format!("{STATE_COOKIE}=; {}", auth.cookie_attributes(0));
+
+    // without a code, they declined to sign in.
+    let code = match callback.code {
+        Some(code) => code,
+        None => return Ok(redirect("/", &[clear_state])?),
+    };
+
+    let session = auth.sign_in(&code).await.map_err(|error| {
+        tracing::warn!("Couldn't sign in with Discord: {error:#}");
+        anyhow!("Couldn't sign in with Discord. Try again.")
+    })?;
+
+    let cookie = format!(
+        "{SESSION_COOKIE}={session}; {}",
+        auth.cookie_attributes(session_length().num_seconds())
+    );
+    Ok(redirect("/", &[clear_state, cookie])?)
+}
+
+/// End the session of whoever's signed in, if anyone.
+pub async fn logout(
+    Extension(auth): Extension<Arc<Auth>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, HeaderMap), ApiError> {
+    if let Some(token) = cookie(&headers, SESSION_COOKIE) {
+        auth.sessions.lock().unwrap().remove(token);
+    }
+
+    let cookie = format!("{SESSION_COOKIE}=; {}", auth.cookie_attributes(0));
+    Ok(redirect("/", &[cookie])?)
+}
+
+/// Send the browser to `location`, setting `cookies` on the way.
+fn redirect(location: &str, cookies: &[String]) -> Result<(StatusCode, HeaderMap), Error> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::LOCATION, HeaderValue::from_str(location)?);
+    for cookie in cookies {
+        headers.append(header::SET_COOKIE, HeaderValue::from_str(cookie)?);
+    }
+
+    Ok((StatusCode::SEE_OTHER, headers))
+}
+
+/// Get the value of the cookie `name` sent with a request.
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// A random token for session ids and OAuth2 states.
+fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
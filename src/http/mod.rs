@@ -0,0 +1,111 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::Extension;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{AddExtensionLayer, Json, Router};
+use serde_json::json;
+
+use crate::config::DashboardConfig;
+use crate::services::health;
+use crate::{Data, Error};
+
+mod api;
+mod auth;
+mod play;
+
+/// Serve health checks, metrics and playing sounds with API tokens on `address` until the server
+/// fails, along with the dashboard and its API if it's configured.
+pub async fn serve(
+    address: SocketAddr,
+    data: Arc<Data>,
+    dashboard: Option<DashboardConfig>,
+) -> Result<(), Error> {
+    let mut app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .merge(play::routes());
+    if let Some(dashboard) = dashboard {
+        app = app.merge(api::routes(Arc::new(auth::Auth::new(dashboard))));
+    }
+    let app = app.layer(AddExtensionLayer::new(data));
+
+    tracing::info!("Serving HTTP on {address}.");
+    axum::Server::bind(&address)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+/// The process is up, whether or not it can do anything.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(Extension(data): Extension<Arc<Data>>) -> impl IntoResponse {
+    let readiness = health::readiness(&data).await;
+
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, readiness.to_string())
+}
+
+async fn metrics(Extension(data): Extension<Arc<Data>>) -> impl IntoResponse {
+    match data.metrics.gather(&data).await {
+        Ok(metrics) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(prometheus::TEXT_FORMAT),
+            );
+            Ok((headers, metrics))
+        }
+        Err(error) => {
+            tracing::error!("Couldn't gather metrics: {error:#}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Why an API request failed, answered with a JSON body like `{"error": "..."}`.
+#[derive(Debug)]
+pub enum ApiError {
+    /// Nobody's signed in, or their session expired.
+    Unauthorized,
+    /// The person signed in isn't in the guild, or Bernie isn't.
+    Forbidden,
+    /// Anything else, usually something the services turned down, with a message meant for
+    /// people like the ones slash commands reply with.
+    BadRequest(Error),
+}
+
+impl<E: Into<Error>> From<E> for ApiError {
+    fn from(error: E) -> Self {
+        Self::BadRequest(error.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Sign in first.".to_owned()),
+            Self::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "You and Bernie both have to be in that guild.".to_owned(),
+            ),
+            Self::BadRequest(error) => {
+                tracing::debug!("API request failed: {error:#}");
+                (StatusCode::BAD_REQUEST, error.to_string())
+            }
+        };
+
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
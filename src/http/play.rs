@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{Extension, FromRequest, Path, RequestParts};
+use axum::http::header;
+use axum::routing::post;
+use axum::{Json, Router};
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::{Deserialize, Serialize};
+
+use super::ApiError;
+use crate::services::{playbacks, tokens};
+use crate::sound_name::SoundName;
+use crate::Data;
+
+/// Playing sounds with API tokens from `/token`, for stream decks and the like.
+pub fn routes() -> Router {
+    Router::new().route("/guilds/:guild_id/play", post(play))
+}
+
+/// Whoever the request's `Authorization: Bearer <token>` header belongs to.
+pub struct TokenUser(pub UserId);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for TokenUser {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(data) = Extension::<Arc<Data>>::from_request(req)
+            .await
+            .map_err(|_| ApiError::Unauthorized)?;
+
+        let token = req
+            .headers()
+            .and_then(|headers| headers.get(header::AUTHORIZATION))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::Unauthorized)?;
+
+        tokens::authenticate(&data, token.trim())
+            .await?
+            .map(TokenUser)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayRequest {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Played {
+    playback_id: i32,
+}
+
+/// Play a sound in the voice channel its player is in, like `/play`.
+async fn play(
+    TokenUser(player_id): TokenUser,
+    Extension(data): Extension<Arc<Data>>,
+    Path(guild_id): Path<u64>,
+    Json(request): Json<PlayRequest>,
+) -> Result<Json<Played>, ApiError> {
+    let guild_id = GuildId(guild_id);
+    let name = SoundName::new(&request.name)?;
+
+    // only members can be in the guild's voice channels, so this also checks membership.
+    let guild = data
+        .cache
+        .get()
+        .and_then(|cache| cache.guild(guild_id))
+        .ok_or(ApiError::Forbidden)?;
+    if !guild.members.contains_key(&player_id) && !guild.voice_states.contains_key(&player_id) {
+        return Err(ApiError::Forbidden);
+    }
+    let channel_id = guild
+        .voice_states
+        .get(&player_id)
+        .and_then(|voice_state| voice_state.channel_id);
+    let roles = guild
+        .members
+        .get(&player_id)
+        .map(|member| member.roles.clone())
+        .unwrap_or_default();
+
+    data.db.ensure_guild(guild_id).await?;
+    let playback_id =
+        playbacks::play(&data, guild_id, channel_id, player_id, &roles, &name).await?;
+
+    Ok(Json(Played { playback_id }))
+}
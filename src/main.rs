@@ -1,64 +1,213 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::Context as _;
+use clap::Parser;
 use dotenv::dotenv;
+use serenity::cache::Cache;
+use serenity::gateway::ConnectionStage;
 use serenity::model::prelude::*;
-use sqlx::PgPool;
-
-use commands::{TrackManager, COMMANDS};
+use songbird::Songbird;
+
+use cli::{Cli, Command};
+use commands::COMMANDS;
+use config::{Config, LogFormat};
+use cooldown::{CooldownSettings, Cooldowns};
+use db::Repository;
+use media::{Downloader, FfProbe, Prober, TimeLimited, YtDlp};
+use metrics::{MeasuredDownloader, Metrics};
+use quota::Limits;
+use services::sounds::PendingAdds;
+use store::SoundStore;
 use tokio::sync::OnceCell;
+use tracing_subscriber::EnvFilter;
+use voice::{SongbirdPlayer, VoicePlayer};
+
+/// Define a command that's traced in a `command` span, which records who ran it and where.
+/// Commands that play a sound can record its `playback_id` in the span too.
+macro_rules! command {
+    (
+        $(#[$($attr:tt)*])*
+        $vis:vis async fn $name:ident($ctx:ident: $($params:tt)*) -> $ret:ty { $($body:tt)* }
+    ) => {
+        $(#[$($attr)*])*
+        #[tracing::instrument(
+            name = "command",
+            skip_all,
+            fields(
+                command = %$ctx.command().name,
+                guild_id = ?$ctx.guild_id(),
+                user_id = %$ctx.author().id,
+                playback_id = tracing::field::Empty,
+            )
+        )]
+        $vis async fn $name($ctx: $($params)*) -> $ret { $($body)* }
+    };
+}
 
+mod archive;
+mod cli;
 mod commands;
+mod config;
+mod cooldown;
+mod db;
+#[cfg(test)]
+mod fixture;
+mod http;
+mod media;
+mod metrics;
+mod quota;
+mod services;
+mod sound_name;
+mod soundbert;
+mod storage;
+mod store;
+mod voice;
 
 pub type Error = anyhow::Error;
-pub type Context<'a> = poise::Context<'a, Data, Error>;
+pub type Context<'a> = poise::Context<'a, Arc<Data>, Error>;
+
+/// How long sounds being added get to finish when shutting down, before they're cancelled.
+const ADD_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct Data {
-    db: PgPool,
+    db: Box<dyn Repository>,
+    /// Local working directory for exports, imports and the like.
+    /// Also holds sound files when they're stored locally.
     storage_dir: PathBuf,
-    track_manager: TrackManager,
+    store: Box<dyn SoundStore>,
+    /// Limits for guilds that don't override them.
+    limits: Limits,
+    /// Recent plays, to hold people to cooldowns.
+    cooldowns: Cooldowns,
+    voice: Box<dyn VoicePlayer>,
+    downloader: Box<dyn Downloader>,
+    prober: Box<dyn Prober>,
+    /// Sounds being added, so they can be cancelled.
+    adds: PendingAdds,
+    metrics: Arc<Metrics>,
+    /// Discord's cache, once the bot has connected.
+    cache: OnceCell<Arc<Cache>>,
+    /// Whether the bot is connected to Discord's gateway.
+    gateway_connected: AtomicBool,
 }
 
 impl Data {
-    pub fn new<P: AsRef<Path>>(db: PgPool, storage_dir: P) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<P: AsRef<Path>>(
+        db: Box<dyn Repository>,
+        storage_dir: P,
+        store: Box<dyn SoundStore>,
+        limits: Limits,
+        cooldowns: CooldownSettings,
+        voice: Box<dyn VoicePlayer>,
+        downloader: Box<dyn Downloader>,
+        prober: Box<dyn Prober>,
+    ) -> Self {
+        let metrics = Arc::new(Metrics::new());
+
         Self {
             db,
             storage_dir: storage_dir.as_ref().to_path_buf(),
-            track_manager: TrackManager::new(),
+            store,
+            limits,
+            cooldowns: Cooldowns::new(cooldowns),
+            voice,
+            downloader: Box::new(MeasuredDownloader::new(downloader, metrics.clone())),
+            prober,
+            adds: PendingAdds::new(),
+            metrics,
+            cache: OnceCell::new(),
+            gateway_connected: AtomicBool::new(false),
         }
     }
+
+    /// Like [`Data::load_with_voice`], but with a voice player that isn't hooked up to Discord,
+    /// for when no sounds are played.
+    pub async fn load(config: &Config) -> Result<Self, Error> {
+        Self::load_with_voice(config, Box::new(SongbirdPlayer::new(Songbird::serenity()))).await
+    }
+
+    /// Set up a migrated database, the storage directory, sound store, limits, yt-dlp and ffprobe
+    /// as configured, playing sounds with `voice`.
+    pub async fn load_with_voice(
+        config: &Config,
+        voice: Box<dyn VoicePlayer>,
+    ) -> Result<Self, Error> {
+        let db = connect_database(config).await?;
+        migrate_database(&*db).await?;
+        let storage_dir = create_storage_dir(config).await?;
+        let store = store::from_config(&config.store, &storage_dir)
+            .await
+            .context("Couldn't set up sound store")?;
+        storage::convert_legacy_files(&*db, &storage_dir, &*store)
+            .await
+            .context("Couldn't move sound files into the store")?;
+        storage::fill_file_sizes(&*db, &*store)
+            .await
+            .context("Couldn't record the size of sound files")?;
+
+        let yt_dlp = YtDlp::new(
+            config.yt_dlp.path.clone(),
+            config.download_retries,
+            config.yt_dlp.args.clone(),
+        );
+        let downloader: Box<dyn Downloader> = match config.download_timeout {
+            Some(timeout) => Box::new(TimeLimited::new(yt_dlp, timeout)),
+            None => Box::new(yt_dlp),
+        };
+        let prober = FfProbe::new(config.ffprobe.path.clone(), config.ffprobe.args.clone());
+
+        Ok(Self::new(
+            db,
+            storage_dir,
+            store,
+            config.limits,
+            config.cooldowns.clone(),
+            voice,
+            downloader,
+            Box::new(prober),
+        ))
+    }
 }
 
 const OAUTH_SCOPES: [OAuth2Scope; 2] = [OAuth2Scope::Bot, OAuth2Scope::ApplicationsCommands];
 
 const PERMISSIONS: [Permissions; 2] = [Permissions::SPEAK, Permissions::CONNECT];
 
-/// Register slash commands.
-/// No argument to register with current guild; `global` as argument to register globally.
-#[poise::command(prefix_command, hide_in_help)]
-async fn register(ctx: Context<'_>, #[flag] global: bool) -> Result<(), Error> {
-    poise::builtins::register_application_commands(ctx, global).await?;
+command! {
+    /// Register slash commands.
+    /// No argument to register with current guild; `global` as argument to register globally.
+    #[poise::command(prefix_command, hide_in_help)]
+    async fn register(ctx: Context<'_>, #[flag] global: bool) -> Result<(), Error> {
+        poise::builtins::register_application_commands(ctx, global).await?;
 
-    Ok(())
+        Ok(())
+    }
 }
 
-/// Send help.
-#[poise::command(slash_command, prefix_command)]
-async fn help(
-    ctx: Context<'_>,
-    #[description = "Help"]
-    #[autocomplete = "poise::builtins::autocomplete_command"]
-    command: Option<String>,
-) -> Result<(), Error> {
-    poise::builtins::help(
-        ctx,
-        command.as_deref(),
-        poise::builtins::HelpConfiguration {
-            ..Default::default()
-        },
-    )
-    .await?;
-    Ok(())
+command! {
+    /// Send help.
+    #[poise::command(slash_command, prefix_command)]
+    async fn help(
+        ctx: Context<'_>,
+        #[description = "Help"]
+        #[autocomplete = "poise::builtins::autocomplete_command"]
+        command: Option<String>,
+    ) -> Result<(), Error> {
+        poise::builtins::help(
+            ctx,
+            command.as_deref(),
+            poise::builtins::HelpConfiguration {
+                ..Default::default()
+            },
+        )
+        .await?;
+        Ok(())
+    }
 }
 
 static INVITE: OnceCell<String> = OnceCell::const_new();
@@ -83,97 +232,144 @@ async fn get_invite(ctx: Context<'_>) -> Result<&'static String, Error> {
         .await
 }
 
-/// Get the invite for this bot.
-#[poise::command(slash_command, prefix_command)]
-async fn invite(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say(get_invite(ctx).await?).await?;
+command! {
+    /// Get the invite for this bot.
+    #[poise::command(slash_command, prefix_command)]
+    async fn invite(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.say(get_invite(ctx).await?).await?;
 
-    Ok(())
+        Ok(())
+    }
 }
-/// Get info about the bot.
-#[poise::command(slash_command, prefix_command)]
-async fn about(ctx: Context<'_>) -> Result<(), Error> {
-    // todo: add library information.
-    let author = {
-        // we're (i'm) going to assume only one author.
-        let original = env!("CARGO_PKG_AUTHORS");
-        if let Some((name, _)) = original.split_once(" ") {
-            name
-        } else {
-            original
-        }
-        .trim()
-    };
 
-    let name = env!("CARGO_PKG_NAME");
-    let version = env!("CARGO_PKG_VERSION");
-    let repo = env!("CARGO_PKG_REPOSITORY");
+command! {
+    /// Get info about the bot.
+    #[poise::command(slash_command, prefix_command)]
+    async fn about(ctx: Context<'_>) -> Result<(), Error> {
+        // todo: add library information.
+        let author = {
+            // we're (i'm) going to assume only one author.
+            let original = env!("CARGO_PKG_AUTHORS");
+            if let Some((name, _)) = original.split_once(" ") {
+                name
+            } else {
+                original
+            }
+            .trim()
+        };
 
-    let about = format!(
-        "{name} v{version}\n\
-        by `{author}`\n\
-        source: {repo}",
-    );
+        let name = env!("CARGO_PKG_NAME");
+        let version = env!("CARGO_PKG_VERSION");
+        let repo = env!("CARGO_PKG_REPOSITORY");
 
-    let message = if let Ok(invite) = get_invite(ctx).await {
-        format!("{about}\ninvite: {invite}")
-    } else {
-        about.to_owned()
-    };
+        let about = format!(
+            "{name} v{version}\n\
+            by `{author}`\n\
+            source: {repo}",
+        );
 
-    ctx.say(message).await?;
-    Ok(())
+        let message = if let Ok(invite) = get_invite(ctx).await {
+            format!("{about}\ninvite: {invite}")
+        } else {
+            about.to_owned()
+        };
+
+        ctx.say(message).await?;
+        Ok(())
+    }
 }
 
-async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
-    log::warn!("Encountered error: {error:#?}");
-    match error {
+async fn on_error(error: poise::FrameworkError<'_, Arc<Data>, Error>) {
+    match &error {
         poise::FrameworkError::Setup { error } => panic!("Failed to start bot: {error:?}"),
-        // poise::FrameworkError::Command { error, ctx } => {
-        //     log::error!("Error in command `{}`: {error:#?}", ctx.command().name);
-        // }
-        error => {
-            if let Err(e) = poise::builtins::on_error(error).await {
-                log::error!("Error while handling error: {e:#?}");
-            }
+        poise::FrameworkError::Command { error, ctx } => {
+            let command = ctx.command().name;
+            ctx.data().metrics.command_finished(command, false);
+            tracing::warn!(
+                command,
+                guild_id = ?ctx.guild_id(),
+                user_id = %ctx.author().id,
+                "Command failed: {error:#}"
+            );
         }
+        error => tracing::warn!("Encountered error: {error:?}"),
     }
-}
 
-async fn do_main() {
-    dotenv().ok();
-    env_logger::init();
+    if let Err(error) = poise::builtins::on_error(error).await {
+        tracing::error!("Error while handling error: {error:#}");
+    }
+}
 
-    log::info!(
-        "Initializing {} v{}.",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    );
+async fn connect_database(config: &Config) -> Result<Box<dyn Repository>, Error> {
+    let db = db::connect(&config.database_url)
+        .await
+        .context("Couldn't connect to database")?;
+    tracing::debug!("Connected to database.");
 
-    let token = std::env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment.");
-    let db_uri = std::env::var("DATABASE_URL").expect("Expected DATABASE_URL in environment.");
-    let storage_dir = std::env::var("STORAGE_DIR").expect("Expected STORAGE_DIR in environment.");
+    Ok(db)
+}
 
-    let db = PgPool::connect(&db_uri)
+async fn migrate_database(db: &dyn Repository) -> Result<(), Error> {
+    db.migrate()
         .await
-        .expect("Couldn't connect to database.");
-    log::debug!("Connected to database.");
+        .context("Error while migrating database")?;
+    tracing::debug!("Database migrated.");
 
-    sqlx::migrate!()
-        .run(&db)
-        .await
-        .expect("Error while migrating database.");
-    log::debug!("Database migrated.");
+    Ok(())
+}
 
-    let storage_dir = PathBuf::from(&storage_dir);
+async fn create_storage_dir(config: &Config) -> Result<PathBuf, Error> {
+    let storage_dir = config.storage_dir.clone();
     tokio::fs::create_dir_all(&storage_dir)
         .await
-        .unwrap_or_else(|_| panic!("Couldn't create storage directory: {storage_dir:?}"));
-    log::debug!("Storage directory at {storage_dir:?} created.");
+        .with_context(|| format!("Couldn't create storage directory {storage_dir:?}"))?;
+    tracing::debug!("Storage directory at {storage_dir:?} created.");
 
+    Ok(storage_dir)
+}
+
+fn all_commands() -> Vec<poise::Command<Arc<Data>, Error>> {
     let mut commands = vec![register(), help(), invite(), about()];
     commands.extend(Vec::from(COMMANDS.map(|f| f())));
-    log::debug!(
+    commands
+}
+
+async fn do_main(config: &Config) -> Result<(), Error> {
+    tracing::info!(
+        "Initializing {} v{}.",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let token = config.discord_token()?.to_owned();
+    let songbird = Songbird::serenity();
+    let data =
+        Data::load_with_voice(config, Box::new(SongbirdPlayer::new(songbird.clone()))).await?;
+    let data = Arc::new(data);
+
+    storage::check_on_startup(
+        config.storage_check,
+        &*data.db,
+        &data.storage_dir,
+        &*data.store,
+        &*data.downloader,
+        &*data.prober,
+    )
+    .await
+    .context("Error while checking storage")?;
+
+    if let Some(address) = config.http_address {
+        let data = data.clone();
+        let dashboard = config.dashboard.clone();
+        tokio::spawn(async move {
+            if let Err(error) = http::serve(address, data, dashboard).await {
+                tracing::error!("HTTP server stopped: {error:#}");
+            }
+        });
+    }
+
+    let commands = all_commands();
+    tracing::debug!(
         "Found commands: {:?}.",
         commands.iter().map(|c| c.name).collect::<Vec<&str>>()
     );
@@ -185,11 +381,26 @@ async fn do_main() {
             ..Default::default()
         },
         on_error: |error| Box::pin(on_error(error)),
-        listener: move |_ctx, event, _framework, _data| {
+        post_command: |ctx| {
+            Box::pin(async move {
+                ctx.data()
+                    .metrics
+                    .command_finished(ctx.command().name, true);
+            })
+        },
+        listener: move |_ctx, event, _framework, data| {
             Box::pin(async move {
-                if let poise::Event::Ready { .. } = event {
-                    log::info!("Ready.")
-                };
+                match event {
+                    poise::Event::Ready { .. } => {
+                        tracing::info!("Ready.");
+                        data.gateway_connected.store(true, Ordering::Relaxed);
+                    }
+                    poise::Event::ShardStageUpdate { update } => {
+                        let connected = update.new == ConnectionStage::Connected;
+                        data.gateway_connected.store(connected, Ordering::Relaxed);
+                    }
+                    _ => {}
+                }
                 Ok(())
             })
         },
@@ -198,49 +409,114 @@ async fn do_main() {
 
     let framework = poise::Framework::build()
         .token(token)
-        .user_data_setup(move |_ctx, _ready, _framework| {
-            Box::pin(async move { Ok(Data::new(db, storage_dir)) })
+        .user_data_setup({
+            let data = data.clone();
+            move |ctx, _ready, _framework| {
+                Box::pin(async move {
+                    let _ = data.cache.set(ctx.cache.clone());
+                    Ok(data)
+                })
+            }
         })
         .options(options)
-        .client_settings(songbird::register)
+        .client_settings(move |client| songbird::register_with(client, songbird))
         .build()
         .await
-        .expect("Couldn't build command framework.");
+        .context("Couldn't build command framework")?;
+    let shard_manager = framework.shard_manager();
 
-    framework
-        .start()
-        .await
-        .expect("Couldn't start command framework.");
+    tokio::select! {
+        result = framework.start() => match result {
+            Ok(()) => tracing::error!("Bot crashed!"),
+            Err(error) => tracing::error!("Couldn't start command framework: {error}"),
+        },
+        _ = shutdown_signal() => {}
+    }
+
+    // leave voice channels while still connected to the gateway, then disconnect.
+    shut_down(&data).await;
+    shard_manager.lock().await.shutdown_all().await;
+    data.db.close().await;
+
+    Ok(())
+}
+
+/// Wait for SIGTERM or SIGINT, or CTRL-C on Windows.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen to SIGTERM.");
+        let mut sigint = signal(SignalKind::interrupt()).expect("Failed to listen to SIGINT");
+        tokio::select! {
+            _ = sigterm.recv() => tracing::info!("Received SIGTERM. Shutting down..."),
+            _ = sigint.recv() => tracing::info!("Received SIGINT. Shutting down...")
+        }
+    }
+    #[cfg(windows)]
+    {
+        use tokio::signal::windows::ctrl_c;
+        let mut ctrl_c = ctrl_c().expect("Failed to listen to CTRL-C");
+        ctrl_c.recv().await;
+        tracing::info!("Received CTRL-C. Shutting down...");
+    }
+}
+
+/// Let sounds being added finish, and stop everything that's playing.
+async fn shut_down(data: &Data) {
+    services::sounds::finish_adds(data, ADD_GRACE_PERIOD).await;
+
+    match services::playbacks::stop_all(data, services::playbacks::SHUTDOWN).await {
+        Ok(stopped) => tracing::info!("Stopped {} sounds.", stopped.len()),
+        Err(error) => tracing::error!("Couldn't stop everything playing: {error:#}"),
+    }
+}
+
+async fn run(config: &Config) -> Result<(), Error> {
+    do_main(config).await?;
+    tracing::info!("Goodbye.");
+
+    Ok(())
+}
+
+/// Log to stderr as filtered by `RUST_LOG`, in `format`.
+fn init_logging(format: LogFormat) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).init(),
+    }
 }
 
 fn main() {
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            #[cfg(target_os = "linux")]
-            {
-                use tokio::signal::unix::{signal, SignalKind};
-                let mut sigterm =
-                    signal(SignalKind::terminate()).expect("Failed to listen to SIGTERM.");
-                let mut sigint =
-                    signal(SignalKind::interrupt()).expect("Failed to listen to SIGINT");
-                tokio::select! {
-                    _ = do_main() => log::error!("Bot crashed!"),
-                    _ = sigterm.recv() => log::info!("Received SIGTERM. Shutting down..."),
-                    _ = sigint.recv() => log::info!("Received SIGINT. Shutting down...")
-                }
-            }
-            #[cfg(target_os = "windows")]
-            {
-                use tokio::signal::windows::ctrl_c;
-                let mut ctrl_c = ctrl_c().expect("Failed to listen to CTRL-C");
-                tokio::select! {
-                    _ = do_main() => log::error!("Bot crashed!"),
-                    _ = ctrl_c.recv() => log::info!("Received CTRL-C. Shutting down...")
+    dotenv().ok();
+
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref());
+    // problems with the config are logged as text, since it's what says how to log.
+    init_logging(
+        config
+            .as_ref()
+            .map_or_else(|_| LogFormat::default(), |config| config.log_format),
+    );
+
+    let result = config.and_then(|config| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                match cli.command.unwrap_or(Command::Run) {
+                    Command::Run => run(&config).await,
+                    Command::Admin(command) => cli::execute(command, &config).await,
                 }
-            }
-        });
-    log::info!("Goodbye.");
+            })
+    });
+
+    if let Err(error) = result {
+        tracing::error!("{error:#}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+use super::{
+    nothing_downloaded, too_large, DownloadLimits, Downloader, Prober, SoundMetadata, NOT_A_SOUND,
+};
+use crate::Error;
+
+#[derive(Debug, Clone)]
+enum FakeSource {
+    /// Downloads as `contents`, which can only be probed if they have a `length`.
+    File {
+        contents: Vec<u8>,
+        length: Option<i32>,
+        metadata: SoundMetadata,
+    },
+    Failure(String),
+    /// Never finishes downloading.
+    Hang,
+}
+
+/// Pretends to download and probe sounds, without running anything.
+///
+/// Only registered sources can be downloaded, and only their files can be probed; everything
+/// else fails the way yt-dlp and ffprobe would. Clones share sources.
+#[derive(Debug, Clone, Default)]
+pub struct FakeMedia {
+    sources: Arc<Mutex<HashMap<String, FakeSource>>>,
+}
+
+impl FakeMedia {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `source` download as a file containing `contents` that's `length` milliseconds long.
+    pub fn add_sound(&self, source: &str, contents: &[u8], length: i32) {
+        let metadata = SoundMetadata {
+            original_url: Some(source.to_owned()),
+            ..Default::default()
+        };
+        self.add_sound_with_metadata(source, contents, length, metadata);
+    }
+
+    /// Like [`Self::add_sound`], but with yt-dlp reporting `metadata`.
+    pub fn add_sound_with_metadata(
+        &self,
+        source: &str,
+        contents: &[u8],
+        length: i32,
+        metadata: SoundMetadata,
+    ) {
+        self.add(
+            source,
+            FakeSource::File {
+                contents: contents.to_vec(),
+                length: Some(length),
+                metadata,
+            },
+        );
+    }
+
+    /// Make `source` download as a file containing `contents` that isn't a sound.
+    pub fn add_file(&self, source: &str, contents: &[u8]) {
+        self.add(
+            source,
+            FakeSource::File {
+                contents: contents.to_vec(),
+                length: None,
+                metadata: SoundMetadata::default(),
+            },
+        );
+    }
+
+    /// Make downloading `source` fail with `message`.
+    pub fn add_failure(&self, source: &str, message: &str) {
+        self.add(source, FakeSource::Failure(message.to_owned()));
+    }
+
+    /// Make downloading `source` hang forever.
+    pub fn add_hang(&self, source: &str) {
+        self.add(source, FakeSource::Hang);
+    }
+
+    fn add(&self, source: &str, fake: FakeSource) {
+        self.sources.lock().unwrap().insert(source.to_owned(), fake);
+    }
+}
+
+#[async_trait]
+impl Downloader for FakeMedia {
+    async fn download(
+        &self,
+        source: &str,
+        dest: &Path,
+        limits: DownloadLimits,
+    ) -> Result<SoundMetadata, Error> {
+        let fake = self.sources.lock().unwrap().get(source).cloned();
+
+        let (contents, length, metadata) = match fake {
+            Some(FakeSource::File {
+                contents,
+                length,
+                metadata,
+            }) => (contents, length, metadata),
+            Some(FakeSource::Failure(message)) => return Err(anyhow!(message)),
+            Some(FakeSource::Hang) => std::future::pending().await,
+            None => return Err(anyhow!("Couldn't download a sound from {source}.")),
+        };
+        if let (Some(length), Some(max_length)) = (length, limits.max_length) {
+            if length > max_length {
+                return Err(nothing_downloaded(source, limits.max_length));
+            }
+        }
+        if let Some(max_size) = limits.max_size {
+            if contents.len() as i64 > max_size {
+                return Err(too_large(source, max_size));
+            }
+        }
+
+        tokio::fs::write(dest, contents).await?;
+
+        Ok(metadata)
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Prober for FakeMedia {
+    async fn probe_length(&self, location: &OsStr) -> Result<i32, Error> {
+        let contents = tokio::fs::read(location).await?;
+
+        self.sources
+            .lock()
+            .unwrap()
+            .values()
+            .find_map(|fake| match fake {
+                FakeSource::File {
+                    contents: file,
+                    length,
+                    ..
+                } if *file == contents => *length,
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!(NOT_A_SOUND))
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::ffi::OsStr;
+use std::fmt::Debug;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::quota::{format_length, format_size};
+use crate::Error;
+
+#[cfg(test)]
+mod fake;
+mod process;
+
+#[cfg(test)]
+pub use self::fake::FakeMedia;
+pub use self::process::{check_program, FfProbe, YtDlp};
+
+/// Limits on what a [`Downloader`] will fetch. `None` means there's no limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DownloadLimits {
+    /// Longest a sound can be, in milliseconds.
+    pub max_length: Option<i32>,
+    /// Largest a download can be, in bytes.
+    pub max_size: Option<i64>,
+}
+
+/// What yt-dlp knows about a sound's source. Fields it didn't report are `None`.
+///
+/// Field names match yt-dlp's JSON output, so it can be deserialized directly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundMetadata {
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    /// The URL the sound was downloaded from, as given.
+    pub original_url: Option<String>,
+    pub thumbnail: Option<String>,
+    /// Name of the yt-dlp extractor used, e.g. `youtube`.
+    pub extractor: Option<String>,
+    /// Length of the source in seconds.
+    pub duration: Option<f64>,
+}
+
+/// Fetches sounds from wherever users say they are.
+#[async_trait]
+pub trait Downloader: Debug + Send + Sync {
+    /// Download the sound at `source` and write it to `dest`, returning what's known about it.
+    ///
+    /// Sounds known to be too long or too large aren't downloaded at all, and downloads are
+    /// stopped as soon as they go over `limits.max_size`. Sounds of unknown length still need to
+    /// be probed afterwards.
+    async fn download(
+        &self,
+        source: &str,
+        dest: &Path,
+        limits: DownloadLimits,
+    ) -> Result<SoundMetadata, Error>;
+
+    /// Make sure downloads can be started at all, e.g. that yt-dlp is installed.
+    async fn check(&self) -> Result<(), Error>;
+}
+
+/// Gives up on downloads that take longer than `timeout`.
+///
+/// Downloads are dropped when they time out, which kills any process they were running.
+#[derive(Debug)]
+pub struct TimeLimited<D> {
+    inner: D,
+    timeout: Duration,
+}
+
+impl<D: Downloader> TimeLimited<D> {
+    pub fn new(inner: D, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+#[async_trait]
+impl<D: Downloader> Downloader for TimeLimited<D> {
+    async fn download(
+        &self,
+        source: &str,
+        dest: &Path,
+        limits: DownloadLimits,
+    ) -> Result<SoundMetadata, Error> {
+        tokio::time::timeout(self.timeout, self.inner.download(source, dest, limits))
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Downloading from {source} took longer than {} seconds.",
+                    self.timeout.as_secs_f64()
+                )
+            })?
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        self.inner.check().await
+    }
+}
+
+/// Reads sound files.
+#[async_trait]
+pub trait Prober: Debug + Send + Sync {
+    /// Get the length of the sound at `location`, which can be anything ffmpeg can read, in
+    /// milliseconds.
+    async fn probe_length(&self, location: &OsStr) -> Result<i32, Error>;
+
+    /// Make sure sounds can be probed at all, e.g. that ffprobe is installed.
+    async fn check(&self) -> Result<(), Error>;
+}
+
+fn too_large(source: &str, max_size: i64) -> Error {
+    anyhow!(
+        "The sound at {source} is larger than {}.",
+        format_size(max_size)
+    )
+}
+
+fn nothing_downloaded(source: &str, max_length: Option<i32>) -> Error {
+    match max_length {
+        Some(max_length) => anyhow!(
+            "Nothing was downloaded from {source}. It might be longer than {}.",
+            format_length(max_length)
+        ),
+        None => anyhow!("Nothing was downloaded from {source}."),
+    }
+}
+
+const NOT_A_SOUND: &str =
+    "Couldn't determine the length of the sound; it's probably not a sound file.";
@@ -0,0 +1,276 @@
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    nothing_downloaded, too_large, DownloadLimits, Downloader, Prober, SoundMetadata, NOT_A_SOUND,
+};
+use crate::Error;
+
+/// Downloads sounds by running yt-dlp.
+#[derive(Debug, Clone)]
+pub struct YtDlp {
+    program: OsString,
+    /// How many times failed requests are retried.
+    retries: u64,
+    /// Added after Bernie's own arguments, so they can override them.
+    extra_args: Vec<String>,
+}
+
+impl YtDlp {
+    pub fn new<P: Into<OsString>>(program: P, retries: u64, extra_args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            retries,
+            extra_args,
+        }
+    }
+
+    /// Arguments to download `source` to stdout with, within `limits`.
+    fn args(&self, source: &str, limits: DownloadLimits) -> Vec<String> {
+        let mut args = vec![
+            "--quiet".to_owned(),
+            // print metadata as json, but still download. since the download goes to stdout, the
+            // json ends up on stderr.
+            "--dump-json".to_owned(),
+            "--no-simulate".to_owned(),
+            "-f".to_owned(),
+            "webm[abr>0]/bestaudio/best".to_owned(),
+            "-R".to_owned(),
+            self.retries.to_string(),
+            "--no-playlist".to_owned(),
+            "--ignore-config".to_owned(),
+            "--no-warnings".to_owned(),
+        ];
+        if let Some(max_length) = limits.max_length {
+            // `<=?` lets sources without a known duration through; they're probed afterwards.
+            args.push("--match-filter".to_owned());
+            args.push(format!("duration <=? {}", max_length as f64 / 1000.0));
+        }
+        if let Some(max_size) = limits.max_size {
+            args.push("--max-filesize".to_owned());
+            args.push(max_size.to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args.extend([source.to_owned(), "-o".to_owned(), "-".to_owned()]);
+
+        args
+    }
+}
+
+#[async_trait]
+impl Downloader for YtDlp {
+    #[tracing::instrument(name = "yt-dlp", skip(self, dest, limits))]
+    async fn download(
+        &self,
+        source: &str,
+        dest: &Path,
+        limits: DownloadLimits,
+    ) -> Result<SoundMetadata, Error> {
+        let mut ytdl = tokio::process::Command::new(&self.program)
+            .args(self.args(source, limits))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Couldn't run {:?}.", self.program))?;
+
+        // read stderr alongside stdout, so yt-dlp doesn't block on either.
+        let mut stderr = ytdl.stderr.take().unwrap();
+        let stderr = tokio::spawn(async move {
+            let mut output = vec![];
+            stderr.read_to_end(&mut output).await.map(|_| output)
+        });
+
+        // copy the download to `dest` as it comes in so it can be cut off once it's too large.
+        let mut stdout = ytdl.stdout.take().unwrap();
+        let mut file = tokio::fs::File::create(dest).await?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut written = 0;
+        loop {
+            let read = stdout.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+
+            written += read as i64;
+            if let Some(max_size) = limits.max_size {
+                if written > max_size {
+                    ytdl.kill().await?;
+                    return Err(too_large(source, max_size));
+                }
+            }
+
+            file.write_all(&buffer[..read]).await?;
+        }
+        file.flush().await?;
+
+        if !ytdl.wait().await?.success() {
+            bail!("Couldn't download a sound from {source}.");
+        }
+
+        // yt-dlp skips sources that don't match the filters without failing.
+        if written == 0 {
+            return Err(nothing_downloaded(source, limits.max_length));
+        }
+
+        Ok(parse_metadata(source, &stderr.await??))
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        check_program(&self.program, "--version").await
+    }
+}
+
+/// Find yt-dlp's json output among anything else it printed to stderr.
+///
+/// The metadata is only informational, so sounds without any are still added.
+fn parse_metadata(source: &str, stderr: &[u8]) -> SoundMetadata {
+    let metadata = String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .filter(|line| line.starts_with('{'))
+        .find_map(|line| serde_json::from_str(line).ok());
+
+    metadata.unwrap_or_else(|| {
+        tracing::warn!("yt-dlp didn't print any metadata for {source}.");
+        SoundMetadata::default()
+    })
+}
+
+/// Probes sounds by running ffprobe.
+#[derive(Debug, Clone)]
+pub struct FfProbe {
+    program: OsString,
+    /// Added before Bernie's own arguments.
+    extra_args: Vec<String>,
+}
+
+impl FfProbe {
+    pub fn new<P: Into<OsString>>(program: P, extra_args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            extra_args,
+        }
+    }
+}
+
+#[async_trait]
+impl Prober for FfProbe {
+    #[tracing::instrument(name = "ffprobe", skip(self))]
+    async fn probe_length(&self, location: &OsStr) -> Result<i32, Error> {
+        // using ffprobe here because yt-dlp's `metadata["duration"]` is unreliable
+        static FFPROBE_ARGS: [&str; 6] = [
+            "-v",
+            "quiet",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ];
+
+        let ffprobe_output = tokio::process::Command::new(&self.program)
+            .args(&self.extra_args)
+            .arg(location)
+            .args(&FFPROBE_ARGS)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .with_context(|| format!("Couldn't run {:?}.", self.program))?;
+
+        let length = std::str::from_utf8(&ffprobe_output.stdout)?
+            .trim()
+            .parse::<f64>()
+            .context(NOT_A_SOUND)?
+            * 1000.0;
+
+        Ok(length as i32)
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        check_program(&self.program, "-version").await
+    }
+}
+
+/// Make sure `program` can be run, by running it with only `version_arg`.
+pub async fn check_program(program: &OsStr, version_arg: &str) -> Result<(), Error> {
+    let status = tokio::process::Command::new(program)
+        .arg(version_arg)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("Couldn't run {program:?}."))?;
+
+    if !status.success() {
+        bail!("{program:?} exited with {status}.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_are_limited_with_filters_yt_dlp_understands() {
+        let ytdl = YtDlp::new(
+            "yt-dlp",
+            3,
+            vec!["--proxy".to_owned(), "socks5://proxy".to_owned()],
+        );
+        let limits = DownloadLimits {
+            max_length: Some(90_500),
+            max_size: Some(10 * 1024 * 1024),
+        };
+
+        assert_eq!(
+            ytdl.args("https://example.com/bonk", limits),
+            [
+                "--quiet",
+                "--dump-json",
+                "--no-simulate",
+                "-f",
+                "webm[abr>0]/bestaudio/best",
+                "-R",
+                "3",
+                "--no-playlist",
+                "--ignore-config",
+                "--no-warnings",
+                "--match-filter",
+                "duration <=? 90.5",
+                "--max-filesize",
+                "10485760",
+                "--proxy",
+                "socks5://proxy",
+                "https://example.com/bonk",
+                "-o",
+                "-",
+            ]
+        );
+    }
+
+    #[test]
+    fn unlimited_downloads_have_no_filters() {
+        let ytdl = YtDlp::new("yt-dlp", 0, vec![]);
+
+        let args = ytdl.args("https://example.com/bonk", DownloadLimits::default());
+
+        assert!(!args.iter().any(|arg| arg == "--match-filter"));
+        assert!(!args.iter().any(|arg| arg == "--max-filesize"));
+        assert!(args.ends_with(&[
+            "https://example.com/bonk".to_owned(),
+            "-o".to_owned(),
+            "-".to_owned()
+        ]));
+    }
+}
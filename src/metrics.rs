@@ -0,0 +1,232 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::media::{DownloadLimits, Downloader, SoundMetadata};
+use crate::{Data, Error};
+
+/// Counters and histograms exported on `/metrics`.
+///
+/// Every instance has its own registry, so tests don't share metrics.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// Commands run, by command name and outcome (`ok` or `error`).
+    pub commands: IntCounterVec,
+    pub playbacks_started: IntCounter,
+    pub playbacks_stopped: IntCounter,
+    /// How long downloads took, in seconds, whether they succeeded or not.
+    pub download_seconds: Histogram,
+    pub download_failures: IntCounter,
+    // these are sampled when metrics are gathered.
+    voice_connections: IntGauge,
+    playing_tracks: IntGauge,
+    db_connections: IntGauge,
+    db_idle_connections: IntGauge,
+    cached_guilds: IntGauge,
+    cached_users: IntGauge,
+    pending_adds: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("bernie".to_owned()), None).expect("the prefix is valid");
+
+        let commands = IntCounterVec::new(
+            Opts::new("commands_total", "Commands run, by name and outcome."),
+            &["command", "outcome"],
+        )
+        .expect("the labels are valid");
+        let download_seconds = Histogram::with_opts(
+            HistogramOpts::new("download_seconds", "How long downloads took.")
+                .buckets(vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]),
+        )
+        .expect("the buckets are valid");
+
+        let metrics = Self {
+            registry,
+            commands,
+            playbacks_started: counter("playbacks_started_total", "Sounds started playing."),
+            playbacks_stopped: counter(
+                "playbacks_stopped_total",
+                "Sounds stopped, with /stop or on shutdown.",
+            ),
+            download_seconds,
+            download_failures: counter("download_failures_total", "Downloads that failed."),
+            voice_connections: gauge("voice_connections", "Voice channels the bot is in."),
+            playing_tracks: gauge("playing_tracks", "Tracks kept around to be stopped."),
+            db_connections: gauge("db_connections", "Open database connections."),
+            db_idle_connections: gauge("db_idle_connections", "Idle database connections."),
+            cached_guilds: gauge("cached_guilds", "Guilds in Discord's cache."),
+            cached_users: gauge("cached_users", "Users in Discord's cache."),
+            pending_adds: gauge("pending_adds", "Sounds being downloaded and probed."),
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 12] = [
+            Box::new(metrics.commands.clone()),
+            Box::new(metrics.playbacks_started.clone()),
+            Box::new(metrics.playbacks_stopped.clone()),
+            Box::new(metrics.download_seconds.clone()),
+            Box::new(metrics.download_failures.clone()),
+            Box::new(metrics.voice_connections.clone()),
+            Box::new(metrics.playing_tracks.clone()),
+            Box::new(metrics.db_connections.clone()),
+            Box::new(metrics.db_idle_connections.clone()),
+            Box::new(metrics.cached_guilds.clone()),
+            Box::new(metrics.cached_users.clone()),
+            Box::new(metrics.pending_adds.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        metrics
+    }
+
+    /// Count a run of `command`.
+    pub fn command_finished(&self, command: &str, ok: bool) {
+        let outcome = if ok { "ok" } else { "error" };
+        self.commands.with_label_values(&[command, outcome]).inc();
+    }
+
+    /// Sample everything that isn't counted as it happens, and render all metrics in
+    /// Prometheus' text format.
+    pub async fn gather(&self, data: &Data) -> Result<String, Error> {
+        let voice = data.voice.stats().await;
+        self.voice_connections.set(voice.connections as i64);
+        self.playing_tracks.set(voice.tracks as i64);
+
+        if let Some(pool) = data.db.pool_stats() {
+            self.db_connections.set(pool.connections as i64);
+            self.db_idle_connections.set(pool.idle as i64);
+        }
+
+        if let Some(cache) = data.cache.get() {
+            self.cached_guilds.set(cache.guild_count() as i64);
+            self.cached_users.set(cache.user_count() as i64);
+        }
+
+        self.pending_adds.set(data.adds.count() as i64);
+
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn counter(name: &str, help: &str) -> IntCounter {
+    IntCounter::new(name, help).expect("the name is valid")
+}
+
+fn gauge(name: &str, help: &str) -> IntGauge {
+    IntGauge::new(name, help).expect("the name is valid")
+}
+
+/// Records how long downloads take and how many of them fail.
+#[derive(Debug)]
+pub struct MeasuredDownloader {
+    inner: Box<dyn Downloader>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeasuredDownloader {
+    pub fn new(inner: Box<dyn Downloader>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Downloader for MeasuredDownloader {
+    async fn download(
+        &self,
+        source: &str,
+        dest: &Path,
+        limits: DownloadLimits,
+    ) -> Result<SoundMetadata, Error> {
+        let start = Instant::now();
+        let result = self.inner.download(source, dest, limits).await;
+
+        self.metrics
+            .download_seconds
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            self.metrics.download_failures.inc();
+        }
+
+        result
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        self.inner.check().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{name, seed, setup, ALICE, BOB, CHANNEL, GUILD};
+    use crate::services::playbacks::StopFilter;
+    use crate::services::{playbacks, sounds};
+
+    #[tokio::test]
+    async fn metrics_count_playbacks_and_downloads() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        setup
+            .media
+            .add_failure("https://example.com/gone", "Video unavailable.");
+
+        playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("bonk"))
+            .await
+            .unwrap();
+        playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap();
+        sounds::add(
+            data,
+            GUILD,
+            ALICE,
+            None,
+            "https://example.com/gone".to_owned(),
+        )
+        .await
+        .unwrap_err();
+
+        let metrics = data.metrics.gather(data).await.unwrap();
+        let lines: Vec<&str> = metrics.lines().collect();
+        for expected in [
+            "bernie_playbacks_started_total 2",
+            "bernie_playbacks_stopped_total 2",
+            "bernie_download_failures_total 1",
+            "bernie_download_seconds_count 1",
+            "bernie_voice_connections 0",
+            "bernie_playing_tracks 0",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected:?} in:\n{metrics}"
+            );
+        }
+    }
+}
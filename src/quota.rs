@@ -0,0 +1,230 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail};
+use poise::serenity_prelude::GuildId;
+use serde::Serialize;
+
+use crate::db::Repository;
+use crate::Error;
+
+/// Limits on a guild's sounds. `None` means there's no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Limits {
+    /// Longest a sound can be, in milliseconds.
+    pub max_length: Option<i32>,
+    /// Largest a sound's file can be, in bytes.
+    pub max_size: Option<i64>,
+    /// Most storage a guild's sounds can use together, in bytes.
+    pub max_total_size: Option<i64>,
+    pub max_sounds: Option<i32>,
+}
+
+/// A guild's overrides of the default [`Limits`], as stored in the `guilds` table.
+///
+/// `None` uses the default; 0 means there's no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, sqlx::FromRow)]
+pub struct GuildLimits {
+    pub max_length: Option<i32>,
+    pub max_size: Option<i64>,
+    pub max_total_size: Option<i64>,
+    pub max_sounds: Option<i32>,
+}
+
+/// How much of its [`Limits`] a guild is using.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Usage {
+    pub sounds: i64,
+    /// Size of the guild's files in bytes. Files shared by several sounds are only counted once.
+    pub total_size: i64,
+}
+
+impl Limits {
+    /// Make limits from the longest a sound can be in seconds, the largest it can be in MiB,
+    /// the most storage a guild can use in MiB and the most sounds it can have. Any of them can be
+    /// 0 for no limit.
+    pub fn from_settings(
+        max_sound_seconds: u32,
+        max_sound_mb: u32,
+        max_guild_mb: u32,
+        max_guild_sounds: u32,
+    ) -> Result<Self, Error> {
+        let max_length = i32::try_from(max_sound_seconds as i64 * 1000).map_err(|_| {
+            anyhow!(
+                "Sounds can't be limited to more than {} seconds.",
+                i32::MAX / 1000
+            )
+        })?;
+        let max_sounds = i32::try_from(max_guild_sounds)
+            .map_err(|_| anyhow!("Guilds can't be limited to more than {} sounds.", i32::MAX))?;
+
+        Ok(Self {
+            max_length: limit(max_length),
+            max_size: limit(max_sound_mb as i64 * MB),
+            max_total_size: limit(max_guild_mb as i64 * MB),
+            max_sounds: limit(max_sounds),
+        })
+    }
+
+    /// Get the limits of `guild_id`, which are `defaults` unless the guild overrides them.
+    pub async fn for_guild(
+        db: &dyn Repository,
+        guild_id: GuildId,
+        defaults: &Self,
+    ) -> Result<Self, Error> {
+        let overrides = db.guild_limits(guild_id).await?;
+
+        Ok(Self {
+            max_length: resolve(overrides.max_length, defaults.max_length),
+            max_size: resolve(overrides.max_size, defaults.max_size),
+            max_total_size: resolve(overrides.max_total_size, defaults.max_total_size),
+            max_sounds: resolve(overrides.max_sounds, defaults.max_sounds),
+        })
+    }
+
+    /// Make sure a guild using `usage` has room for another sound.
+    pub fn check_room(&self, usage: &Usage) -> Result<(), Error> {
+        if let Some(max_sounds) = self.max_sounds {
+            if usage.sounds >= max_sounds as i64 {
+                bail!("This server already has the maximum of {max_sounds} sounds.");
+            }
+        }
+        if let Some(max_total_size) = self.max_total_size {
+            if usage.total_size >= max_total_size {
+                bail!(
+                    "This server is out of storage; its sounds use {} of {}.",
+                    format_size(usage.total_size),
+                    format_size(max_total_size)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make sure a guild using `usage` has room for `sounds` more sounds, whose files that it
+    /// doesn't have yet take up `size` bytes.
+    pub fn check_room_for(&self, usage: &Usage, sounds: i64, size: i64) -> Result<(), Error> {
+        if let Some(max_sounds) = self.max_sounds {
+            if usage.sounds + sounds > max_sounds as i64 {
+                bail!(
+                    "That's {sounds} more sounds, but this server has {} and can have at most \
+                    {max_sounds}.",
+                    usage.sounds
+                );
+            }
+        }
+        if let Some(max_total_size) = self.max_total_size {
+            if usage.total_size + size > max_total_size {
+                bail!(
+                    "The sounds are {}, but this server only has {} of storage left.",
+                    format_size(size),
+                    format_size((max_total_size - usage.total_size).max(0))
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make sure a sound `length` milliseconds long in a file of `size` bytes can be added by a
+    /// guild using `usage`.
+    pub fn check_sound(&self, usage: &Usage, length: i32, size: i64) -> Result<(), Error> {
+        self.check_length_and_size(length, size)?;
+        if let Some(max_total_size) = self.max_total_size {
+            if usage.total_size + size > max_total_size {
+                bail!(
+                    "The sound is {}, but this server only has {} of storage left.",
+                    format_size(size),
+                    format_size(max_total_size - usage.total_size)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make sure a sound is no longer than `length` milliseconds and its file is no larger than
+    /// `size` bytes, regardless of how much storage the guild has left.
+    pub fn check_length_and_size(&self, length: i32, size: i64) -> Result<(), Error> {
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                bail!(
+                    "The sound is {}, but sounds can be at most {}.",
+                    format_length(length),
+                    format_length(max_length)
+                );
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if size > max_size {
+                bail!(
+                    "The sound is {}, but sounds can be at most {}.",
+                    format_size(size),
+                    format_size(max_size)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_unlimited = |limit: Option<String>| limit.unwrap_or_else(|| "unlimited".to_owned());
+
+        writeln!(
+            f,
+            "Longest sound: {}",
+            or_unlimited(self.max_length.map(format_length))
+        )?;
+        writeln!(
+            f,
+            "Largest sound: {}",
+            or_unlimited(self.max_size.map(format_size))
+        )?;
+        writeln!(
+            f,
+            "Storage: {}",
+            or_unlimited(self.max_total_size.map(format_size))
+        )?;
+        write!(
+            f,
+            "Sounds: {}",
+            or_unlimited(self.max_sounds.map(|max| max.to_string()))
+        )
+    }
+}
+
+const MB: i64 = 1024 * 1024;
+
+/// Treat a limit of 0 as no limit.
+fn limit<T: Default + PartialEq>(value: T) -> Option<T> {
+    if value == T::default() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Apply a guild's override of a limit to the default.
+fn resolve<T: Default + PartialEq>(value: Option<T>, default: Option<T>) -> Option<T> {
+    match value {
+        Some(value) => limit(value),
+        None => default,
+    }
+}
+
+/// Format a file size in bytes for humans.
+pub fn format_size(bytes: i64) -> String {
+    if bytes < MB {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / MB as f64)
+    }
+}
+
+/// Format a length in milliseconds for humans.
+pub fn format_length(length: i32) -> String {
+    format!("{:.1} seconds", length as f64 / 1000.0)
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::sync::atomic::Ordering;
+
+use anyhow::bail;
+use tempfile::NamedTempFile;
+
+use crate::{Data, Error};
+
+/// Whether everything Bernie needs is working, as found by [`readiness`].
+#[derive(Debug)]
+pub struct Readiness {
+    /// The outcome of checking each dependency, by name.
+    pub checks: Vec<(&'static str, Result<(), String>)>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .checks
+            .iter()
+            .map(|(name, result)| match result {
+                Ok(()) => format!("{name}: ok"),
+                Err(error) => format!("{name}: {error}"),
+            })
+            .collect();
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Check the connection to Discord, the database, the storage directory, and the programs used
+/// to download, probe and play sounds.
+pub async fn readiness(data: &Data) -> Readiness {
+    let gateway = if data.gateway_connected.load(Ordering::Relaxed) {
+        Ok(())
+    } else {
+        Err("Not connected to Discord.".to_owned())
+    };
+
+    let checks = vec![
+        ("gateway", gateway),
+        ("database", outcome(data.db.ping().await)),
+        ("storage", outcome(check_writable(data).await)),
+        ("yt-dlp", outcome(data.downloader.check().await)),
+        ("ffprobe", outcome(data.prober.check().await)),
+        ("ffmpeg", outcome(data.voice.check().await)),
+    ];
+
+    Readiness { checks }
+}
+
+fn outcome(result: Result<(), Error>) -> Result<(), String> {
+    result.map_err(|error| format!("{error:#}"))
+}
+
+/// Make sure files can be written to the storage directory.
+async fn check_writable(data: &Data) -> Result<(), Error> {
+    let storage_dir = data.storage_dir.clone();
+
+    let written = tokio::task::spawn_blocking(move || {
+        let mut file = NamedTempFile::new_in(&storage_dir)?;
+        std::io::Write::write_all(&mut file, b"bernie")
+    })
+    .await?;
+    if let Err(error) = written {
+        bail!("Couldn't write to {:?}: {error}", data.storage_dir);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::setup;
+    use crate::services::health;
+
+    #[tokio::test]
+    async fn readiness_needs_the_gateway() {
+        let setup = setup();
+        let data = &setup.data;
+
+        let readiness = health::readiness(data).await;
+        assert!(!readiness.is_ready());
+        assert_eq!(
+            readiness.to_string(),
+            "gateway: Not connected to Discord.\n\
+            database: ok\n\
+            storage: ok\n\
+            yt-dlp: ok\n\
+            ffprobe: ok\n\
+            ffmpeg: ok"
+        );
+
+        data.gateway_connected.store(true, Ordering::Relaxed);
+        assert!(health::readiness(data).await.is_ready());
+    }
+}
@@ -0,0 +1,8 @@
+//! What the commands actually do, apart from talking to Discord.
+//!
+//! Everything here works on [`Data`](crate::Data), so it can run against fakes in tests.
+
+pub mod health;
+pub mod playbacks;
+pub mod sounds;
+pub mod tokens;
@@ -0,0 +1,649 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId, UserId};
+
+use crate::db::Playback;
+use crate::sound_name::SoundName;
+use crate::voice::{check_room, Track, TrackFilter};
+use crate::{Data, Error};
+
+/// Play a sound in `channel_id`, which is the voice channel the player is in, if any.
+/// Returns the id of the playback.
+///
+/// Fails if the player or the sound is on cooldown, unless one of the player's `roles` is exempt,
+/// or if too many sounds are already playing in the guild.
+pub async fn play(
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+    player_id: UserId,
+    roles: &[RoleId],
+    name: &SoundName,
+) -> Result<i32, Error> {
+    let db = &*data.db;
+
+    let sound = db
+        .find_sound(guild_id, name)
+        .await?
+        .ok_or_else(|| anyhow!("There's no sound named `{name}`."))?;
+    let file_hash = sound
+        .file_hash
+        .ok_or_else(|| anyhow!("`{name}` doesn't have a file."))?;
+    let channel_id = channel_id.ok_or_else(|| anyhow!("Not in a voice channel."))?;
+
+    let started = data
+        .cooldowns
+        .start(guild_id, player_id, roles, sound.id, &sound.name)?;
+
+    // the play only counts towards cooldowns, and is only recorded, if the sound actually starts
+    // playing.
+    let result = async {
+        let file = data.store.open(&file_hash).await?;
+        let max_tracks = data.cooldowns.settings().max_tracks;
+        check_room(data.voice.track_count(guild_id).await, max_tracks)?;
+
+        let playback_id = db.add_playback(sound.id, player_id).await?;
+        let track = Track {
+            playback_id,
+            sound_id: sound.id,
+            player_id,
+            volume: sound.volume,
+        };
+        // checked again, in case another sound started since.
+        if let Err(err) = data
+            .voice
+            .play(guild_id, channel_id, track, file, max_tracks)
+            .await
+        {
+            db.delete_playback(playback_id).await?;
+            return Err(err);
+        }
+
+        Ok::<_, Error>(playback_id)
+    }
+    .await;
+
+    match result {
+        Ok(_) => data.metrics.playbacks_started.inc(),
+        Err(_) => data.cooldowns.undo(started),
+    }
+    result
+}
+
+/// Which playbacks [`stop`] stops. Everything that's set has to match; the default stops
+/// everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StopFilter<'a> {
+    /// Only stop plays of this sound.
+    pub name: Option<&'a SoundName>,
+    /// Only stop sounds this user played.
+    pub player_id: Option<UserId>,
+    /// Only stop the most recently started of the matching playbacks.
+    pub latest: bool,
+}
+
+/// Stop what's playing in `guild_id` that matches `filter`, returning the ids of the stopped
+/// playbacks.
+pub async fn stop(
+    data: &Data,
+    guild_id: GuildId,
+    stopper_id: UserId,
+    filter: StopFilter<'_>,
+) -> Result<Vec<i32>, Error> {
+    let sound_ids = match filter.name {
+        Some(name) => {
+            // sounds removed while they're playing can still be stopped.
+            let sound_ids = data.db.sound_ids_named(guild_id, name).await?;
+            if sound_ids.is_empty() {
+                bail!("There's no sound named `{name}`.");
+            }
+            Some(sound_ids)
+        }
+        None => None,
+    };
+    let filter = TrackFilter {
+        sound_ids,
+        player_id: filter.player_id,
+        latest: filter.latest,
+        oldest: false,
+    };
+
+    let stopped = data.voice.stop(guild_id, filter).await?;
+
+    data.metrics.playbacks_stopped.inc_by(stopped.len() as u64);
+    data.db.stop_playbacks(&stopped, stopper_id).await?;
+
+    Ok(stopped)
+}
+
+/// Stop the sound that's been playing the longest in `guild_id`, returning the id of its
+/// playback if anything was playing.
+pub async fn skip(data: &Data, guild_id: GuildId, stopper_id: UserId) -> Result<Vec<i32>, Error> {
+    let filter = TrackFilter {
+        oldest: true,
+        ..TrackFilter::default()
+    };
+
+    let stopped = data.voice.stop(guild_id, filter).await?;
+
+    data.metrics.playbacks_stopped.inc_by(stopped.len() as u64);
+    data.db.stop_playbacks(&stopped, stopper_id).await?;
+
+    Ok(stopped)
+}
+
+/// Pause what's playing in `guild_id`, returning the ids of the paused playbacks. Time spent
+/// paused doesn't count towards how long sounds played.
+pub async fn pause(data: &Data, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+    let paused = data.voice.pause(guild_id).await?;
+    data.db.pause_playbacks(&paused).await?;
+
+    Ok(paused)
+}
+
+/// Resume what's paused in `guild_id`, returning the ids of the resumed playbacks.
+pub async fn resume(data: &Data, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+    let resumed = data.voice.resume(guild_id).await?;
+    data.db.resume_playbacks(&resumed).await?;
+
+    Ok(resumed)
+}
+
+/// Move what's playing in `guild_id` to `position`, returning the ids of the moved playbacks.
+pub async fn seek(data: &Data, guild_id: GuildId, position: Duration) -> Result<Vec<i32>, Error> {
+    data.voice.seek(guild_id, position).await
+}
+
+/// Why playbacks are stopped when Bernie shuts down.
+pub const SHUTDOWN: &str = "shutdown";
+
+/// Stop everything playing in every guild and leave every voice channel, recording the playbacks
+/// that were still playing as stopped for `reason`.
+pub async fn stop_all(data: &Data, reason: &str) -> Result<Vec<i32>, Error> {
+    let stopped = data.voice.leave_all().await?;
+
+    data.metrics.playbacks_stopped.inc_by(stopped.len() as u64);
+    data.db.end_playbacks(&stopped, reason).await?;
+
+    Ok(stopped)
+}
+
+/// Get the plays of sounds in `guild_id`, newest first, optionally only those of `name`.
+pub async fn history(
+    data: &Data,
+    guild_id: GuildId,
+    name: Option<&SoundName>,
+) -> Result<Vec<Playback>, Error> {
+    data.db.history(guild_id, name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cooldown::CooldownSettings;
+    use crate::db::DEFAULT_VOLUME;
+    use crate::fixture::{
+        name, seed, setup, setup_with_cooldowns, ALICE, BOB, CHANNEL, DJ, GUILD, OTHER_GUILD,
+    };
+    use crate::services::{playbacks, sounds};
+
+    #[tokio::test]
+    async fn play_records_and_plays_the_sound() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        let id = seed(&setup, GUILD, "bonk").await;
+
+        let playback_id = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("BONK"))
+            .await
+            .unwrap();
+
+        let playing = voice.playing(GUILD);
+        assert_eq!(playing.len(), 1);
+        assert_eq!(playing[0].channel_id, CHANNEL);
+        assert_eq!(
+            playing[0].track,
+            Track {
+                playback_id,
+                sound_id: id,
+                player_id: ALICE,
+                volume: DEFAULT_VOLUME,
+            }
+        );
+        assert_eq!(playing[0].source, "memory:hash-of-bonk");
+
+        let history = playbacks::history(data, GUILD, None).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, playback_id);
+        assert_eq!(history[0].name, "bonk");
+        assert_eq!(history[0].player_id, ALICE.0 as i64);
+        assert_eq!(history[0].stopper_id, None);
+    }
+
+    #[tokio::test]
+    async fn play_needs_a_sound_and_a_voice_channel() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        seed(&setup, GUILD, "bonk").await;
+
+        let error = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("boop"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `boop`.");
+
+        let error = playbacks::play(data, GUILD, None, ALICE, &[], &name("bonk"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Not in a voice channel.");
+
+        // sounds in other guilds can't be played either.
+        let error = playbacks::play(data, OTHER_GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `bonk`.");
+
+        assert!(voice.playing(GUILD).is_empty());
+        assert!(playbacks::history(data, GUILD, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn play_holds_players_to_their_cooldown_unless_exempt() {
+        let setup = setup_with_cooldowns(CooldownSettings {
+            plays_per_user: Some(2),
+            window: Duration::from_millis(200),
+            exempt_roles: vec![DJ],
+            ..CooldownSettings::none()
+        });
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        let play = |player_id: UserId, roles: &'static [RoleId]| async move {
+            playbacks::play(data, GUILD, Some(CHANNEL), player_id, roles, &name("bonk")).await
+        };
+
+        play(ALICE, &[]).await.unwrap();
+        play(ALICE, &[]).await.unwrap();
+        let error = play(ALICE, &[]).await.unwrap_err();
+        assert_eq!(error.to_string(), "You're on cooldown for 1s.");
+        // refused plays aren't recorded.
+        assert_eq!(setup.voice.playing(GUILD).len(), 2);
+
+        // others have cooldowns of their own, and exempt roles have none.
+        play(BOB, &[]).await.unwrap();
+        play(ALICE, &[DJ]).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        play(ALICE, &[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn play_holds_sounds_to_their_cooldown() {
+        let setup = setup_with_cooldowns(CooldownSettings {
+            sound_cooldown: Some(Duration::from_secs(60)),
+            exempt_roles: vec![DJ],
+            ..CooldownSettings::none()
+        });
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+
+        playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let error = playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("BONK"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "`bonk` is on cooldown for 60s.");
+
+        playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("boop"))
+            .await
+            .unwrap();
+        playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[DJ], &name("bonk"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn play_limits_sounds_playing_at_once_for_everyone() {
+        let setup = setup_with_cooldowns(CooldownSettings {
+            max_tracks: Some(2),
+            exempt_roles: vec![DJ],
+            ..CooldownSettings::none()
+        });
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, OTHER_GUILD, "bonk").await;
+        let play = |guild_id: GuildId| async move {
+            playbacks::play(data, guild_id, Some(CHANNEL), ALICE, &[DJ], &name("bonk")).await
+        };
+
+        play(GUILD).await.unwrap();
+        play(GUILD).await.unwrap();
+        let error = play(GUILD).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many sounds are playing already. Wait for one to finish, or /stop them."
+        );
+        play(OTHER_GUILD).await.unwrap();
+
+        playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap();
+        play(GUILD).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn play_only_counts_towards_cooldowns_once_the_sound_plays() {
+        let setup = setup_with_cooldowns(CooldownSettings {
+            plays_per_user: Some(1),
+            window: Duration::from_secs(60),
+            sound_cooldown: Some(Duration::from_secs(60)),
+            max_tracks: Some(1),
+            ..CooldownSettings::none()
+        });
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+
+        playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let error = playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("boop"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many sounds are playing already. Wait for one to finish, or /stop them."
+        );
+        let history = playbacks::history(data, GUILD, None).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].player_id, ALICE.0 as i64);
+
+        playbacks::stop(data, GUILD, ALICE, StopFilter::default())
+            .await
+            .unwrap();
+        playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("boop"))
+            .await
+            .unwrap();
+        assert_eq!(setup.voice.playing(GUILD).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stop_stops_everything_in_the_guild() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, OTHER_GUILD, "boop").await;
+
+        let first = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let second = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let other = playbacks::play(data, OTHER_GUILD, Some(CHANNEL), ALICE, &[], &name("boop"))
+            .await
+            .unwrap();
+
+        let stopped = playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(stopped, [first, second]);
+        assert!(voice.playing(GUILD).is_empty());
+        assert_eq!(voice.playing(OTHER_GUILD).len(), 1);
+
+        for playback in playbacks::history(data, GUILD, None).await.unwrap() {
+            assert_eq!(playback.stopper_id, Some(BOB.0 as i64));
+            assert!(playback.stopped_at.is_some());
+        }
+        let history = playbacks::history(data, OTHER_GUILD, None).await.unwrap();
+        assert_eq!(history[0].id, other);
+        assert_eq!(history[0].stopper_id, None);
+
+        // stopping again does nothing.
+        assert!(playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn skip_stops_the_oldest_play() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        let bonk = name("bonk");
+        let play = || playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &bonk);
+
+        let first = play().await.unwrap();
+        let second = play().await.unwrap();
+
+        let skipped = playbacks::skip(data, GUILD, BOB).await.unwrap();
+        assert_eq!(skipped, [first]);
+        let playing: Vec<i32> = setup
+            .voice
+            .playing(GUILD)
+            .into_iter()
+            .map(|fake| fake.track.playback_id)
+            .collect();
+        assert_eq!(playing, [second]);
+        let history = playbacks::history(data, GUILD, None).await.unwrap();
+        assert_eq!(history[1].id, first);
+        assert_eq!(history[1].stopper_id, Some(BOB.0 as i64));
+
+        assert_eq!(playbacks::skip(data, GUILD, BOB).await.unwrap(), [second]);
+        assert!(playbacks::skip(data, GUILD, BOB).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stop_can_target_a_sound_a_player_or_the_latest_play() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+        let play = |player_id: UserId, sound: &'static str| async move {
+            playbacks::play(data, GUILD, Some(CHANNEL), player_id, &[], &name(sound))
+                .await
+                .unwrap()
+        };
+        let playing = || -> Vec<i32> {
+            voice
+                .playing(GUILD)
+                .into_iter()
+                .map(|fake| fake.track.playback_id)
+                .collect()
+        };
+
+        let alices_bonk = play(ALICE, "bonk").await;
+        let alices_boop = play(ALICE, "boop").await;
+        let bobs_bonk = play(BOB, "bonk").await;
+        let bobs_boop = play(BOB, "boop").await;
+
+        let bonk = name("bonk");
+        let latest_bonk = StopFilter {
+            name: Some(&bonk),
+            latest: true,
+            ..Default::default()
+        };
+        let stopped = playbacks::stop(data, GUILD, BOB, latest_bonk)
+            .await
+            .unwrap();
+        assert_eq!(stopped, [bobs_bonk]);
+        assert_eq!(playing(), [alices_bonk, alices_boop, bobs_boop]);
+
+        let alices = StopFilter {
+            player_id: Some(ALICE),
+            ..Default::default()
+        };
+        let stopped = playbacks::stop(data, GUILD, BOB, alices).await.unwrap();
+        assert_eq!(stopped, [alices_bonk, alices_boop]);
+        assert_eq!(playing(), [bobs_boop]);
+
+        let zap = name("zap");
+        let missing = StopFilter {
+            name: Some(&zap),
+            ..Default::default()
+        };
+        let error = playbacks::stop(data, GUILD, BOB, missing)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `zap`.");
+
+        // removing a sound doesn't stop it, but it can still be stopped by name.
+        sounds::remove(data, GUILD, &name("boop")).await.unwrap();
+        let boop = name("boop");
+        let boops = StopFilter {
+            name: Some(&boop),
+            ..Default::default()
+        };
+        let stopped = playbacks::stop(data, GUILD, ALICE, boops).await.unwrap();
+        assert_eq!(stopped, [bobs_boop]);
+        assert!(playing().is_empty());
+
+        let history = playbacks::history(data, GUILD, None).await.unwrap();
+        let stopped: Vec<i32> = history
+            .iter()
+            .filter(|playback| playback.stopper_id == Some(BOB.0 as i64))
+            .map(|playback| playback.id)
+            .collect();
+        assert_eq!(stopped.len(), 3);
+        assert!(!stopped.contains(&bobs_boop));
+    }
+
+    #[tokio::test]
+    async fn pausing_and_seeking_apply_to_what_is_playing_and_pauses_are_recorded() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        seed(&setup, GUILD, "bonk").await;
+
+        let paused = playbacks::pause(data, GUILD).await.unwrap();
+        assert!(paused.is_empty());
+
+        let first = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let second = playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("bonk"))
+            .await
+            .unwrap();
+
+        let moved = playbacks::seek(data, GUILD, Duration::from_secs(90))
+            .await
+            .unwrap();
+        assert_eq!(moved, [first, second]);
+        assert!(voice
+            .playing(GUILD)
+            .iter()
+            .all(|fake| fake.position == Some(Duration::from_secs(90))));
+
+        let paused = playbacks::pause(data, GUILD).await.unwrap();
+        assert_eq!(paused, [first, second]);
+        assert!(voice.playing(GUILD).iter().all(|fake| fake.paused));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let resumed = playbacks::resume(data, GUILD).await.unwrap();
+        assert_eq!(resumed, [first, second]);
+        assert!(voice.playing(GUILD).iter().all(|fake| !fake.paused));
+
+        // stopping while paused counts the pause up to the stop.
+        playbacks::pause(data, GUILD).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap();
+
+        let history = playbacks::history(data, GUILD, None).await.unwrap();
+        assert_eq!(history.len(), 2);
+        for playback in history {
+            assert!(playback.paused_ms >= 90, "{}", playback.paused_ms);
+            let stopped = playback.stopped_at.unwrap() - playback.created_at;
+            assert!(playback.duration().unwrap() <= stopped - chrono::Duration::milliseconds(90));
+        }
+    }
+
+    #[tokio::test]
+    async fn stop_all_stops_every_guild_and_records_why() {
+        let setup = setup();
+        let data = &setup.data;
+        let voice = &setup.voice;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, OTHER_GUILD, "boop").await;
+
+        let stopped_by_bob = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        playbacks::stop(data, GUILD, BOB, StopFilter::default())
+            .await
+            .unwrap();
+        let first = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let other = playbacks::play(data, OTHER_GUILD, Some(CHANNEL), ALICE, &[], &name("boop"))
+            .await
+            .unwrap();
+
+        let mut stopped = playbacks::stop_all(data, playbacks::SHUTDOWN)
+            .await
+            .unwrap();
+        stopped.sort_unstable();
+        assert_eq!(stopped, [first, other]);
+        assert!(voice.playing(GUILD).is_empty());
+        assert!(voice.playing(OTHER_GUILD).is_empty());
+
+        let mut history = playbacks::history(data, GUILD, None).await.unwrap();
+        history.extend(playbacks::history(data, OTHER_GUILD, None).await.unwrap());
+        for playback in history {
+            assert!(playback.stopped_at.is_some());
+            if playback.id == stopped_by_bob {
+                assert_eq!(playback.stopper_id, Some(BOB.0 as i64));
+                assert_eq!(playback.stop_reason, None);
+            } else {
+                assert_eq!(playback.stopper_id, None);
+                assert_eq!(playback.stop_reason.as_deref(), Some("shutdown"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn history_is_newest_first_and_filters_by_name() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+
+        let first = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let second = playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("boop"))
+            .await
+            .unwrap();
+        let third = playbacks::play(data, GUILD, Some(CHANNEL), BOB, &[], &name("bonk"))
+            .await
+            .unwrap();
+
+        let ids = |history: Vec<Playback>| -> Vec<i32> {
+            history.into_iter().map(|playback| playback.id).collect()
+        };
+
+        let all = playbacks::history(data, GUILD, None).await.unwrap();
+        assert_eq!(ids(all), [third, second, first]);
+
+        let bonk = playbacks::history(data, GUILD, Some(&name("BONK")))
+            .await
+            .unwrap();
+        assert_eq!(ids(bonk), [third, first]);
+
+        // removed sounds keep their history.
+        sounds::remove(data, GUILD, &name("boop")).await.unwrap();
+        let boop = playbacks::history(data, GUILD, Some(&name("boop")))
+            .await
+            .unwrap();
+        assert_eq!(ids(boop), [second]);
+    }
+}
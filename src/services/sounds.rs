@@ -0,0 +1,1372 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use poise::serenity_prelude::{GuildId, UserId};
+use tempfile::NamedTempFile;
+use tokio::sync::oneshot;
+
+use crate::db::{NewSound, Sound, DEFAULT_VOLUME};
+use crate::media::{DownloadLimits, SoundMetadata};
+use crate::quota::{Limits, Usage};
+use crate::sound_name::SoundName;
+use crate::storage;
+use crate::store::hash_file;
+use crate::{Data, Error};
+
+/// Sounds being added, so they can be waited for when shutting down. Until they've been
+/// downloaded and probed, they're kept by guild and uploader so they can be cancelled.
+///
+/// Everyone can only add one sound per guild at a time.
+#[derive(Debug, Default)]
+pub struct PendingAdds {
+    state: Mutex<PendingState>,
+}
+
+#[derive(Debug, Default)]
+struct PendingState {
+    last_id: u64,
+    cancels: HashMap<(GuildId, UserId), (u64, oneshot::Sender<()>)>,
+    /// Adds that haven't finished, whether they can still be cancelled or not.
+    running: usize,
+    /// Set when shutting down, so no new adds can start.
+    closed: bool,
+}
+
+/// An add in progress. It stops being tracked when this is dropped.
+struct PendingAdd<'a> {
+    adds: &'a PendingAdds,
+    key: (GuildId, UserId),
+    id: u64,
+    cancelled: oneshot::Receiver<()>,
+}
+
+impl PendingAdds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many sounds are being added.
+    pub fn count(&self) -> usize {
+        self.state.lock().unwrap().running
+    }
+
+    fn start(&self, guild_id: GuildId, uploader_id: UserId) -> Result<PendingAdd<'_>, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.closed {
+            bail!("Bernie is shutting down. Try adding the sound again once it's back.");
+        }
+
+        let key = (guild_id, uploader_id);
+        if state.cancels.contains_key(&key) {
+            bail!("You're already adding a sound. Wait for it to finish or /cancel it first.");
+        }
+
+        state.last_id += 1;
+        let id = state.last_id;
+        let (sender, receiver) = oneshot::channel();
+        state.cancels.insert(key, (id, sender));
+        state.running += 1;
+
+        Ok(PendingAdd {
+            adds: self,
+            key,
+            id,
+            cancelled: receiver,
+        })
+    }
+
+    /// Cancel the add by `uploader_id` in `guild_id`, returning whether there was one.
+    fn cancel(&self, guild_id: GuildId, uploader_id: UserId) -> bool {
+        let removed = self
+            .state
+            .lock()
+            .unwrap()
+            .cancels
+            .remove(&(guild_id, uploader_id));
+
+        match removed {
+            Some((_, sender)) => sender.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Stop new adds from starting.
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+    }
+
+    /// Cancel every add in progress.
+    fn cancel_all(&self) {
+        for (_, (_, sender)) in self.state.lock().unwrap().cancels.drain() {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Wait until no sounds are being added, or `timeout` has passed. Returns whether there are
+    /// none left.
+    async fn wait_until_done(&self, timeout: Duration) -> bool {
+        let waiting = async {
+            while self.count() > 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+
+        tokio::time::timeout(timeout, waiting).await.is_ok()
+    }
+}
+
+impl PendingAdd<'_> {
+    /// Stop the add from being cancelled, and let the uploader start another one.
+    fn uncancellable(&self) {
+        let mut state = self.adds.state.lock().unwrap();
+
+        // the add might have been cancelled and replaced by another one already.
+        if matches!(state.cancels.get(&self.key), Some((id, _)) if *id == self.id) {
+            state.cancels.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for PendingAdd<'_> {
+    fn drop(&mut self) {
+        self.uncancellable();
+        self.adds.state.lock().unwrap().running -= 1;
+    }
+}
+
+/// Where a sound being added comes from.
+enum Source {
+    /// A URL to download the sound from with yt-dlp.
+    Url(String),
+    /// A file that was uploaded, along with its name.
+    Upload {
+        file_name: String,
+        file: NamedTempFile,
+    },
+}
+
+/// Prefix of the recorded source of sounds that were uploaded, followed by the file's name.
+const UPLOAD_PREFIX: &str = "upload:";
+
+/// Most tags a sound can have.
+pub const MAX_TAGS: usize = 10;
+
+/// Longest a tag can be, in characters.
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// Loudest a sound can be made to play, in percent of its file's own volume.
+pub const MAX_VOLUME: i32 = 200;
+
+/// How many sounds [`stats`] lists as the most played.
+const MOST_PLAYED: i64 = 10;
+
+/// A sound along with its tags.
+#[derive(Debug, Clone)]
+pub struct TaggedSound {
+    pub sound: Sound,
+    pub tags: Vec<String>,
+}
+
+/// How much of its limits a guild is using, and which of its sounds are played the most.
+#[derive(Debug, Clone)]
+pub struct GuildStats {
+    pub usage: Usage,
+    pub limits: Limits,
+    /// Names of the most played sounds and how many times they've been played.
+    pub most_played: Vec<(String, i64)>,
+}
+
+/// Download `source` and add it to `guild_id` as `name`, returning the new sound's id and name.
+///
+/// Without a name, one is made from the title of the source.
+/// Until the sound has been downloaded and probed, the uploader can stop this with [`cancel`].
+pub async fn add(
+    data: &Data,
+    guild_id: GuildId,
+    uploader_id: UserId,
+    name: Option<SoundName>,
+    source: String,
+) -> Result<(i32, SoundName), Error> {
+    add_from(data, guild_id, uploader_id, name, Source::Url(source)).await
+}
+
+/// Add the uploaded `file` to `guild_id` as `name`, returning the new sound's id and name.
+///
+/// Without a name, one is made from `file_name`. Like with [`add`], the uploader can stop this
+/// with [`cancel`] until the file has been probed.
+pub async fn add_upload(
+    data: &Data,
+    guild_id: GuildId,
+    uploader_id: UserId,
+    name: Option<SoundName>,
+    file_name: String,
+    file: NamedTempFile,
+) -> Result<(i32, SoundName), Error> {
+    let source = Source::Upload { file_name, file };
+
+    add_from(data, guild_id, uploader_id, name, source).await
+}
+
+async fn add_from(
+    data: &Data,
+    guild_id: GuildId,
+    uploader_id: UserId,
+    name: Option<SoundName>,
+    source: Source,
+) -> Result<(i32, SoundName), Error> {
+    let db = &*data.db;
+
+    let mut pending = data.adds.start(guild_id, uploader_id)?;
+
+    let limits = Limits::for_guild(db, guild_id, &data.limits).await?;
+    let usage = db.usage(guild_id).await?;
+    limits.check_room(&usage)?;
+
+    // check this before downloading anything. the name could still be taken while we download,
+    // in which case adding the sound fails below.
+    if let Some(name) = &name {
+        if db.find_sound(guild_id, name).await?.is_some() {
+            return Err(anyhow!("A sound named `{name}` already exists."));
+        }
+    }
+
+    let (file, url, recorded_source, file_title) = match source {
+        // download to a temporary file first, so we can check it before storing it.
+        Source::Url(url) => (NamedTempFile::new()?, Some(url.clone()), url, None),
+        Source::Upload { file_name, file } => {
+            let title = Path::new(&file_name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+            (file, None, format!("{UPLOAD_PREFIX}{file_name}"), title)
+        }
+    };
+    let download_limits = DownloadLimits {
+        max_length: limits.max_length,
+        max_size: limits.max_size,
+    };
+    let fetch = async {
+        let metadata = match &url {
+            Some(url) => Some(
+                data.downloader
+                    .download(url, file.path(), download_limits)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        // get the sound's length.
+        let length = data.prober.probe_length(file.path().as_os_str()).await?;
+
+        Ok::<_, Error>((metadata, length))
+    };
+    // dropping the download kills yt-dlp, if it's still running.
+    let (metadata, length) = tokio::select! {
+        fetched = fetch => fetched?,
+        _ = &mut pending.cancelled => match &name {
+            Some(name) => bail!("Cancelled adding `{name}`."),
+            None => match &url {
+                Some(url) => bail!("Cancelled adding the sound from {url}."),
+                None => bail!("Cancelled adding the uploaded sound."),
+            },
+        },
+    };
+    // it's too late to cancel from here on.
+    pending.uncancellable();
+
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let title = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.title.clone())
+                .or(file_title);
+            let name = title
+                .as_deref()
+                .and_then(SoundName::suggest)
+                .ok_or_else(|| {
+                    anyhow!("Couldn't come up with a name for the sound; give it one.")
+                })?;
+            if db.find_sound(guild_id, &name).await?.is_some() {
+                bail!("A sound named `{name}` already exists; give the sound another name.");
+            }
+            name
+        }
+    };
+
+    let file_size = tokio::fs::metadata(file.path()).await?.len() as i64;
+    limits.check_sound(&usage, length, file_size)?;
+
+    let file_hash = hash_file(file.path()).await?;
+    data.store.put(&file_hash, file.path()).await?;
+
+    let id = db
+        .add_sound(&NewSound {
+            guild_id,
+            name: name.clone(),
+            source: recorded_source,
+            uploader_id,
+            length,
+            created_at: None,
+            file_hash,
+            file_size,
+            metadata,
+            volume: DEFAULT_VOLUME,
+        })
+        .await?;
+
+    Ok((id, name))
+}
+
+/// Get a sound along with what yt-dlp said about its source, if anything.
+pub async fn info(
+    data: &Data,
+    guild_id: GuildId,
+    name: &SoundName,
+) -> Result<(Sound, Option<SoundMetadata>), Error> {
+    let sound = find(data, guild_id, name).await?;
+    let metadata = data.db.sound_metadata(sound.id).await?;
+
+    Ok((sound, metadata))
+}
+
+/// Download a sound from its source again, keeping its id and play history.
+///
+/// The new file has to fit the guild's limits on length and size, like a newly added sound.
+pub async fn refresh(data: &Data, guild_id: GuildId, name: &SoundName) -> Result<Sound, Error> {
+    let db = &*data.db;
+
+    let sound = find(data, guild_id, name).await?;
+    if sound.source.starts_with(UPLOAD_PREFIX) {
+        bail!("`{name}` was uploaded, so it can't be downloaded again.");
+    }
+    let limits = Limits::for_guild(db, guild_id, &data.limits).await?;
+
+    storage::refetch(
+        db,
+        &*data.store,
+        &*data.downloader,
+        &*data.prober,
+        &sound,
+        Some(&limits),
+    )
+    .await?;
+
+    db.find_sound(guild_id, name)
+        .await?
+        .ok_or_else(|| anyhow!("`{name}` was removed while it was being refreshed."))
+}
+
+/// Get every sound in `guild_id`, ordered by name.
+pub async fn list(data: &Data, guild_id: GuildId) -> Result<Vec<Sound>, Error> {
+    data.db.guild_sounds(guild_id).await
+}
+
+/// Get every sound in `guild_id` along with its tags, ordered by name.
+pub async fn list_tagged(data: &Data, guild_id: GuildId) -> Result<Vec<TaggedSound>, Error> {
+    let sounds = data.db.guild_sounds(guild_id).await?;
+
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for (sound_id, tag) in data.db.guild_tags(guild_id).await? {
+        tags.entry(sound_id).or_default().push(tag);
+    }
+
+    let tagged = sounds
+        .into_iter()
+        .map(|sound| {
+            let tags = tags.remove(&sound.id).unwrap_or_default();
+            TaggedSound { sound, tags }
+        })
+        .collect();
+
+    Ok(tagged)
+}
+
+/// Find the sounds in `guild_id` whose names contain `query`, or that have a tag starting with it,
+/// regardless of case.
+pub async fn search(
+    data: &Data,
+    guild_id: GuildId,
+    query: &str,
+) -> Result<Vec<TaggedSound>, Error> {
+    let query = query.trim().to_lowercase();
+
+    let found = list_tagged(data, guild_id)
+        .await?
+        .into_iter()
+        .filter(|tagged| {
+            tagged.sound.name.to_lowercase().contains(&query)
+                || tagged.tags.iter().any(|tag| tag.starts_with(&query))
+        })
+        .collect();
+
+    Ok(found)
+}
+
+/// Replace the tags of the sound named `name`, returning them as they're stored: in lower case,
+/// sorted and without duplicates.
+pub async fn tag(
+    data: &Data,
+    guild_id: GuildId,
+    name: &SoundName,
+    tags: &[String],
+) -> Result<Vec<String>, Error> {
+    let normalized = normalize_tags(tags)?;
+
+    let sound = find(data, guild_id, name).await?;
+    data.db.set_sound_tags(sound.id, &normalized).await?;
+
+    Ok(normalized)
+}
+
+/// Check `tags` and put them the way they're stored: in lower case, sorted and without
+/// duplicates.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, Error> {
+    let mut normalized = vec![];
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            bail!("Tags can't be empty.");
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            bail!("Tags can't be longer than {MAX_TAG_LENGTH} characters.");
+        }
+        if tag.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            bail!("Tags can't contain spaces or control characters.");
+        }
+        normalized.push(tag);
+    }
+    normalized.sort();
+    normalized.dedup();
+    if normalized.len() > MAX_TAGS {
+        bail!("Sounds can't have more than {MAX_TAGS} tags.");
+    }
+
+    Ok(normalized)
+}
+
+/// Make the sound named `name` play at `volume` percent of its file's own volume.
+pub async fn set_volume(
+    data: &Data,
+    guild_id: GuildId,
+    name: &SoundName,
+    volume: i32,
+) -> Result<(), Error> {
+    check_volume(volume)?;
+
+    let sound = find(data, guild_id, name).await?;
+    data.db.set_sound_volume(sound.id, volume).await
+}
+
+/// Make sure sounds can play at `volume` percent.
+pub fn check_volume(volume: i32) -> Result<(), Error> {
+    if !(0..=MAX_VOLUME).contains(&volume) {
+        bail!("Volume has to be between 0 and {MAX_VOLUME}%.");
+    }
+
+    Ok(())
+}
+
+/// Get the contents of a sound's file, to listen to it outside of a voice channel.
+pub async fn file(
+    data: &Data,
+    guild_id: GuildId,
+    name: &SoundName,
+) -> Result<(Sound, Vec<u8>), Error> {
+    let sound = find(data, guild_id, name).await?;
+    let file_hash = sound
+        .file_hash
+        .clone()
+        .ok_or_else(|| anyhow!("`{name}` doesn't have a file."))?;
+
+    let contents = data.store.get(&file_hash).await?;
+
+    Ok((sound, contents))
+}
+
+/// Get how much of its limits `guild_id` is using, and its most played sounds.
+pub async fn stats(data: &Data, guild_id: GuildId) -> Result<GuildStats, Error> {
+    let db = &*data.db;
+
+    Ok(GuildStats {
+        usage: db.usage(guild_id).await?,
+        limits: Limits::for_guild(db, guild_id, &data.limits).await?,
+        most_played: db.most_played(guild_id, MOST_PLAYED).await?,
+    })
+}
+
+pub async fn rename(
+    data: &Data,
+    guild_id: GuildId,
+    old_name: &SoundName,
+    new_name: &SoundName,
+) -> Result<(), Error> {
+    if !data.db.rename_sound(guild_id, old_name, new_name).await? {
+        return Err(anyhow!("There's no sound named `{old_name}`."));
+    }
+
+    Ok(())
+}
+
+pub async fn remove(data: &Data, guild_id: GuildId, name: &SoundName) -> Result<(), Error> {
+    if !data.db.remove_sound(guild_id, name).await? {
+        return Err(anyhow!("There's no sound named `{name}`."));
+    }
+
+    // the sound's file is left for garbage collection, since other sounds might share it.
+
+    Ok(())
+}
+
+/// Stop new sounds from being added, give the ones in progress `grace` to finish, and cancel
+/// whatever's left, so downloads are cleaned up. Returns once every add is done, or has taken
+/// another `grace` to wind down.
+pub async fn finish_adds(data: &Data, grace: Duration) {
+    data.adds.close();
+    if data.adds.wait_until_done(grace).await {
+        return;
+    }
+
+    tracing::info!("Cancelling {} sounds being added.", data.adds.count());
+    data.adds.cancel_all();
+    if !data.adds.wait_until_done(grace).await {
+        tracing::warn!(
+            "{} sounds were still being added after being cancelled.",
+            data.adds.count()
+        );
+    }
+}
+
+async fn find(data: &Data, guild_id: GuildId, name: &SoundName) -> Result<Sound, Error> {
+    data.db
+        .find_sound(guild_id, name)
+        .await?
+        .ok_or_else(|| anyhow!("There's no sound named `{name}`."))
+}
+
+/// Cancel the sound `uploader_id` is adding to `guild_id`.
+pub fn cancel(data: &Data, guild_id: GuildId, uploader_id: UserId) -> Result<(), Error> {
+    if !data.adds.cancel(guild_id, uploader_id) {
+        bail!("You're not adding a sound.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::fixture::{
+        name, names, seed, set_limits, setup, Setup, ALICE, BOB, CHANNEL, GUILD, NO_LIMITS,
+        OTHER_GUILD,
+    };
+    use crate::quota::GuildLimits;
+    use crate::services::{playbacks, sounds};
+    use crate::store::SoundStore;
+
+    #[tokio::test]
+    async fn add_rejects_taken_name_regardless_of_case() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "Bonk").await;
+
+        let error = sounds::add(data, GUILD, BOB, Some(name("bONK")), "unused".to_owned())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "A sound named `bONK` already exists.");
+        assert_eq!(names(data, GUILD).await, ["Bonk"]);
+    }
+
+    #[tokio::test]
+    async fn add_rejects_when_guild_is_full() {
+        let setup = setup();
+        let data = &setup.data;
+        set_limits(
+            data,
+            GUILD,
+            GuildLimits {
+                max_sounds: Some(1),
+                ..Default::default()
+            },
+        )
+        .await;
+        seed(&setup, GUILD, "bonk").await;
+
+        let error = sounds::add(data, GUILD, BOB, Some(name("boop")), "unused".to_owned())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "This server already has the maximum of 1 sounds."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_downloads_probes_and_stores_the_sound() {
+        let setup = setup();
+        let data = &setup.data;
+        setup
+            .media
+            .add_sound("https://example.com/bonk", b"bonk!", 1500);
+
+        let (id, added_as) = sounds::add(
+            data,
+            GUILD,
+            BOB,
+            Some(name("bonk")),
+            "https://example.com/bonk".to_owned(),
+        )
+        .await
+        .unwrap();
+
+        let sound = data
+            .db
+            .find_sound(GUILD, &name("bonk"))
+            .await
+            .unwrap()
+            .unwrap();
+        let file_hash = format!("{:x}", Sha256::digest(b"bonk!"));
+        assert_eq!(sound.id, id);
+        assert_eq!(added_as, name("bonk"));
+        assert_eq!(sound.source, "https://example.com/bonk");
+        assert_eq!(sound.uploader_id, BOB.0 as i64);
+        assert_eq!(sound.length, 1500);
+        assert_eq!(sound.file_hash.as_ref(), Some(&file_hash));
+        assert_eq!(setup.store.get(&file_hash).await.unwrap(), b"bonk!");
+        assert_eq!(data.db.usage(GUILD).await.unwrap().total_size, 5);
+    }
+
+    #[tokio::test]
+    async fn add_stores_the_sources_metadata() {
+        let setup = setup();
+        let data = &setup.data;
+        let metadata = SoundMetadata {
+            title: Some("Bonk".to_owned()),
+            uploader: Some("Horny Jail".to_owned()),
+            original_url: Some("https://example.com/bonk".to_owned()),
+            extractor: Some("generic".to_owned()),
+            duration: Some(1.5),
+            ..Default::default()
+        };
+        setup.media.add_sound_with_metadata(
+            "https://example.com/bonk",
+            b"bonk!",
+            1500,
+            metadata.clone(),
+        );
+
+        sounds::add(
+            data,
+            GUILD,
+            BOB,
+            Some(name("bonk")),
+            "https://example.com/bonk".to_owned(),
+        )
+        .await
+        .unwrap();
+
+        let (sound, stored) = sounds::info(data, GUILD, &name("BONK")).await.unwrap();
+        assert_eq!(sound.name, "bonk");
+        assert_eq!(stored, Some(metadata));
+    }
+
+    #[tokio::test]
+    async fn add_names_sounds_after_their_titles_by_default() {
+        let setup = setup();
+        let data = &setup.data;
+        let metadata = SoundMetadata {
+            title: Some("  Bonk / The\tMovie ".to_owned()),
+            ..Default::default()
+        };
+        setup
+            .media
+            .add_sound_with_metadata("https://example.com/bonk", b"bonk!", 1500, metadata);
+
+        let (id, added_as) = sounds::add(
+            data,
+            GUILD,
+            BOB,
+            None,
+            "https://example.com/bonk".to_owned(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(added_as, name("Bonk The Movie"));
+        let sound = data.db.find_sound(GUILD, &added_as).await.unwrap().unwrap();
+        assert_eq!(sound.id, id);
+    }
+
+    #[tokio::test]
+    async fn add_needs_a_name_if_the_title_is_missing_or_taken() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        setup
+            .media
+            .add_sound("https://example.com/untitled", b"untitled", 1000);
+        let metadata = SoundMetadata {
+            title: Some("BONK".to_owned()),
+            ..Default::default()
+        };
+        setup
+            .media
+            .add_sound_with_metadata("https://example.com/bonk", b"bonk!", 1500, metadata);
+
+        let error = sounds::add(
+            data,
+            GUILD,
+            BOB,
+            None,
+            "https://example.com/untitled".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Couldn't come up with a name for the sound; give it one."
+        );
+
+        let error = sounds::add(
+            data,
+            GUILD,
+            BOB,
+            None,
+            "https://example.com/bonk".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "A sound named `BONK` already exists; give the sound another name."
+        );
+        assert_eq!(names(data, GUILD).await, ["bonk"]);
+    }
+
+    #[tokio::test]
+    async fn info_needs_a_sound() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        let (_, metadata) = sounds::info(data, GUILD, &name("bonk")).await.unwrap();
+        assert_eq!(metadata, None);
+
+        let error = sounds::info(data, OTHER_GUILD, &name("bonk"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `bonk`.");
+    }
+
+    /// Try adding `source` as `bonk`, expecting it to fail without storing anything.
+    async fn add_fails(setup: &Setup, source: &str) -> String {
+        let error = sounds::add(
+            &setup.data,
+            GUILD,
+            BOB,
+            Some(name("bonk")),
+            source.to_owned(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(setup
+            .data
+            .db
+            .find_sound(GUILD, &name("bonk"))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(setup.store.list().await.unwrap().is_empty());
+
+        error.to_string()
+    }
+
+    #[tokio::test]
+    async fn add_reports_download_failures() {
+        let setup = setup();
+        setup
+            .media
+            .add_failure("https://example.com/gone", "Video unavailable");
+
+        assert_eq!(
+            add_fails(&setup, "https://example.com/gone").await,
+            "Video unavailable"
+        );
+        assert_eq!(
+            add_fails(&setup, "https://example.com/unknown").await,
+            "Couldn't download a sound from https://example.com/unknown."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_rejects_files_that_arent_sounds() {
+        let setup = setup();
+        setup.media.add_file("https://example.com/page", b"<html>");
+
+        assert_eq!(
+            add_fails(&setup, "https://example.com/page").await,
+            "Couldn't determine the length of the sound; it's probably not a sound file."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_rejects_sounds_that_are_too_long() {
+        let setup = setup();
+        set_limits(
+            &setup.data,
+            GUILD,
+            GuildLimits {
+                max_length: Some(1000),
+                ..Default::default()
+            },
+        )
+        .await;
+        setup
+            .media
+            .add_sound("https://example.com/long", b"long", 2000);
+
+        assert_eq!(
+            add_fails(&setup, "https://example.com/long").await,
+            "Nothing was downloaded from https://example.com/long. It might be longer than 1.0 seconds."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_rejects_files_that_are_too_large() {
+        let setup = setup();
+        set_limits(
+            &setup.data,
+            GUILD,
+            GuildLimits {
+                max_size: Some(1024),
+                ..Default::default()
+            },
+        )
+        .await;
+        setup
+            .media
+            .add_sound("https://example.com/large", &[0; 2048], 1000);
+
+        assert_eq!(
+            add_fails(&setup, "https://example.com/large").await,
+            "The sound at https://example.com/large is larger than 1.0 KiB."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_rejects_sounds_over_the_storage_limit() {
+        let setup = setup();
+        set_limits(
+            &setup.data,
+            GUILD,
+            GuildLimits {
+                max_total_size: Some(1536),
+                ..Default::default()
+            },
+        )
+        .await;
+        setup
+            .media
+            .add_sound("https://example.com/large", &[0; 1024], 1000);
+
+        // uses 1 KiB of the 1.5 KiB.
+        seed(&setup, GUILD, "boop").await;
+
+        let error = sounds::add(
+            &setup.data,
+            GUILD,
+            BOB,
+            Some(name("bonk")),
+            "https://example.com/large".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The sound is 1.0 KiB, but this server only has 0.5 KiB of storage left."
+        );
+    }
+
+    #[tokio::test]
+    async fn add_gives_up_on_slow_downloads() {
+        let setup = setup();
+        setup.media.add_hang("https://example.com/slow");
+
+        assert_eq!(
+            add_fails(&setup, "https://example.com/slow").await,
+            "Downloading from https://example.com/slow took longer than 0.1 seconds."
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_the_uploaders_add() {
+        let setup = setup();
+        let data = &setup.data;
+        setup.media.add_hang("https://example.com/slow");
+        setup
+            .media
+            .add_sound("https://example.com/boop", b"boop", 1000);
+
+        let (result, ()) = tokio::join!(
+            sounds::add(
+                data,
+                GUILD,
+                BOB,
+                Some(name("bonk")),
+                "https://example.com/slow".to_owned()
+            ),
+            async {
+                // let the add get going.
+                tokio::task::yield_now().await;
+
+                // only one add at a time, and only the uploader can cancel it.
+                let error = sounds::add(
+                    data,
+                    GUILD,
+                    BOB,
+                    Some(name("boop")),
+                    "https://example.com/boop".to_owned(),
+                )
+                .await
+                .unwrap_err();
+                assert_eq!(
+                    error.to_string(),
+                    "You're already adding a sound. Wait for it to finish or /cancel it first."
+                );
+                let error = sounds::cancel(data, GUILD, ALICE).unwrap_err();
+                assert_eq!(error.to_string(), "You're not adding a sound.");
+
+                sounds::cancel(data, GUILD, BOB).unwrap();
+            }
+        );
+
+        assert_eq!(result.unwrap_err().to_string(), "Cancelled adding `bonk`.");
+        assert!(names(data, GUILD).await.is_empty());
+
+        // nothing's left to cancel, and the uploader can add sounds again.
+        let error = sounds::cancel(data, GUILD, BOB).unwrap_err();
+        assert_eq!(error.to_string(), "You're not adding a sound.");
+        sounds::add(
+            data,
+            GUILD,
+            BOB,
+            Some(name("boop")),
+            "https://example.com/boop".to_owned(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn finish_adds_cancels_slow_adds_and_refuses_new_ones() {
+        let setup = setup();
+        let data = &setup.data;
+        setup.media.add_hang("https://example.com/slow");
+        setup
+            .media
+            .add_sound("https://example.com/boop", b"boop", 1000);
+
+        let (result, ()) = tokio::join!(
+            sounds::add(
+                data,
+                GUILD,
+                BOB,
+                Some(name("bonk")),
+                "https://example.com/slow".to_owned()
+            ),
+            async {
+                tokio::task::yield_now().await;
+                assert_eq!(data.adds.count(), 1);
+
+                // the download outlasts the grace period, so it's cancelled.
+                sounds::finish_adds(data, Duration::from_millis(10)).await;
+                assert_eq!(data.adds.count(), 0);
+            }
+        );
+        assert_eq!(result.unwrap_err().to_string(), "Cancelled adding `bonk`.");
+
+        let error = sounds::add(
+            data,
+            GUILD,
+            ALICE,
+            Some(name("boop")),
+            "https://example.com/boop".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Bernie is shutting down. Try adding the sound again once it's back."
+        );
+        assert!(names(data, GUILD).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_only_shows_the_guilds_sounds_by_name() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "zap").await;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, OTHER_GUILD, "boop").await;
+
+        assert_eq!(names(data, GUILD).await, ["bonk", "zap"]);
+        assert_eq!(names(data, OTHER_GUILD).await, ["boop"]);
+    }
+
+    #[tokio::test]
+    async fn rename_changes_the_name() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        sounds::rename(data, GUILD, &name("BONK"), &name("boop"))
+            .await
+            .unwrap();
+
+        assert_eq!(names(data, GUILD).await, ["boop"]);
+    }
+
+    #[tokio::test]
+    async fn rename_can_change_case() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        sounds::rename(data, GUILD, &name("bonk"), &name("Bonk"))
+            .await
+            .unwrap();
+
+        assert_eq!(names(data, GUILD).await, ["Bonk"]);
+    }
+
+    #[tokio::test]
+    async fn rename_rejects_missing_and_taken_names() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+
+        let error = sounds::rename(data, GUILD, &name("zap"), &name("zip"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `zap`.");
+
+        let error = sounds::rename(data, GUILD, &name("bonk"), &name("Boop"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "A sound named `Boop` already exists.");
+
+        assert_eq!(names(data, GUILD).await, ["bonk", "boop"]);
+    }
+
+    #[tokio::test]
+    async fn refresh_replaces_the_file_but_keeps_the_sound() {
+        let setup = setup();
+        let data = &setup.data;
+        let id = seed(&setup, GUILD, "bonk").await;
+        let playback_id = playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        let metadata = SoundMetadata {
+            title: Some("Bonk (remastered)".to_owned()),
+            ..Default::default()
+        };
+        setup.media.add_sound_with_metadata(
+            "https://example.com/bonk",
+            b"BONK!",
+            2000,
+            metadata.clone(),
+        );
+        // the new file replaces the old one, so it doesn't need room of its own.
+        set_limits(
+            data,
+            GUILD,
+            GuildLimits {
+                max_total_size: Some(1024),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let sound = sounds::refresh(data, GUILD, &name("BONK")).await.unwrap();
+
+        let file_hash = format!("{:x}", Sha256::digest(b"BONK!"));
+        assert_eq!(sound.id, id);
+        assert_eq!(sound.name, "bonk");
+        assert_eq!(sound.length, 2000);
+        assert_eq!(sound.file_hash.as_ref(), Some(&file_hash));
+        assert_eq!(setup.store.get(&file_hash).await.unwrap(), b"BONK!");
+        assert_eq!(data.db.sound_metadata(id).await.unwrap(), Some(metadata));
+
+        let history = playbacks::history(data, GUILD, Some(&name("bonk")))
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, playback_id);
+    }
+
+    #[tokio::test]
+    async fn refresh_leaves_the_sound_alone_when_it_fails() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+        setup
+            .media
+            .add_failure("https://example.com/bonk", "Video unavailable.");
+        setup
+            .media
+            .add_sound("https://example.com/boop", b"boooooop", 3000);
+        set_limits(
+            data,
+            GUILD,
+            GuildLimits {
+                max_length: Some(2000),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let error = sounds::refresh(data, GUILD, &name("zap"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `zap`.");
+
+        let error = sounds::refresh(data, GUILD, &name("bonk"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Video unavailable.");
+
+        let error = sounds::refresh(data, GUILD, &name("boop"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Nothing was downloaded from https://example.com/boop. It might be longer than 2.0 seconds."
+        );
+
+        for sound in sounds::list(data, GUILD).await.unwrap() {
+            assert_eq!(sound.length, 1000);
+            assert_eq!(sound.file_hash, Some(format!("hash-of-{}", sound.name)));
+        }
+    }
+
+    #[tokio::test]
+    async fn remove_hides_the_sound_and_frees_its_name() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        sounds::remove(data, GUILD, &name("Bonk")).await.unwrap();
+        assert!(names(data, GUILD).await.is_empty());
+
+        let error = sounds::remove(data, GUILD, &name("bonk"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `bonk`.");
+
+        // the name can be used again.
+        seed(&setup, GUILD, "bonk").await;
+        assert_eq!(names(data, GUILD).await, ["bonk"]);
+    }
+
+    /// Write `contents` to a temporary file, like an upload through the dashboard.
+    fn upload(contents: &[u8]) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), contents).unwrap();
+        file
+    }
+
+    #[tokio::test]
+    async fn add_upload_stores_the_file_named_after_it() {
+        let setup = setup();
+        let data = &setup.data;
+        // uploads are probed like downloads, so the fake needs to know the contents.
+        setup.media.add_sound("unused", b"honk!", 800);
+
+        let (id, added_as) = sounds::add_upload(
+            data,
+            GUILD,
+            BOB,
+            None,
+            "Goose Honk.ogg".to_owned(),
+            upload(b"honk!"),
+        )
+        .await
+        .unwrap();
+
+        let sound = data.db.find_sound(GUILD, &added_as).await.unwrap().unwrap();
+        let file_hash = format!("{:x}", Sha256::digest(b"honk!"));
+        assert_eq!(sound.id, id);
+        assert_eq!(sound.name, "Goose Honk");
+        assert_eq!(sound.source, "upload:Goose Honk.ogg");
+        assert_eq!(sound.length, 800);
+        assert_eq!(setup.store.get(&file_hash).await.unwrap(), b"honk!");
+
+        let error = sounds::refresh(data, GUILD, &added_as).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`Goose Honk` was uploaded, so it can't be downloaded again."
+        );
+
+        let error = sounds::add_upload(
+            data,
+            GUILD,
+            BOB,
+            Some(name("notes")),
+            "notes.txt".to_owned(),
+            upload(b"not a sound"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Couldn't determine the length of the sound; it's probably not a sound file."
+        );
+    }
+
+    #[tokio::test]
+    async fn tag_normalizes_tags_and_search_finds_them() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+        seed(&setup, GUILD, "honk").await;
+
+        let tags = [
+            " Funny ".to_owned(),
+            "cartoon".to_owned(),
+            "FUNNY".to_owned(),
+        ];
+        let stored = sounds::tag(data, GUILD, &name("bonk"), &tags)
+            .await
+            .unwrap();
+        assert_eq!(stored, ["cartoon", "funny"]);
+        sounds::tag(data, GUILD, &name("honk"), &["goose".to_owned()])
+            .await
+            .unwrap();
+
+        let found = |query: &'static str| async move {
+            sounds::search(data, GUILD, query)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|tagged| tagged.sound.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found("BO").await, ["bonk", "boop"]);
+        assert_eq!(found("fun").await, ["bonk"]);
+        assert_eq!(found("oose").await, Vec::<String>::new());
+        assert_eq!(found("goose").await, ["honk"]);
+
+        let tagged = sounds::list_tagged(data, GUILD).await.unwrap();
+        assert_eq!(tagged[0].tags, ["cartoon", "funny"]);
+        assert!(tagged[1].tags.is_empty());
+
+        // tags are replaced, not added to.
+        sounds::tag(data, GUILD, &name("bonk"), &[]).await.unwrap();
+        assert_eq!(found("fun").await, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn tag_rejects_bad_tags() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        let tag_fails = |tags: Vec<String>| async move {
+            sounds::tag(data, GUILD, &name("bonk"), &tags)
+                .await
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            tag_fails(vec![" ".to_owned()]).await,
+            "Tags can't be empty."
+        );
+        assert_eq!(
+            tag_fails(vec!["a".repeat(33)]).await,
+            "Tags can't be longer than 32 characters."
+        );
+        assert_eq!(
+            tag_fails(vec!["two words".to_owned()]).await,
+            "Tags can't contain spaces or control characters."
+        );
+        assert_eq!(
+            tag_fails((0..11).map(|i| format!("tag{i}")).collect()).await,
+            "Sounds can't have more than 10 tags."
+        );
+
+        let error = sounds::tag(data, GUILD, &name("boop"), &[])
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "There's no sound named `boop`.");
+    }
+
+    #[tokio::test]
+    async fn set_volume_changes_how_loud_the_sound_plays() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        sounds::set_volume(data, GUILD, &name("BONK"), 50)
+            .await
+            .unwrap();
+        playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name("bonk"))
+            .await
+            .unwrap();
+        assert_eq!(setup.voice.playing(GUILD)[0].track.volume, 50);
+
+        let error = sounds::set_volume(data, GUILD, &name("bonk"), 201)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Volume has to be between 0 and 200%.");
+        let (sound, _) = sounds::info(data, GUILD, &name("bonk")).await.unwrap();
+        assert_eq!(sound.volume, 50);
+    }
+
+    #[tokio::test]
+    async fn file_gets_the_sounds_contents() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+
+        let (sound, contents) = sounds::file(data, GUILD, &name("BONK")).await.unwrap();
+        assert_eq!(sound.name, "bonk");
+        assert_eq!(contents, b"bonk");
+
+        assert!(sounds::file(data, OTHER_GUILD, &name("bonk"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn stats_show_usage_and_the_most_played_sounds() {
+        let setup = setup();
+        let data = &setup.data;
+        seed(&setup, GUILD, "bonk").await;
+        seed(&setup, GUILD, "boop").await;
+        seed(&setup, GUILD, "honk").await;
+        for sound in ["boop", "bonk", "boop"] {
+            playbacks::play(data, GUILD, Some(CHANNEL), ALICE, &[], &name(sound))
+                .await
+                .unwrap();
+        }
+
+        let stats = sounds::stats(data, GUILD).await.unwrap();
+        assert_eq!(stats.usage.sounds, 3);
+        assert_eq!(stats.limits, NO_LIMITS);
+        assert_eq!(
+            stats.most_played,
+            [("boop".to_owned(), 2), ("bonk".to_owned(), 1)]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use poise::serenity_prelude::UserId;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::{Data, Error};
+
+/// Starts every API token, so they're easy to recognize if they leak.
+const TOKEN_PREFIX: &str = "bernie_";
+
+/// Give `user_id` a new API token, replacing the one they had. Only its hash is kept, so this is
+/// the only time it can be seen.
+pub async fn create(data: &Data, user_id: UserId) -> Result<String, Error> {
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    let token = format!("{TOKEN_PREFIX}{secret}");
+
+    data.db.set_api_token(user_id, &hash(&token)).await?;
+
+    Ok(token)
+}
+
+/// Remove the API token of `user_id`, returning whether they had one.
+pub async fn revoke(data: &Data, user_id: UserId) -> Result<bool, Error> {
+    data.db.remove_api_token(user_id).await
+}
+
+/// Get who `token` belongs to, if it's a valid API token.
+pub async fn authenticate(data: &Data, token: &str) -> Result<Option<UserId>, Error> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    data.db.api_token_user(&hash(token)).await
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{setup, ALICE, BOB};
+    use crate::services::tokens;
+
+    #[tokio::test]
+    async fn api_tokens_identify_their_user_until_replaced_or_revoked() {
+        let setup = setup();
+        let data = &setup.data;
+
+        let first = tokens::create(data, ALICE).await.unwrap();
+        let bobs = tokens::create(data, BOB).await.unwrap();
+        assert_ne!(first, bobs);
+        assert_eq!(
+            tokens::authenticate(data, &first).await.unwrap(),
+            Some(ALICE)
+        );
+        assert_eq!(tokens::authenticate(data, &bobs).await.unwrap(), Some(BOB));
+        assert_eq!(
+            tokens::authenticate(data, "bernie_nope").await.unwrap(),
+            None
+        );
+
+        // a new token replaces the old one.
+        let second = tokens::create(data, ALICE).await.unwrap();
+        assert_eq!(tokens::authenticate(data, &first).await.unwrap(), None);
+        assert_eq!(
+            tokens::authenticate(data, &second).await.unwrap(),
+            Some(ALICE)
+        );
+
+        assert!(tokens::revoke(data, ALICE).await.unwrap());
+        assert!(!tokens::revoke(data, ALICE).await.unwrap());
+        assert_eq!(tokens::authenticate(data, &second).await.unwrap(), None);
+        assert_eq!(tokens::authenticate(data, &bobs).await.unwrap(), Some(BOB));
+    }
+}
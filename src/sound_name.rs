@@ -0,0 +1,183 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest a sound name can be, in characters.
+pub const MAX_LENGTH: usize = 64;
+
+/// A valid sound name.
+///
+/// Sound names are unique within a guild regardless of case, so they should be compared by their
+/// [`key`](SoundName::key): with `lower(name)` in Postgres, and by the stored `name_key` in SQLite,
+/// whose `lower` only folds ASCII.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SoundName(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidSoundName {
+    Empty,
+    TooLong,
+    PathSeparator,
+    ControlCharacter,
+    OnlyDots,
+}
+
+impl fmt::Display for InvalidSoundName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Sound names can't be empty."),
+            Self::TooLong => write!(
+                f,
+                "Sound names can't be longer than {MAX_LENGTH} characters."
+            ),
+            Self::PathSeparator => write!(f, "Sound names can't contain `/` or `\\`."),
+            Self::ControlCharacter => write!(f, "Sound names can't contain control characters."),
+            Self::OnlyDots => write!(f, "Sound names can't be made up of only dots."),
+        }
+    }
+}
+
+impl std::error::Error for InvalidSoundName {}
+
+impl SoundName {
+    /// Validate `name`, ignoring leading and trailing whitespace.
+    pub fn new(name: &str) -> Result<Self, InvalidSoundName> {
+        let name = name.trim();
+
+        if name.is_empty() {
+            return Err(InvalidSoundName::Empty);
+        }
+        if name.chars().count() > MAX_LENGTH {
+            return Err(InvalidSoundName::TooLong);
+        }
+        if name.contains(['/', '\\']) {
+            return Err(InvalidSoundName::PathSeparator);
+        }
+        if name.chars().any(char::is_control) {
+            return Err(InvalidSoundName::ControlCharacter);
+        }
+        if name.chars().all(|c| c == '.') {
+            return Err(InvalidSoundName::OnlyDots);
+        }
+
+        Ok(Self(name.to_owned()))
+    }
+
+    /// Make a name out of something like a video title, replacing characters names can't contain
+    /// with spaces and cutting it down to [`MAX_LENGTH`]. Returns `None` if nothing usable is left.
+    pub fn suggest(title: &str) -> Option<Self> {
+        let cleaned = title.replace(|c: char| c == '/' || c == '\\' || c.is_control(), " ");
+        let words: Vec<&str> = cleaned.split_whitespace().collect();
+        let shortened: String = words.join(" ").chars().take(MAX_LENGTH).collect();
+
+        Self::new(&shortened).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// The form of the name that uniqueness is checked against.
+    pub fn key(&self) -> String {
+        name_key(&self.0)
+    }
+}
+
+/// The form of a stored sound's name that uniqueness is checked against, like [`SoundName::key`].
+pub fn name_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+impl FromStr for SoundName {
+    type Err = InvalidSoundName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for SoundName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for SoundName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_trimmed() {
+        assert_eq!(SoundName::new("  bonk \t").unwrap().as_str(), "bonk");
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let cases = [
+            ("", InvalidSoundName::Empty),
+            ("   ", InvalidSoundName::Empty),
+            ("a/b", InvalidSoundName::PathSeparator),
+            ("a\\b", InvalidSoundName::PathSeparator),
+            ("../bonk", InvalidSoundName::PathSeparator),
+            ("bo\nnk", InvalidSoundName::ControlCharacter),
+            ("bo\u{7}nk", InvalidSoundName::ControlCharacter),
+            (".", InvalidSoundName::OnlyDots),
+            ("..", InvalidSoundName::OnlyDots),
+            (" ... ", InvalidSoundName::OnlyDots),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(SoundName::new(name), Err(expected), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn names_can_be_up_to_64_characters() {
+        assert!(SoundName::new(&"é".repeat(MAX_LENGTH)).is_ok());
+        assert_eq!(
+            SoundName::new(&"é".repeat(MAX_LENGTH + 1)),
+            Err(InvalidSoundName::TooLong)
+        );
+    }
+
+    #[test]
+    fn names_with_dots_and_other_characters_are_fine() {
+        for name in [".bonk", "bonk.mp3", "...!", "Été 🎵", "a b"] {
+            assert!(SoundName::new(name).is_ok(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn keys_fold_case_beyond_ascii() {
+        assert_eq!(SoundName::new("BoNk").unwrap().key(), "bonk");
+        assert_eq!(
+            SoundName::new("ÉTÉ").unwrap().key(),
+            SoundName::new("été").unwrap().key()
+        );
+        assert_eq!(name_key("ÉTÉ"), "été");
+    }
+
+    #[test]
+    fn suggestions_are_cleaned_up_titles() {
+        let suggest = |title| SoundName::suggest(title).map(SoundName::into_string);
+
+        assert_eq!(
+            suggest("  AC/DC -\tBack in\nBlack  ").as_deref(),
+            Some("AC DC - Back in Black")
+        );
+        assert_eq!(
+            suggest(&"word ".repeat(20)).unwrap().chars().count(),
+            MAX_LENGTH
+        );
+        assert_eq!(suggest("/\\\n"), None);
+        assert_eq!(suggest("..."), None);
+    }
+}
@@ -0,0 +1,359 @@
+//! Migration of data from [SoundBert](https://github.com/dsluo/SoundBert), Bernie's predecessor.
+//!
+//! SoundBert's tables are read from JSON exports of its database, e.g.
+//!
+//! ```shell
+//! psql soundbert -Atc "select json_agg(s) from sounds s" > sounds.json
+//! psql soundbert -Atc "select json_agg(p) from plays p" > plays.json
+//! ```
+//!
+//! Sound files are read from SoundBert's sound directory, which holds one directory per guild.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::Deserialize;
+
+use crate::db::{NewPlayback, NewSound, Repository, DEFAULT_VOLUME};
+use crate::media::Prober;
+use crate::sound_name::{name_key, SoundName};
+use crate::store::{hash_file, SoundStore};
+use crate::Error;
+
+/// A row of SoundBert's `sounds` table.
+#[derive(Debug, Deserialize)]
+pub struct LegacySound {
+    pub id: i64,
+    pub guild_id: i64,
+    pub name: String,
+    /// Name of the sound's file in its guild's directory. Defaults to the sound's name.
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default, alias = "uploader")]
+    pub uploader_id: Option<i64>,
+    #[serde(default, alias = "upload_time")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A row of SoundBert's `plays` table.
+#[derive(Debug, Deserialize)]
+pub struct LegacyPlay {
+    pub sound_id: i64,
+    #[serde(alias = "user_id")]
+    pub player_id: i64,
+    #[serde(alias = "played_at", alias = "time")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub stopper_id: Option<i64>,
+    #[serde(default)]
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+/// A SoundBert data dump.
+#[derive(Debug)]
+pub struct Dump {
+    pub sounds: Vec<LegacySound>,
+    pub plays: Vec<LegacyPlay>,
+    pub sound_dir: PathBuf,
+}
+
+impl Dump {
+    /// Read a dump from its JSON exports and sound directory.
+    pub async fn load(
+        sounds: &Path,
+        plays: Option<&Path>,
+        sound_dir: &Path,
+    ) -> Result<Self, Error> {
+        let sounds = serde_json::from_slice(&tokio::fs::read(sounds).await?)?;
+        let plays = match plays {
+            Some(plays) => serde_json::from_slice(&tokio::fs::read(plays).await?)?,
+            None => vec![],
+        };
+
+        Ok(Self {
+            sounds,
+            plays,
+            sound_dir: sound_dir.to_path_buf(),
+        })
+    }
+}
+
+/// The outcome of a migration.
+#[derive(Debug, Default)]
+pub struct MigrationSummary {
+    pub guilds: usize,
+    pub sounds: usize,
+    pub playbacks: usize,
+    /// Sounds that weren't migrated, with the reason.
+    pub skipped: Vec<(i64, String, String)>,
+    /// Plays that weren't migrated because their sound wasn't.
+    pub skipped_playbacks: usize,
+}
+
+/// Create guilds, sounds and playbacks from `dump`, copying sound files into `store`.
+///
+/// Sounds whose names are already taken in Bernie are skipped, so a migration can safely be re-run.
+/// So are sounds whose names aren't valid [`SoundName`]s, or whose files aren't plain file names in
+/// their guild's directory, and their plays.
+/// Everything is added in one transaction.
+pub async fn migrate(
+    db: &dyn Repository,
+    store: &dyn SoundStore,
+    prober: &dyn Prober,
+    dump: Dump,
+) -> Result<MigrationSummary, Error> {
+    let mut summary = MigrationSummary::default();
+
+    // probe everything before touching the database.
+    let mut sounds = vec![];
+    for sound in dump.sounds {
+        // names can't contain path separators, so a sound without a file name stays in its
+        // guild's directory. file names have to be checked on their own.
+        let name = match SoundName::new(&sound.name) {
+            Ok(name) => name,
+            Err(error) => {
+                summary
+                    .skipped
+                    .push((sound.guild_id, sound.name, error.to_string()));
+                continue;
+            }
+        };
+        let file_name = sound.filename.as_deref().unwrap_or_else(|| name.as_str());
+        if !is_plain_file_name(file_name) {
+            summary.skipped.push((
+                sound.guild_id,
+                sound.name,
+                format!("{file_name:?} isn't a file name."),
+            ));
+            continue;
+        }
+        let path = dump
+            .sound_dir
+            .join(sound.guild_id.to_string())
+            .join(file_name);
+
+        if !path.is_file() {
+            summary.skipped.push((
+                sound.guild_id,
+                sound.name,
+                format!("{path:?} doesn't exist."),
+            ));
+            continue;
+        }
+
+        match prober.probe_length(path.as_os_str()).await {
+            Ok(length) => {
+                let file_hash = hash_file(&path).await?;
+                let file_size = tokio::fs::metadata(&path).await?.len() as i64;
+                sounds.push((sound, name, path, length, file_hash, file_size));
+            }
+            Err(error) => summary
+                .skipped
+                .push((sound.guild_id, sound.name, error.to_string())),
+        }
+    }
+
+    // names taken in each guild, keyed like they're compared in the database.
+    let mut existing: HashMap<i64, HashSet<String>> = HashMap::new();
+    let mut guilds = HashSet::new();
+    let mut sound_indices = HashMap::new();
+    let mut new_sounds = vec![];
+    let mut copies = vec![];
+
+    for (sound, name, path, length, file_hash, file_size) in sounds {
+        let taken = match existing.entry(sound.guild_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                db.guild_sounds(GuildId(sound.guild_id as u64))
+                    .await?
+                    .into_iter()
+                    .map(|sound| name_key(&sound.name))
+                    .collect(),
+            ),
+        };
+
+        if !taken.insert(name.key()) {
+            summary.skipped.push((
+                sound.guild_id,
+                name.into_string(),
+                "A sound with this name already exists.".to_owned(),
+            ));
+            continue;
+        }
+
+        guilds.insert(sound.guild_id);
+        sound_indices.insert(sound.id, new_sounds.len());
+        copies.push((path, file_hash.clone()));
+        new_sounds.push(NewSound {
+            guild_id: GuildId(sound.guild_id as u64),
+            name,
+            source: sound.source.unwrap_or_default(),
+            uploader_id: UserId(sound.uploader_id.unwrap_or_default() as u64),
+            length,
+            created_at: sound.created_at,
+            file_hash,
+            file_size,
+            metadata: None,
+            volume: DEFAULT_VOLUME,
+        });
+    }
+    summary.guilds = guilds.len();
+    summary.sounds = new_sounds.len();
+
+    let mut playbacks = vec![];
+    for play in dump.plays {
+        // plays of sounds that weren't migrated have nothing to point to.
+        let sound = match sound_indices.get(&play.sound_id) {
+            Some(sound) => *sound,
+            None => {
+                summary.skipped_playbacks += 1;
+                continue;
+            }
+        };
+
+        playbacks.push(NewPlayback {
+            sound,
+            player_id: UserId(play.player_id as u64),
+            created_at: play.created_at,
+            stopper_id: play.stopper_id.map(|id| UserId(id as u64)),
+            stopped_at: play.stopped_at,
+        });
+    }
+    summary.playbacks = playbacks.len();
+
+    // store files first; if adding the sounds fails, they're left for garbage collection.
+    for (path, file_hash) in copies {
+        store.put(&file_hash, &path).await?;
+    }
+
+    db.import_sounds(&[], &new_sounds, &playbacks).await?;
+
+    Ok(summary)
+}
+
+/// Whether `file_name` names a file directly inside a directory, rather than somewhere else.
+fn is_plain_file_name(file_name: &str) -> bool {
+    let mut components = Path::new(file_name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{names, setup, Setup, GUILD};
+    use crate::services::playbacks;
+
+    fn legacy_sound(id: i64, name: &str, filename: Option<&str>) -> LegacySound {
+        LegacySound {
+            id,
+            guild_id: GUILD.0 as i64,
+            name: name.to_owned(),
+            filename: filename.map(str::to_owned),
+            source: Some(format!("https://example.com/{id}")),
+            uploader_id: Some(100),
+            created_at: None,
+        }
+    }
+
+    fn legacy_play(sound_id: i64) -> LegacyPlay {
+        LegacyPlay {
+            sound_id,
+            player_id: 101,
+            created_at: Utc::now(),
+            stopper_id: None,
+            stopped_at: None,
+        }
+    }
+
+    /// A dump of `GUILD` with one good sound, `bonk`, and a few that can't be migrated.
+    fn dump(sound_dir: &Path) -> Dump {
+        Dump {
+            sounds: vec![
+                legacy_sound(1, "bonk", Some("bonk.mp3")),
+                legacy_sound(2, "b/oop", None),
+                legacy_sound(3, "zap", Some("../2/zap.mp3")),
+                legacy_sound(4, "honk", None),
+            ],
+            plays: vec![legacy_play(1), legacy_play(1), legacy_play(3)],
+            sound_dir: sound_dir.to_path_buf(),
+        }
+    }
+
+    async fn migrate_dump(setup: &Setup, sound_dir: &Path) -> MigrationSummary {
+        let data = &setup.data;
+
+        migrate(&*data.db, &setup.store, &setup.media, dump(sound_dir))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn migration_skips_sounds_it_cant_use_and_their_plays() {
+        let setup = setup();
+        let sound_dir = tempfile::tempdir().unwrap();
+        let guild_dir = sound_dir.path().join(GUILD.0.to_string());
+        std::fs::create_dir(&guild_dir).unwrap();
+        std::fs::write(guild_dir.join("bonk.mp3"), b"bonk").unwrap();
+        setup
+            .media
+            .add_sound("https://example.com/1", b"bonk", 1000);
+
+        let summary = migrate_dump(&setup, sound_dir.path()).await;
+
+        assert_eq!(summary.guilds, 1);
+        assert_eq!(summary.sounds, 1);
+        assert_eq!(summary.playbacks, 2);
+        assert_eq!(summary.skipped_playbacks, 1);
+        let skipped: Vec<(&str, &str)> = summary
+            .skipped
+            .iter()
+            .map(|(_, name, reason)| (name.as_str(), reason.as_str()))
+            .collect();
+        let missing = format!("{:?} doesn't exist.", guild_dir.join("honk"));
+        assert_eq!(
+            skipped,
+            [
+                ("b/oop", "Sound names can't contain `/` or `\\`."),
+                ("zap", "\"../2/zap.mp3\" isn't a file name."),
+                ("honk", missing.as_str()),
+            ]
+        );
+
+        assert_eq!(names(&setup.data, GUILD).await, ["bonk"]);
+        let history = playbacks::history(&setup.data, GUILD, None).await.unwrap();
+        assert_eq!(history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn migrating_again_skips_sounds_that_were_migrated() {
+        let setup = setup();
+        let sound_dir = tempfile::tempdir().unwrap();
+        let guild_dir = sound_dir.path().join(GUILD.0.to_string());
+        std::fs::create_dir(&guild_dir).unwrap();
+        std::fs::write(guild_dir.join("bonk.mp3"), b"bonk").unwrap();
+        setup
+            .media
+            .add_sound("https://example.com/1", b"bonk", 1000);
+
+        migrate_dump(&setup, sound_dir.path()).await;
+        let summary = migrate_dump(&setup, sound_dir.path()).await;
+
+        assert_eq!(summary.sounds, 0);
+        assert_eq!(summary.playbacks, 0);
+        assert_eq!(summary.skipped_playbacks, 3);
+        assert!(summary.skipped.contains(&(
+            GUILD.0 as i64,
+            "bonk".to_owned(),
+            "A sound with this name already exists.".to_owned()
+        )));
+        assert_eq!(names(&setup.data, GUILD).await, ["bonk"]);
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{Duration, Utc};
+use poise::serenity_prelude::GuildId;
+use serde::Deserialize;
+use tempfile::NamedTempFile;
+
+use crate::db::{Repository, Sound};
+use crate::media::{DownloadLimits, Downloader, Prober};
+use crate::quota::Limits;
+use crate::store::{hash_file, SoundStore};
+use crate::Error;
+
+/// Directory under the storage directory where stray files are moved by [`repair`].
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// How long a file has to have gone without being put before it's collected. Files are put before
+/// the sounds using them are added, so newer ones might belong to sounds that are being added.
+fn garbage_grace_period() -> Duration {
+    Duration::hours(1)
+}
+
+/// Differences between the `sounds` table and the sound store.
+#[derive(Debug, Default)]
+pub struct StorageReport {
+    /// Live sounds without a file.
+    pub missing_files: Vec<Sound>,
+    /// Files in the store that no live sound uses.
+    pub unreferenced_files: Vec<String>,
+    /// Files left in the storage directory from before sounds were stored by hash.
+    pub stray_files: Vec<PathBuf>,
+    /// Live sounds whose stored length doesn't match their file, with the file's actual length.
+    /// The actual length is `None` if the file couldn't be probed.
+    pub wrong_lengths: Vec<(Sound, Option<i32>)>,
+}
+
+impl StorageReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_files.is_empty()
+            && self.unreferenced_files.is_empty()
+            && self.stray_files.is_empty()
+            && self.wrong_lengths.is_empty()
+    }
+}
+
+impl fmt::Display for StorageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_consistent() {
+            return write!(f, "Storage is consistent.");
+        }
+
+        let mut problems = vec![];
+        for sound in self.missing_files.iter() {
+            problems.push(format!(
+                "Missing file for `{}` in guild {}.",
+                sound.name, sound.guild_id
+            ));
+        }
+        for hash in self.unreferenced_files.iter() {
+            problems.push(format!("Unreferenced file {hash}."));
+        }
+        for path in self.stray_files.iter() {
+            problems.push(format!("Stray file {path:?}."));
+        }
+        for (sound, actual) in self.wrong_lengths.iter() {
+            problems.push(match actual {
+                Some(actual) => format!(
+                    "`{}` in guild {} is {actual}ms long, but is recorded as {}ms.",
+                    sound.name, sound.guild_id, sound.length
+                ),
+                None => format!(
+                    "`{}` in guild {} isn't a readable sound file.",
+                    sound.name, sound.guild_id
+                ),
+            });
+        }
+
+        write!(f, "{}", problems.join("\n"))
+    }
+}
+
+/// The outcome of [`repair`].
+#[derive(Debug, Default)]
+pub struct RepairSummary {
+    pub relengthed: usize,
+    pub redownloaded: usize,
+    pub collected: usize,
+    pub quarantined: usize,
+    /// Sounds that couldn't be fixed, as `(guild id, name, reason)`.
+    pub failed: Vec<(i64, String, String)>,
+}
+
+impl fmt::Display for RepairSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Fixed {} lengths, re-downloaded {} sounds, deleted {} unreferenced files \
+            and quarantined {} stray files.",
+            self.relengthed, self.redownloaded, self.collected, self.quarantined
+        )?;
+        for (guild_id, name, reason) in self.failed.iter() {
+            write!(
+                f,
+                "\nCouldn't repair `{name}` in guild {guild_id}: {reason}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of [`refresh_all`].
+#[derive(Debug, Default)]
+pub struct RefreshSummary {
+    pub refreshed: usize,
+    /// Sounds that couldn't be refreshed, as `(guild id, name, reason)`.
+    pub failed: Vec<(i64, String, String)>,
+}
+
+impl fmt::Display for RefreshSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Refreshed {} sounds.", self.refreshed)?;
+        for (guild_id, name, reason) in self.failed.iter() {
+            write!(
+                f,
+                "\nCouldn't refresh `{name}` in guild {guild_id}: {reason}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What to do with storage when the bot starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupCheck {
+    Off,
+    Report,
+    Repair,
+}
+
+impl FromStr for StartupCheck {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "report" => Ok(Self::Report),
+            "repair" => Ok(Self::Repair),
+            _ => Err(anyhow!(
+                "Expected one of `off`, `report` or `repair`, got {s:?}."
+            )),
+        }
+    }
+}
+
+/// Check storage at startup, as configured by `mode`.
+pub async fn check_on_startup(
+    mode: StartupCheck,
+    db: &dyn Repository,
+    storage_dir: &Path,
+    store: &dyn SoundStore,
+    downloader: &dyn Downloader,
+    prober: &dyn Prober,
+) -> Result<(), Error> {
+    if mode == StartupCheck::Off {
+        return Ok(());
+    }
+
+    tracing::info!("Checking storage.");
+    let report = check(db, storage_dir, store, prober).await?;
+    if report.is_consistent() {
+        tracing::info!("{report}");
+        return Ok(());
+    }
+    tracing::warn!("{report}");
+
+    if mode == StartupCheck::Repair {
+        let summary = repair(db, storage_dir, store, downloader, prober, report).await?;
+        tracing::info!("{summary}");
+    }
+
+    Ok(())
+}
+
+/// Move files stored under `<storage dir>/<guild id>/<sound name>` into `store`.
+///
+/// This is how sounds were stored before they were stored by hash.
+pub async fn convert_legacy_files(
+    db: &dyn Repository,
+    storage_dir: &Path,
+    store: &dyn SoundStore,
+) -> Result<usize, Error> {
+    let files = db.legacy_files().await?;
+
+    let mut converted = 0;
+    for file in files {
+        let guild_dir = storage_dir.join(file.guild_id.to_string());
+        let path = guild_dir.join(&file.file_name);
+        if !path.is_file() {
+            continue;
+        }
+
+        let file_hash = hash_file(&path).await?;
+        let file_size = tokio::fs::metadata(&path).await?.len() as i64;
+        store.put(&file_hash, &path).await?;
+
+        db.set_sound_file(file.sound_id, &file_hash, file_size)
+            .await?;
+
+        tokio::fs::remove_file(&path).await?;
+        // only succeeds once the guild's last sound is converted.
+        let _ = tokio::fs::remove_dir(&guild_dir).await;
+
+        converted += 1;
+    }
+
+    if converted > 0 {
+        tracing::info!("Moved {converted} sound files into the store.");
+    }
+
+    Ok(converted)
+}
+
+/// Record the size of stored files for sounds that were added before sizes were recorded.
+pub async fn fill_file_sizes(db: &dyn Repository, store: &dyn SoundStore) -> Result<usize, Error> {
+    let hashes = db.files_without_size().await?;
+
+    let mut filled = 0;
+    for file_hash in hashes {
+        // missing files are left to `check`.
+        if !store.exists(&file_hash).await? {
+            continue;
+        }
+
+        let file_size = store.size(&file_hash).await?;
+        db.set_file_size(&file_hash, file_size).await?;
+
+        filled += 1;
+    }
+
+    if filled > 0 {
+        tracing::info!("Recorded the size of {filled} sound files.");
+    }
+
+    Ok(filled)
+}
+
+/// Compare every live sound against the files in `store`.
+pub async fn check(
+    db: &dyn Repository,
+    storage_dir: &Path,
+    store: &dyn SoundStore,
+    prober: &dyn Prober,
+) -> Result<StorageReport, Error> {
+    let sounds = db.all_sounds().await?;
+
+    let files: HashSet<String> = store.list().await?.into_iter().collect();
+
+    let mut report = StorageReport::default();
+    let mut referenced = HashSet::new();
+    // many sounds can share a file; only probe each one once.
+    let mut lengths = HashMap::new();
+
+    for sound in sounds {
+        let file_hash = match &sound.file_hash {
+            Some(file_hash) if files.contains(file_hash) => file_hash.clone(),
+            _ => {
+                report.missing_files.push(sound);
+                continue;
+            }
+        };
+
+        let actual = match lengths.get(&file_hash) {
+            Some(actual) => *actual,
+            None => {
+                let location = store.open(&file_hash).await?;
+                let actual = prober.probe_length(&location).await.ok();
+                lengths.insert(file_hash.clone(), actual);
+                actual
+            }
+        };
+        if actual != Some(sound.length) {
+            report.wrong_lengths.push((sound, actual));
+        }
+
+        referenced.insert(file_hash);
+    }
+
+    report.unreferenced_files = files.difference(&referenced).cloned().collect();
+    report.unreferenced_files.sort();
+
+    let mut guild_dirs = tokio::fs::read_dir(storage_dir).await?;
+    while let Some(guild_dir) = guild_dirs.next_entry().await? {
+        // legacy guild directories are named after the guild's id.
+        let is_guild_dir = guild_dir
+            .file_name()
+            .to_str()
+            .map_or(false, |name| name.parse::<u64>().is_ok());
+        if !is_guild_dir || !guild_dir.file_type().await?.is_dir() {
+            continue;
+        }
+
+        let mut entries = tokio::fs::read_dir(guild_dir.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            report.stray_files.push(entry.path());
+        }
+    }
+    report.stray_files.sort();
+
+    Ok(report)
+}
+
+/// Fix the problems in `report`.
+///
+/// Wrong lengths are re-probed, missing or unreadable files are re-downloaded from their source,
+/// unreferenced files are deleted and stray files are moved to the quarantine directory in
+/// `storage_dir`.
+pub async fn repair(
+    db: &dyn Repository,
+    storage_dir: &Path,
+    store: &dyn SoundStore,
+    downloader: &dyn Downloader,
+    prober: &dyn Prober,
+    report: StorageReport,
+) -> Result<RepairSummary, Error> {
+    let mut summary = RepairSummary::default();
+
+    let mut redownloads = report.missing_files;
+    for (sound, actual) in report.wrong_lengths {
+        match actual {
+            Some(actual) => {
+                db.set_sound_length(sound.id, actual).await?;
+                summary.relengthed += 1;
+            }
+            None => redownloads.push(sound),
+        }
+    }
+
+    for sound in redownloads {
+        // the sound was already accepted once, so limits don't apply.
+        match refetch(db, store, downloader, prober, &sound, None).await {
+            Ok(()) => summary.redownloaded += 1,
+            Err(error) => summary
+                .failed
+                .push((sound.guild_id, sound.name, error.to_string())),
+        }
+    }
+
+    summary.collected = collect_garbage(db, store).await?;
+
+    for path in report.stray_files {
+        quarantine(storage_dir, &path).await?;
+        summary.quarantined += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Download `sound` from its source again, replacing its file, length and metadata.
+///
+/// The sound keeps its id, name and play history. Its old file is left for [`collect_garbage`],
+/// since other sounds might share it. With `limits`, the new file has to be short and small enough
+/// to be added as a new sound, but doesn't count against the guild's storage.
+pub async fn refetch(
+    db: &dyn Repository,
+    store: &dyn SoundStore,
+    downloader: &dyn Downloader,
+    prober: &dyn Prober,
+    sound: &Sound,
+    limits: Option<&Limits>,
+) -> Result<(), Error> {
+    let download = NamedTempFile::new()?;
+    let download_limits = limits.map_or_else(DownloadLimits::default, |limits| DownloadLimits {
+        max_length: limits.max_length,
+        max_size: limits.max_size,
+    });
+    let metadata = downloader
+        .download(&sound.source, download.path(), download_limits)
+        .await?;
+    let length = prober.probe_length(download.path().as_os_str()).await?;
+    let file_size = tokio::fs::metadata(download.path()).await?.len() as i64;
+    if let Some(limits) = limits {
+        limits.check_length_and_size(length, file_size)?;
+    }
+
+    let file_hash = hash_file(download.path()).await?;
+    store.put(&file_hash, download.path()).await?;
+
+    db.set_sound_file(sound.id, &file_hash, file_size).await?;
+    db.set_sound_length(sound.id, length).await?;
+    db.set_sound_metadata(sound.id, &metadata).await?;
+
+    Ok(())
+}
+
+/// [`refetch`] every live sound, or only those in `guild_id`, without any limits.
+pub async fn refresh_all(
+    db: &dyn Repository,
+    store: &dyn SoundStore,
+    downloader: &dyn Downloader,
+    prober: &dyn Prober,
+    guild_id: Option<GuildId>,
+) -> Result<RefreshSummary, Error> {
+    let sounds = match guild_id {
+        Some(guild_id) => db.guild_sounds(guild_id).await?,
+        None => db.all_sounds().await?,
+    };
+
+    let mut summary = RefreshSummary::default();
+    for sound in sounds {
+        tracing::info!("Refreshing `{}` in guild {}.", sound.name, sound.guild_id);
+        match refetch(db, store, downloader, prober, &sound, None).await {
+            Ok(()) => summary.refreshed += 1,
+            Err(error) => summary
+                .failed
+                .push((sound.guild_id, sound.name, error.to_string())),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Delete every file in `store` that no live sound uses, returning how many were deleted.
+///
+/// Files of removed sounds are only deleted here, since other sounds may share them. Files put
+/// within the last hour are kept, so this can run while sounds are being added.
+pub async fn collect_garbage(db: &dyn Repository, store: &dyn SoundStore) -> Result<usize, Error> {
+    let referenced: HashSet<String> = db.referenced_files().await?.into_iter().collect();
+    let cutoff = Utc::now() - garbage_grace_period();
+
+    let mut collected = 0;
+    for file_hash in store.list().await? {
+        if referenced.contains(&file_hash) {
+            continue;
+        }
+        if store.modified(&file_hash).await? > cutoff {
+            tracing::debug!("Keeping unreferenced file {file_hash}, since it's new.");
+            continue;
+        }
+
+        tracing::info!("Deleting unreferenced file {file_hash}.");
+        store.delete(&file_hash).await?;
+        collected += 1;
+    }
+
+    Ok(collected)
+}
+
+/// Move `path`, a file in a guild directory, to the same guild's directory in quarantine.
+async fn quarantine(storage_dir: &Path, path: &Path) -> Result<(), Error> {
+    let guild_dir = path
+        .parent()
+        .and_then(Path::file_name)
+        .ok_or_else(|| anyhow!("{path:?} isn't in a guild directory."))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{path:?} isn't a file."))?;
+
+    let dest_dir = storage_dir.join(QUARANTINE_DIR).join(guild_dir);
+    tokio::fs::create_dir_all(&dest_dir).await?;
+
+    let mut dest = dest_dir.join(file_name);
+    if dest.exists() {
+        let mut name = file_name.to_os_string();
+        name.push(format!(".{}", Utc::now().format("%Y%m%d%H%M%S")));
+        dest = dest_dir.join(name);
+    }
+
+    tracing::info!("Quarantining {path:?} to {dest:?}.");
+    tokio::fs::rename(path, dest).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{MemoryRepository, NewSound};
+    use crate::fixture::{new_sound, GUILD};
+    use crate::media::FakeMedia;
+    use crate::store::{LocalStore, MemoryStore};
+
+    #[tokio::test]
+    async fn garbage_collection_keeps_used_and_new_files() {
+        let db = MemoryRepository::new();
+        let store = MemoryStore::new();
+        let long_ago = Utc::now() - Duration::days(1);
+
+        store.insert("used", b"bonk");
+        store.set_modified("used", long_ago);
+        db.add_sound(&new_sound(GUILD, "bonk", "used"))
+            .await
+            .unwrap();
+
+        store.insert("unused", b"boop");
+        store.set_modified("unused", long_ago);
+        // as if a sound using it were being added.
+        store.insert("new", b"zap");
+
+        let collected = collect_garbage(&db, &store).await.unwrap();
+
+        assert_eq!(collected, 1);
+        assert_eq!(store.list().await.unwrap(), ["new", "used"]);
+    }
+
+    /// Put a file holding `contents` in `store`, returning its hash.
+    async fn put(store: &dyn SoundStore, dir: &Path, contents: &[u8]) -> String {
+        let path = dir.join("upload");
+        tokio::fs::write(&path, contents).await.unwrap();
+        let file_hash = hash_file(&path).await.unwrap();
+        store.put(&file_hash, &path).await.unwrap();
+
+        file_hash
+    }
+
+    #[tokio::test]
+    async fn check_finds_problems_and_repair_fixes_them() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let db = MemoryRepository::new();
+        let store = LocalStore::new(storage_dir.path());
+        let media = FakeMedia::new();
+        media.add_sound("https://example.com/bonk", b"bonk", 1000);
+
+        let bonk = put(&store, storage_dir.path(), b"bonk").await;
+        let unused = put(&store, storage_dir.path(), b"unused").await;
+        db.add_sound(&new_sound(GUILD, "bonk", &bonk))
+            .await
+            .unwrap();
+        // shares bonk's file, but thinks it's longer.
+        db.add_sound(&NewSound {
+            length: 5000,
+            ..new_sound(GUILD, "boop", &bonk)
+        })
+        .await
+        .unwrap();
+        // its source can't be downloaded again.
+        db.add_sound(&new_sound(GUILD, "zap", "missing"))
+            .await
+            .unwrap();
+        let legacy_dir = storage_dir.path().join(GUILD.0.to_string());
+        tokio::fs::create_dir(&legacy_dir).await.unwrap();
+        tokio::fs::write(legacy_dir.join("honk.mp3"), b"honk")
+            .await
+            .unwrap();
+
+        let report = check(&db, storage_dir.path(), &store, &media)
+            .await
+            .unwrap();
+        let names = |sounds: &[Sound]| -> Vec<String> {
+            sounds.iter().map(|sound| sound.name.clone()).collect()
+        };
+        assert_eq!(names(&report.missing_files), ["zap"]);
+        assert_eq!(report.unreferenced_files, [unused]);
+        assert_eq!(report.stray_files, [legacy_dir.join("honk.mp3")]);
+        assert_eq!(report.wrong_lengths.len(), 1);
+        assert_eq!(report.wrong_lengths[0].0.name, "boop");
+        assert_eq!(report.wrong_lengths[0].1, Some(1000));
+
+        let summary = repair(&db, storage_dir.path(), &store, &media, &media, report)
+            .await
+            .unwrap();
+        assert_eq!(summary.relengthed, 1);
+        assert_eq!(summary.redownloaded, 0);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].1, "zap");
+        // the unreferenced file was only just put.
+        assert_eq!(summary.collected, 0);
+        assert_eq!(summary.quarantined, 1);
+        assert!(storage_dir
+            .path()
+            .join(QUARANTINE_DIR)
+            .join(GUILD.0.to_string())
+            .join("honk.mp3")
+            .is_file());
+
+        let report = check(&db, storage_dir.path(), &store, &media)
+            .await
+            .unwrap();
+        assert_eq!(names(&report.missing_files), ["zap"]);
+        assert!(report.wrong_lengths.is_empty());
+        assert!(report.stray_files.is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use super::{is_hash, SoundStore};
+use crate::Error;
+
+/// Directory under the storage directory that files are kept in.
+const BLOBS_DIR: &str = "blobs";
+
+/// Keeps files in `<storage dir>/blobs/<first two characters of hash>/<hash>`.
+///
+/// Files are copied next to where they go first and then renamed into place, so a file under its
+/// hash is always complete.
+#[derive(Debug)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new<P: AsRef<Path>>(storage_dir: P) -> Self {
+        Self {
+            root: storage_dir.as_ref().join(BLOBS_DIR),
+        }
+    }
+
+    fn path(&self, hash: &str) -> Result<PathBuf, Error> {
+        if !is_hash(hash) {
+            bail!("{hash:?} isn't a file hash.");
+        }
+
+        // spread files out a bit so no one directory gets too big.
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+}
+
+#[async_trait]
+impl SoundStore for LocalStore {
+    async fn put(&self, hash: &str, path: &Path) -> Result<(), Error> {
+        let dest = self.path(hash)?;
+        let dir = dest.parent().unwrap();
+        tokio::fs::create_dir_all(dir).await?;
+
+        // temporary files start with a dot, so they're never mistaken for stored ones.
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+        let temp = dir.join(format!(".{hash}.{suffix}.tmp"));
+
+        let result = async {
+            tokio::fs::copy(path, &temp).await?;
+            tokio::fs::File::open(&temp).await?.sync_all().await?;
+            tokio::fs::rename(&temp, &dest).await
+        }
+        .await;
+        if let Err(error) = result {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, Error> {
+        Ok(tokio::fs::read(self.path(hash)?).await?)
+    }
+
+    async fn open(&self, hash: &str) -> Result<OsString, Error> {
+        Ok(self.path(hash)?.into_os_string())
+    }
+
+    async fn delete(&self, hash: &str) -> Result<(), Error> {
+        tokio::fs::remove_file(self.path(hash)?).await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut hashes = vec![];
+
+        if !self.root.is_dir() {
+            return Ok(hashes);
+        }
+
+        let mut prefixes = tokio::fs::read_dir(&self.root).await?;
+        while let Some(prefix) = prefixes.next_entry().await? {
+            if !prefix.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut entries = tokio::fs::read_dir(prefix.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if is_hash(&name) {
+                    hashes.push(name);
+                }
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, Error> {
+        Ok(self.path(hash)?.is_file())
+    }
+
+    async fn size(&self, hash: &str) -> Result<i64, Error> {
+        Ok(tokio::fs::metadata(self.path(hash)?).await?.len() as i64)
+    }
+
+    async fn modified(&self, hash: &str) -> Result<DateTime<Utc>, Error> {
+        let modified = tokio::fs::metadata(self.path(hash)?).await?.modified()?;
+
+        Ok(modified.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::hash_file;
+
+    #[tokio::test]
+    async fn put_files_are_complete_and_listed_by_hash() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let store = LocalStore::new(storage_dir.path());
+
+        let source = storage_dir.path().join("bonk.mp3");
+        tokio::fs::write(&source, b"bonk").await.unwrap();
+        let hash = hash_file(&source).await.unwrap();
+
+        store.put(&hash, &source).await.unwrap();
+        let first_put = store.modified(&hash).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        store.put(&hash, &source).await.unwrap();
+        assert!(store.modified(&hash).await.unwrap() > first_put);
+
+        assert_eq!(store.get(&hash).await.unwrap(), b"bonk");
+        assert_eq!(store.list().await.unwrap(), [hash.clone()]);
+
+        // left over from a put that never finished.
+        let dir = storage_dir.path().join(BLOBS_DIR).join(&hash[..2]);
+        tokio::fs::write(dir.join(format!(".{hash}.abcdefgh.tmp")), b"bo")
+            .await
+            .unwrap();
+        assert_eq!(store.list().await.unwrap(), [hash]);
+    }
+
+    #[tokio::test]
+    async fn hashes_that_are_not_hashes_are_rejected() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let store = LocalStore::new(storage_dir.path());
+
+        for hash in [
+            "",
+            "a",
+            "../../etc/passwd",
+            &"é".repeat(32),
+            &"A".repeat(64),
+        ] {
+            let error = store.exists(hash).await.unwrap_err();
+            assert_eq!(error.to_string(), format!("{hash:?} isn't a file hash."));
+        }
+    }
+}
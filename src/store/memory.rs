@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::SoundStore;
+use crate::Error;
+
+/// Keeps files in memory. Opened files get locations nothing can actually read.
+///
+/// Clones share files, so tests can keep one to look at after handing another out.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    files: Arc<Mutex<HashMap<String, StoredFile>>>,
+}
+
+#[derive(Debug)]
+struct StoredFile {
+    contents: Vec<u8>,
+    modified: DateTime<Utc>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `contents` as `hash` without checking that it's actually their hash.
+    pub fn insert(&self, hash: &str, contents: &[u8]) {
+        self.files.lock().unwrap().insert(
+            hash.to_owned(),
+            StoredFile {
+                contents: contents.to_vec(),
+                modified: Utc::now(),
+            },
+        );
+    }
+
+    /// Pretend the file `hash` was last put at `modified`.
+    pub fn set_modified(&self, hash: &str, modified: DateTime<Utc>) {
+        if let Some(file) = self.files.lock().unwrap().get_mut(hash) {
+            file.modified = modified;
+        }
+    }
+
+    fn with_file<T>(&self, hash: &str, f: impl FnOnce(&StoredFile) -> T) -> Result<T, Error> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(hash)
+            .map(f)
+            .ok_or_else(|| anyhow!("No file `{hash}` in memory."))
+    }
+}
+
+#[async_trait]
+impl SoundStore for MemoryStore {
+    async fn put(&self, hash: &str, path: &Path) -> Result<(), Error> {
+        let contents = tokio::fs::read(path).await?;
+        self.insert(hash, &contents);
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, Error> {
+        self.with_file(hash, |file| file.contents.clone())
+    }
+
+    async fn open(&self, hash: &str) -> Result<OsString, Error> {
+        if !self.exists(hash).await? {
+            return Err(anyhow!("No file `{hash}` in memory."));
+        }
+
+        Ok(OsString::from(format!("memory:{hash}")))
+    }
+
+    async fn delete(&self, hash: &str) -> Result<(), Error> {
+        self.files.lock().unwrap().remove(hash);
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut hashes: Vec<String> = self.files.lock().unwrap().keys().cloned().collect();
+        hashes.sort();
+
+        Ok(hashes)
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, Error> {
+        Ok(self.files.lock().unwrap().contains_key(hash))
+    }
+
+    async fn size(&self, hash: &str) -> Result<i64, Error> {
+        self.with_file(hash, |file| file.contents.len() as i64)
+    }
+
+    async fn modified(&self, hash: &str) -> Result<DateTime<Utc>, Error> {
+        self.with_file(hash, |file| file.modified)
+    }
+}
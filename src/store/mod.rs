@@ -0,0 +1,82 @@
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::config::StoreConfig;
+use crate::Error;
+
+mod local;
+#[cfg(test)]
+mod memory;
+mod s3;
+
+pub use self::local::LocalStore;
+#[cfg(test)]
+pub use self::memory::MemoryStore;
+pub use self::s3::S3Store;
+
+/// Where sound files are kept.
+///
+/// Files are content-addressed: each is identified by the hex-encoded SHA-256 hash of its contents,
+/// as computed by [`hash_file`]. Identical files are only stored once, however many sounds use them.
+#[async_trait]
+pub trait SoundStore: Debug + Send + Sync {
+    /// Store the file at `path` as `hash`. If it's already stored, it's stored again, so it counts
+    /// as new to garbage collection.
+    async fn put(&self, hash: &str, path: &Path) -> Result<(), Error>;
+
+    /// Get the contents of a file.
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, Error>;
+
+    /// Get a location ffmpeg and ffprobe can read a file from.
+    async fn open(&self, hash: &str) -> Result<OsString, Error>;
+
+    async fn delete(&self, hash: &str) -> Result<(), Error>;
+
+    /// List the hashes of every stored file.
+    async fn list(&self) -> Result<Vec<String>, Error>;
+
+    async fn exists(&self, hash: &str) -> Result<bool, Error>;
+
+    /// Get the size of a file in bytes.
+    async fn size(&self, hash: &str) -> Result<i64, Error>;
+
+    /// Get when a file was last put.
+    async fn modified(&self, hash: &str) -> Result<DateTime<Utc>, Error>;
+}
+
+/// Create the store described by `config`.
+///
+/// Local stores keep files in `storage_dir`.
+pub async fn from_config(
+    config: &StoreConfig,
+    storage_dir: &Path,
+) -> Result<Box<dyn SoundStore>, Error> {
+    match config {
+        StoreConfig::Local => Ok(Box::new(LocalStore::new(storage_dir))),
+        StoreConfig::S3(s3) => Ok(Box::new(S3Store::from_config(s3)?)),
+    }
+}
+
+/// Whether `hash` looks like a hash from [`hash_file`]: 64 lowercase hex digits.
+pub fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hash the file at `path` to get the key it's stored under.
+pub async fn hash_file(path: &Path) -> Result<String, Error> {
+    let path = path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await?
+}
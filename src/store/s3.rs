@@ -0,0 +1,135 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+use ::s3::creds::Credentials;
+use ::s3::{Bucket, Region};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::SoundStore;
+use crate::config::S3Config;
+use crate::Error;
+
+/// How long links handed to ffmpeg stay valid, in seconds.
+const PRESIGN_EXPIRY: u32 = 60 * 60;
+
+/// Prefix of the keys files are kept under.
+const BLOBS_PREFIX: &str = "blobs/";
+
+/// Keeps files in an S3-compatible bucket under `blobs/<hash>`.
+#[derive(Debug)]
+pub struct S3Store {
+    bucket: Bucket,
+}
+
+impl S3Store {
+    pub fn new(bucket: Bucket) -> Self {
+        Self { bucket }
+    }
+
+    /// Create a store for the bucket described by `config`.
+    pub fn from_config(config: &S3Config) -> Result<Self, Error> {
+        let region = match &config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config.region.parse()?,
+        };
+        let credentials = Credentials::new(
+            Some(&config.access_key),
+            Some(&config.secret_key),
+            None,
+            None,
+            None,
+        )?;
+
+        // path style addressing is what self-hosted services generally expect.
+        let bucket = Bucket::new(&config.bucket, region, credentials)?.with_path_style();
+
+        Ok(Self::new(bucket))
+    }
+
+    fn key(hash: &str) -> String {
+        format!("{BLOBS_PREFIX}{hash}")
+    }
+}
+
+#[async_trait]
+impl SoundStore for S3Store {
+    async fn put(&self, hash: &str, path: &Path) -> Result<(), Error> {
+        let data = tokio::fs::read(path).await?;
+        let (_, code) = self.bucket.put_object(Self::key(hash), &data).await?;
+        if code != 200 {
+            bail!("Couldn't store {hash}: status {code}.");
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, Error> {
+        let (data, code) = self.bucket.get_object(Self::key(hash)).await?;
+        if code != 200 {
+            bail!("Couldn't get {hash}: status {code}.");
+        }
+
+        Ok(data)
+    }
+
+    async fn open(&self, hash: &str) -> Result<OsString, Error> {
+        let url = self
+            .bucket
+            .presign_get(Self::key(hash), PRESIGN_EXPIRY, None)?;
+
+        Ok(url.into())
+    }
+
+    async fn delete(&self, hash: &str) -> Result<(), Error> {
+        self.bucket.delete_object(Self::key(hash)).await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut hashes = vec![];
+
+        for page in self.bucket.list(BLOBS_PREFIX.to_owned(), None).await? {
+            for object in page.contents {
+                if let Some(hash) = object.key.strip_prefix(BLOBS_PREFIX) {
+                    hashes.push(hash.to_owned());
+                }
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, Error> {
+        let (_, code) = self.bucket.head_object(Self::key(hash)).await?;
+
+        Ok(code == 200)
+    }
+
+    async fn size(&self, hash: &str) -> Result<i64, Error> {
+        let (head, code) = self.bucket.head_object(Self::key(hash)).await?;
+        if code != 200 {
+            bail!("Couldn't get the size of {hash}: status {code}.");
+        }
+
+        head.content_length
+            .ok_or_else(|| anyhow!("Couldn't get the size of {hash}: no content length."))
+    }
+
+    async fn modified(&self, hash: &str) -> Result<DateTime<Utc>, Error> {
+        let (head, code) = self.bucket.head_object(Self::key(hash)).await?;
+        if code != 200 {
+            bail!("Couldn't get when {hash} was stored: status {code}.");
+        }
+
+        let last_modified = head.last_modified.ok_or_else(|| {
+            anyhow!("Couldn't get when {hash} was stored: no last modified time.")
+        })?;
+        Ok(DateTime::parse_from_rfc2822(&last_modified)?.with_timezone(&Utc))
+    }
+}
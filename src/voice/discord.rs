@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use songbird::input::Restartable;
+use songbird::tracks::TrackHandle;
+use songbird::Songbird;
+use tokio::sync::Mutex;
+
+use super::{check_room, Track, TrackFilter, VoicePlayer, VoiceStats};
+use crate::media::check_program;
+use crate::Error;
+
+/// Plays sounds on Discord through songbird.
+///
+/// The songbird instance has to be registered with the Discord client for this to work.
+#[derive(Debug)]
+pub struct SongbirdPlayer {
+    songbird: Arc<Songbird>,
+    /// Tracks by guild, oldest first.
+    handles: Mutex<HashMap<GuildId, Vec<(Track, TrackHandle)>>>,
+}
+
+impl SongbirdPlayer {
+    pub fn new(songbird: Arc<Songbird>) -> Self {
+        Self {
+            songbird,
+            handles: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl SongbirdPlayer {
+    /// Do `action` to every track still playing in `guild_id`, returning the ids of their
+    /// playbacks.
+    async fn for_each(
+        &self,
+        guild_id: GuildId,
+        action: impl Fn(&TrackHandle) -> Result<(), Error>,
+    ) -> Result<Vec<i32>, Error> {
+        let mut handles = self.handles.lock().await;
+        let handles = match handles.get_mut(&guild_id) {
+            Some(handles) => handles,
+            None => return Ok(vec![]),
+        };
+        forget_ended(handles).await;
+
+        let mut playback_ids = vec![];
+        for (track, handle) in handles.iter() {
+            action(handle)?;
+            playback_ids.push(track.playback_id);
+        }
+
+        Ok(playback_ids)
+    }
+}
+
+#[async_trait]
+impl VoicePlayer for SongbirdPlayer {
+    #[tracing::instrument(name = "songbird.play", skip(self, source))]
+    async fn play(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        track: Track,
+        source: OsString,
+        max_tracks: Option<usize>,
+    ) -> Result<(), Error> {
+        let call_lock = self.songbird.get_or_insert(guild_id.0);
+        let mut call = call_lock.lock().await;
+
+        // every play in the guild waits for the call, so nothing can start between counting the
+        // tracks and adding this one.
+        check_room(self.track_count(guild_id).await, max_tracks)?;
+
+        // restartable, so it can be seeked by running ffmpeg again from another position.
+        let source = Restartable::ffmpeg(source, false).await?;
+        let (mut player, track_handle) = songbird::create_player(source.into());
+        player.set_volume(track.volume as f32 / 100.0);
+
+        // only tracks that start are kept, so a failed join doesn't leave one behind.
+        call.join(channel_id).await?;
+        self.handles
+            .lock()
+            .await
+            .entry(guild_id)
+            .or_default()
+            .push((track, track_handle));
+        call.play(player);
+
+        Ok(())
+    }
+
+    async fn track_count(&self, guild_id: GuildId) -> usize {
+        match self.handles.lock().await.get_mut(&guild_id) {
+            Some(handles) => {
+                forget_ended(handles).await;
+                handles.len()
+            }
+            None => 0,
+        }
+    }
+
+    #[tracing::instrument(name = "songbird.stop", skip(self))]
+    async fn stop(&self, guild_id: GuildId, filter: TrackFilter) -> Result<Vec<i32>, Error> {
+        let mut all_handles = self.handles.lock().await;
+        // the guild is kept, so its call is still looked at by `stats`.
+        let handles = match all_handles.get_mut(&guild_id) {
+            Some(handles) => handles,
+            None => return Ok(vec![]),
+        };
+        forget_ended(handles).await;
+
+        let (stopping, kept) = filter.select(std::mem::take(handles), |(track, _)| track);
+        *handles = kept;
+
+        let mut stopped = vec![];
+        for (track, handle) in stopping {
+            handle.stop()?;
+            stopped.push(track.playback_id);
+        }
+
+        Ok(stopped)
+    }
+
+    #[tracing::instrument(name = "songbird.pause", skip(self))]
+    async fn pause(&self, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+        self.for_each(guild_id, |handle| Ok(handle.pause()?)).await
+    }
+
+    #[tracing::instrument(name = "songbird.resume", skip(self))]
+    async fn resume(&self, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+        self.for_each(guild_id, |handle| Ok(handle.play()?)).await
+    }
+
+    #[tracing::instrument(name = "songbird.seek", skip(self))]
+    async fn seek(&self, guild_id: GuildId, position: Duration) -> Result<Vec<i32>, Error> {
+        self.for_each(guild_id, |handle| Ok(handle.seek_time(position)?))
+            .await
+    }
+
+    #[tracing::instrument(name = "songbird.leave_all", skip(self))]
+    async fn leave_all(&self) -> Result<Vec<i32>, Error> {
+        let handles = std::mem::take(&mut *self.handles.lock().await);
+
+        let mut stopped = vec![];
+        for (guild_id, handles) in handles {
+            for (track, handle) in handles {
+                // tracks that already ended can't be stopped, and weren't playing anyway.
+                if handle.get_info().await.is_ok() && handle.stop().is_ok() {
+                    stopped.push(track.playback_id);
+                }
+            }
+
+            if let Err(error) = self.songbird.remove(guild_id.0).await {
+                tracing::warn!("Couldn't leave the call in guild {guild_id}: {error}");
+            }
+        }
+
+        Ok(stopped)
+    }
+
+    async fn stats(&self) -> VoiceStats {
+        // `play` locks a call before the handles, so the handles can't be held while waiting for
+        // a call.
+        let (guild_ids, tracks) = {
+            let handles = self.handles.lock().await;
+            let guild_ids: Vec<GuildId> = handles.keys().copied().collect();
+            (guild_ids, handles.values().map(Vec::len).sum())
+        };
+
+        // every guild the bot has played in has a call, but it may have been disconnected since.
+        let mut connections = 0;
+        for guild_id in guild_ids {
+            if let Some(call) = self.songbird.get(guild_id.0) {
+                if call.lock().await.current_channel().is_some() {
+                    connections += 1;
+                }
+            }
+        }
+
+        VoiceStats {
+            connections,
+            tracks,
+        }
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        // songbird runs whichever ffmpeg is on the PATH.
+        check_program("ffmpeg".as_ref(), "-version").await
+    }
+}
+
+/// Forget tracks that ended on their own, since they can't be stopped anymore.
+async fn forget_ended(handles: &mut Vec<(Track, TrackHandle)>) {
+    let mut playing = vec![];
+    for (track, handle) in handles.drain(..) {
+        if handle.get_info().await.is_ok() {
+            playing.push((track, handle));
+        }
+    }
+
+    *handles = playing;
+}
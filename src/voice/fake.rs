@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use poise::serenity_prelude::{ChannelId, GuildId};
+
+use super::{check_room, Track, TrackFilter, VoicePlayer, VoiceStats};
+use crate::Error;
+
+/// A play started on a [`FakeVoice`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeTrack {
+    pub channel_id: ChannelId,
+    pub track: Track,
+    pub source: OsString,
+    pub paused: bool,
+    /// Where the track was last moved to, if it was.
+    pub position: Option<Duration>,
+}
+
+/// Pretends to play sounds, remembering what's playing where.
+///
+/// Clones share what's playing, so tests can keep one to look at after handing another out.
+#[derive(Debug, Clone, Default)]
+pub struct FakeVoice {
+    playing: Arc<Mutex<HashMap<GuildId, Vec<FakeTrack>>>>,
+}
+
+impl FakeVoice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get what's playing in `guild_id`, in the order it was started.
+    pub fn playing(&self, guild_id: GuildId) -> Vec<FakeTrack> {
+        self.playing
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Do `action` to every track playing in `guild_id`, returning the ids of their playbacks.
+    fn for_each(&self, guild_id: GuildId, action: impl Fn(&mut FakeTrack)) -> Vec<i32> {
+        let mut playing = self.playing.lock().unwrap();
+        let tracks = match playing.get_mut(&guild_id) {
+            Some(tracks) => tracks,
+            None => return vec![],
+        };
+
+        tracks
+            .iter_mut()
+            .map(|fake| {
+                action(fake);
+                fake.track.playback_id
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl VoicePlayer for FakeVoice {
+    async fn play(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        track: Track,
+        source: OsString,
+        max_tracks: Option<usize>,
+    ) -> Result<(), Error> {
+        let mut playing = self.playing.lock().unwrap();
+        let tracks = playing.entry(guild_id).or_default();
+        check_room(tracks.len(), max_tracks)?;
+
+        tracks.push(FakeTrack {
+            channel_id,
+            track,
+            source,
+            paused: false,
+            position: None,
+        });
+
+        Ok(())
+    }
+
+    async fn track_count(&self, guild_id: GuildId) -> usize {
+        self.playing(guild_id).len()
+    }
+
+    async fn stop(&self, guild_id: GuildId, filter: TrackFilter) -> Result<Vec<i32>, Error> {
+        let mut playing = self.playing.lock().unwrap();
+        let tracks = playing.remove(&guild_id).unwrap_or_default();
+
+        let (stopped, kept) = filter.select(tracks, |fake| &fake.track);
+        if !kept.is_empty() {
+            playing.insert(guild_id, kept);
+        }
+
+        Ok(stopped.iter().map(|fake| fake.track.playback_id).collect())
+    }
+
+    async fn pause(&self, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+        Ok(self.for_each(guild_id, |fake| fake.paused = true))
+    }
+
+    async fn resume(&self, guild_id: GuildId) -> Result<Vec<i32>, Error> {
+        Ok(self.for_each(guild_id, |fake| fake.paused = false))
+    }
+
+    async fn seek(&self, guild_id: GuildId, position: Duration) -> Result<Vec<i32>, Error> {
+        Ok(self.for_each(guild_id, |fake| fake.position = Some(position)))
+    }
+
+    async fn leave_all(&self) -> Result<Vec<i32>, Error> {
+        let stopped = std::mem::take(&mut *self.playing.lock().unwrap())
+            .into_values()
+            .flatten()
+            .map(|fake| fake.track.playback_id)
+            .collect();
+
+        Ok(stopped)
+    }
+
+    async fn stats(&self) -> VoiceStats {
+        let playing = self.playing.lock().unwrap();
+
+        VoiceStats {
+            connections: playing.len(),
+            tracks: playing.values().map(Vec::len).sum(),
+        }
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
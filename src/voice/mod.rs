@@ -0,0 +1,137 @@
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+
+use crate::Error;
+
+mod discord;
+#[cfg(test)]
+mod fake;
+
+pub use self::discord::SongbirdPlayer;
+#[cfg(test)]
+pub use self::fake::FakeVoice;
+
+/// What a [`VoicePlayer`] is doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoiceStats {
+    /// Voice channels joined.
+    pub connections: usize,
+    /// Tracks that can still be stopped.
+    pub tracks: usize,
+}
+
+/// A play of a sound, as a [`VoicePlayer`] keeps track of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Track {
+    pub playback_id: i32,
+    pub sound_id: i32,
+    pub player_id: UserId,
+    /// How loud to play the sound, in percent of its file's own volume.
+    pub volume: i32,
+}
+
+/// Which of a guild's tracks to stop. Everything that's set has to match; the default matches
+/// every track.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackFilter {
+    /// Only tracks of one of these sounds.
+    pub sound_ids: Option<Vec<i32>>,
+    pub player_id: Option<UserId>,
+    /// Only stop the most recently started of the matching tracks.
+    pub latest: bool,
+    /// Only stop the first started of the matching tracks. Ignored if `latest` is set.
+    pub oldest: bool,
+}
+
+impl TrackFilter {
+    pub fn matches(&self, track: &Track) -> bool {
+        self.sound_ids
+            .as_ref()
+            .map_or(true, |ids| ids.contains(&track.sound_id))
+            && self.player_id.map_or(true, |id| track.player_id == id)
+    }
+
+    /// Split `tracks`, oldest first, into the ones to stop and the ones to keep playing, keeping
+    /// their order.
+    pub fn select<T>(&self, tracks: Vec<T>, track: impl Fn(&T) -> &Track) -> (Vec<T>, Vec<T>) {
+        let latest = tracks.iter().rposition(|item| self.matches(track(item)));
+        let oldest = tracks.iter().position(|item| self.matches(track(item)));
+
+        let mut stopped = vec![];
+        let mut kept = vec![];
+        for (index, item) in tracks.into_iter().enumerate() {
+            let stop = if self.latest {
+                Some(index) == latest
+            } else if self.oldest {
+                Some(index) == oldest
+            } else {
+                self.matches(track(&item))
+            };
+            if stop {
+                stopped.push(item);
+            } else {
+                kept.push(item);
+            }
+        }
+
+        (stopped, kept)
+    }
+}
+
+/// Fail if `max_tracks` tracks are already among the `playing` ones.
+pub fn check_room(playing: usize, max_tracks: Option<usize>) -> Result<(), Error> {
+    if max_tracks.map_or(false, |max_tracks| playing >= max_tracks) {
+        bail!("Too many sounds are playing already. Wait for one to finish, or /stop them.");
+    }
+
+    Ok(())
+}
+
+/// Plays sounds in voice channels.
+///
+/// Every play is identified by its [`Track`], so it can be stopped selectively and recorded as
+/// stopped later.
+#[async_trait]
+pub trait VoicePlayer: Debug + Send + Sync {
+    /// Join `channel_id` if needed and start playing `source`, which is anything ffmpeg can read.
+    ///
+    /// Fails if `max_tracks` tracks are already playing in `guild_id`.
+    async fn play(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        track: Track,
+        source: OsString,
+        max_tracks: Option<usize>,
+    ) -> Result<(), Error>;
+
+    /// Count the tracks playing in `guild_id`.
+    async fn track_count(&self, guild_id: GuildId) -> usize;
+
+    /// Stop the tracks playing in `guild_id` that match `filter`, returning the ids of the stopped
+    /// playbacks.
+    async fn stop(&self, guild_id: GuildId, filter: TrackFilter) -> Result<Vec<i32>, Error>;
+
+    /// Pause the tracks playing in `guild_id`, returning the ids of their playbacks.
+    async fn pause(&self, guild_id: GuildId) -> Result<Vec<i32>, Error>;
+
+    /// Resume the paused tracks in `guild_id`, returning the ids of their playbacks.
+    async fn resume(&self, guild_id: GuildId) -> Result<Vec<i32>, Error>;
+
+    /// Move the tracks in `guild_id` to `position`, returning the ids of their playbacks.
+    async fn seek(&self, guild_id: GuildId, position: Duration) -> Result<Vec<i32>, Error>;
+
+    /// Stop everything playing in every guild and leave every voice channel, returning the ids
+    /// of the playbacks that were still playing.
+    async fn leave_all(&self) -> Result<Vec<i32>, Error>;
+
+    async fn stats(&self) -> VoiceStats;
+
+    /// Make sure sounds can be played at all, e.g. that ffmpeg is installed.
+    async fn check(&self) -> Result<(), Error>;
+}